@@ -1,5 +1,8 @@
 ﻿use criterion::{criterion_group, criterion_main, Criterion, black_box, BenchmarkId};
-use system_runtime_rustlib::diffusion::symbol_element_remap::{from_elements, SymbolElementOwned, SymbolStringOwned};
+use system_runtime_rustlib::diffusion::diffusion_options::DiffusionOptions;
+use system_runtime_rustlib::diffusion::extract_graph::extract_edges_and_nodes_read_only;
+use system_runtime_rustlib::diffusion::symbol_element_remap::SymbolStringOwned;
+use system_runtime_rustlib::diffusion::symbol_string_builder::SymbolStringBuilder;
 use system_runtime_rustlib::interop_extern::data::JaggedIndexing;
 
 use system_runtime_rustlib::interop_extern::diffusion::{LSystemMatchErrorCode, LSystemSingleSymbolMatchData, perform_parallel_diffusion_internal};
@@ -13,6 +16,18 @@ fn get_diffuse_node_parameters(diffuse_constant: f32, amount: f32, max: f32, res
     params
 }
 
+/// a node, followed by two branches each containing the tree one level shallower
+fn push_diffusion_tree(builder: &mut SymbolStringBuilder, diffusion_node_symbol: i32, depth: u8, resources_per_node: u8) {
+    if depth == 0 {
+        builder.push(diffusion_node_symbol, &get_diffuse_node_parameters(0.5, 20.0, 1000.0, resources_per_node));
+        return;
+    }
+    let i = depth - 1;
+    builder.push(diffusion_node_symbol, &get_diffuse_node_parameters(0.5, i as f32 * 25.0, 1000.0, resources_per_node));
+    builder.push_branch(|b| push_diffusion_tree(b, diffusion_node_symbol, i, resources_per_node));
+    builder.push_branch(|b| push_diffusion_tree(b, diffusion_node_symbol, i, resources_per_node));
+}
+
 fn benchmark_diffusion_variant(c: &mut Criterion, depth: u8, resources_per_node: u8) {
 
     let open_branch_symbol = 0;
@@ -20,38 +35,14 @@ fn benchmark_diffusion_variant(c: &mut Criterion, depth: u8, resources_per_node:
     let diffusion_node_symbol = 2;
     let diffusion_amount_symbol = 3;
 
-    // open branch
-    let b = SymbolElementOwned {
-        symbol: open_branch_symbol,
-        params: vec![]
-    };
-    // close branch
-    let d = SymbolElementOwned {
-        symbol: close_branch_symbol,
-        params: vec![]
-    };
-
-    let mut initial_state = vec![SymbolElementOwned {
-        symbol: diffusion_node_symbol,
-        params: get_diffuse_node_parameters(0.5, 20.0, 1000.0, resources_per_node)
-    }];
-
-    for i in 0..depth{
-        let next_node = SymbolElementOwned{
-            symbol: diffusion_node_symbol,
-            params: get_diffuse_node_parameters(0.5, i as f32 * 25.0, 1000.0, resources_per_node)
-        };
-        let mut next_state = vec![next_node];
-        next_state.push(b.clone());
-        next_state.extend(initial_state.clone());
-        next_state.push(d.clone());
-        next_state.push(b.clone());
-        next_state.extend(initial_state);
-        next_state.push(d.clone());
-        initial_state = next_state;
-    }
-
-    let source_symbol_string = from_elements(initial_state);
+    let total_nodes = (1usize << (depth as usize + 1)) - 1;
+    let mut builder = SymbolStringBuilder::with_capacity(
+        open_branch_symbol,
+        close_branch_symbol,
+        total_nodes * 3,
+        total_nodes * (1 + resources_per_node as usize * 2));
+    push_diffusion_tree(&mut builder, diffusion_node_symbol, depth, resources_per_node);
+    let source_symbol_string = builder.build();
 
     let mut target_symbol_string = SymbolStringOwned {
         symbols: vec![0; source_symbol_string.symbols.len()],
@@ -100,11 +91,26 @@ fn benchmark_diffusion_variant(c: &mut Criterion, depth: u8, resources_per_node:
                     black_box(close_branch_symbol),
                     black_box(diffuse_steps),
                     black_box(1.0),
+                    black_box(&DiffusionOptions::default()),
+                    None,
                 );
                 black_box(target_symbol_string.symbols[0]);
             });
         });
     }
+    group.bench_function("extract", |b| {
+        b.iter(|| {
+            extract_edges_and_nodes_read_only(
+                black_box(&source_symbol_string.borrow()),
+                black_box(diffusion_node_symbol),
+                black_box(diffusion_amount_symbol),
+                black_box(open_branch_symbol),
+                black_box(close_branch_symbol),
+                black_box(&DiffusionOptions::default()),
+                None,
+            )
+        });
+    });
     group.finish();
 }
 
@@ -5,7 +5,7 @@
 parent_path=$( cd "$(dirname "${BASH_SOURCE[0]}")" ; pwd -P )
 cd "$parent_path"
 
-cargo expand interop_extern > expanded.rs.tmp
+cargo expand --lib interop_extern > expanded.rs.tmp
 
 cargo build --release
 
@@ -0,0 +1,439 @@
+use std::fs;
+use std::process::ExitCode;
+use system_runtime_rustlib::diffusion::branch_tree::BranchTree;
+use system_runtime_rustlib::diffusion::diffusion_options::DiffusionOptions;
+use system_runtime_rustlib::diffusion::diffusion_stats::DiffusionStats;
+use system_runtime_rustlib::diffusion::symbol_element_remap::SymbolStringOwned;
+use system_runtime_rustlib::diffusion::symbol_string_binary::{read_symbol_string, write_symbol_string, SymbolStringMetadata, SYMBOL_STRING_MAGIC};
+use system_runtime_rustlib::diffusion::symbol_string_text::{parse_symbol_string, SymbolNames};
+use system_runtime_rustlib::interop_extern::diffusion::perform_in_place_diffusion_internal;
+use system_runtime_rustlib::lsystem::rewrite_trace::RewriteTrace;
+use system_runtime_rustlib::lsystem::rewrite::{rewrite, rewrite_traced, RewriteRandom};
+use system_runtime_rustlib::lsystem::rule_file::{load_lsystem_file, LSystemFile};
+use system_runtime_rustlib::math::{Mat4, Vec3};
+use system_runtime_rustlib::mesh::builder::{build_plant_mesh, StemClass};
+use system_runtime_rustlib::mesh::gltf::plant_mesh_to_glb;
+use system_runtime_rustlib::mesh::obj::plant_mesh_to_obj;
+use system_runtime_rustlib::turtle::interpreter::{interpret_turtle, TurtleOperationTable, TurtleSymbols};
+use system_runtime_rustlib::turtle::operations::{StemOperation, TurtleOperation};
+use system_runtime_rustlib::turtle::planar::{interpret_planar_turtle, planar_lines_to_svg, PlanarOperation, PlanarTurtleState, PlanarTurtleTable};
+use system_runtime_rustlib::turtle::state::TurtleState;
+
+const USAGE: &str = "\
+Usage: lsystem_runner <input> [options]
+
+Loads an L-system or a symbol string, steps it, then prints the string as text.
+An input ending in .lsystem is read as an L-system file in the unity format, and its axiom is stepped with its rules.
+    symbols are single characters, named by the character. #include paths are read relative to the including file,
+    and symbols of included libraries are named by their character and library, such as c:tree-sizing.
+    the builtin libraries only add their symbols and rules, except that an included diffusion library runs diffusion.
+Other inputs are read as the binary symbol string format when they start with \"LSYS\", otherwise as text.
+Each step rewrites the string with the rules, then runs diffusion when asked. stochastic rules are picked
+    from the seed with the same random generators as the unity matcher.
+
+Options:
+    --names <file>              symbol names for reading and printing text, one \"<id> <name>\" per line.
+                                    added to any names saved in a binary input
+    --branch-symbols <open> <close>
+                                symbol ids or names of the branch symbols. defaults to the symbols named [ and ]
+    --rules <file>              rewrite the loaded string with the rules of an .lsystem file
+    --steps <n>                 number of steps to run. defaults to the #iterations of an .lsystem input, otherwise 0
+    --seed <n>                  seed of the stochastic rules, saved in the metadata. defaults to the saved seed.
+                                    a saved string continues the random sequence from its step count
+    --diffusion <node> <amount> run diffusion each step, using these diffusion node and amount symbols.
+                                    defaults to the symbols of the builtin diffusion library when it is included
+    --diffusion-steps <n>       diffusion steps per step. default 1
+    --diffusion-multiplier <x>  default 1
+    --stats                     print statistics for the string after loading and after each step to stderr
+    --trace                     print the rule, stochastic pick and condition value which rewrote each symbol
+                                    on every step to stderr
+    --quiet                     do not print the final string
+    --save <file>               save the final string in the binary format, with metadata and names
+    --svg <file>                draw the string with a classic 2D turtle as an svg
+    --obj <file>                build the string with a classic 3D turtle, and save the mesh as a wavefront obj
+    --glb <file>                build the string with a classic 3D turtle, and save the mesh as binary gltf
+    --angle <degrees>           turn angle of the classic turtles. default 25
+    --step-length <x>           step length of the classic turtles. default 1
+    --radius <x>                stem radius of the classic 3D turtle. default 0.1
+
+The classic turtles read the symbols named F and G to draw a step, f to move a step in 2D,
+    + and - to turn, & and ^ to pitch, \\ and / to roll, and ! to scale thickness by 0.7.
+    parameters replace the default angle or thickness factor, and the step length in 2D
+";
+
+struct Options {
+    input: String,
+    names: Option<String>,
+    branch_symbols: Option<(String, String)>,
+    rules: Option<String>,
+    steps: Option<u32>,
+    seed: Option<u32>,
+    diffusion_symbols: Option<(String, String)>,
+    diffusion_steps: i32,
+    diffusion_multiplier: f32,
+    stats: bool,
+    trace: bool,
+    quiet: bool,
+    save: Option<String>,
+    svg: Option<String>,
+    obj: Option<String>,
+    glb: Option<String>,
+    angle: f32,
+    step_length: f32,
+    radius: f32,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        input: String::new(),
+        names: None,
+        branch_symbols: None,
+        rules: None,
+        steps: None,
+        seed: None,
+        diffusion_symbols: None,
+        diffusion_steps: 1,
+        diffusion_multiplier: 1.0,
+        stats: false,
+        trace: false,
+        quiet: false,
+        save: None,
+        svg: None,
+        obj: None,
+        glb: None,
+        angle: 25.0,
+        step_length: 1.0,
+        radius: 0.1,
+    };
+    let mut input = None;
+    let mut args = args.iter();
+    let value = |args: &mut std::slice::Iter<String>, option: &str| args.next()
+        .cloned()
+        .ok_or_else(|| format!("{} expects a value", option));
+    let number = |text: String, option: &str| text.parse::<f32>()
+        .map_err(|_| format!("{} expects a number, found {}", option, text));
+    let count = |text: String, option: &str| text.parse::<u32>()
+        .map_err(|_| format!("{} expects a whole number, found {}", option, text));
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--names" => options.names = Some(value(&mut args, arg)?),
+            "--branch-symbols" => options.branch_symbols = Some((value(&mut args, arg)?, value(&mut args, arg)?)),
+            "--rules" => options.rules = Some(value(&mut args, arg)?),
+            "--steps" => options.steps = Some(count(value(&mut args, arg)?, arg)?),
+            "--seed" => options.seed = Some(count(value(&mut args, arg)?, arg)?),
+            "--diffusion" => options.diffusion_symbols = Some((value(&mut args, arg)?, value(&mut args, arg)?)),
+            "--diffusion-steps" => options.diffusion_steps = count(value(&mut args, arg)?, arg)? as i32,
+            "--diffusion-multiplier" => options.diffusion_multiplier = number(value(&mut args, arg)?, arg)?,
+            "--stats" => options.stats = true,
+            "--trace" => options.trace = true,
+            "--quiet" => options.quiet = true,
+            "--save" => options.save = Some(value(&mut args, arg)?),
+            "--svg" => options.svg = Some(value(&mut args, arg)?),
+            "--obj" => options.obj = Some(value(&mut args, arg)?),
+            "--glb" => options.glb = Some(value(&mut args, arg)?),
+            "--angle" => options.angle = number(value(&mut args, arg)?, arg)?,
+            "--step-length" => options.step_length = number(value(&mut args, arg)?, arg)?,
+            "--radius" => options.radius = number(value(&mut args, arg)?, arg)?,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if input.is_none() => input = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    options.input = input.ok_or("Missing input file")?;
+    Ok(options)
+}
+
+/// one "<id> <name>" per line. blank lines and lines starting with # are skipped
+fn parse_names(text: &str, names: &mut SymbolNames) -> Result<(), String> {
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (symbol, name) = line.split_once(char::is_whitespace)
+            .ok_or_else(|| format!("Expected an id and a name on line {} of the names", line_index + 1))?;
+        let symbol = symbol.parse::<i32>()
+            .map_err(|_| format!("Invalid symbol id {} on line {} of the names", symbol, line_index + 1))?;
+        names.add(symbol, name.trim());
+    }
+    Ok(())
+}
+
+/// a raw symbol id, or the name of a symbol
+fn resolve_symbol(names: &SymbolNames, text: &str) -> Result<i32, String> {
+    if let Ok(symbol) = text.parse::<i32>() {
+        return Ok(symbol);
+    }
+    names.iter()
+        .find(|(_, name)| *name == text)
+        .map(|(symbol, _)| symbol)
+        .ok_or_else(|| format!("No symbol is named {}", text))
+}
+
+fn named_symbols<'a>(names: &'a SymbolNames, wanted: &'a [&str]) -> impl Iterator<Item = (i32, &'a str)> + 'a {
+    names.iter().filter(|(_, name)| wanted.contains(name))
+}
+
+fn print_stats(step: u32, symbol_string: &SymbolStringOwned, branch_symbols: (i32, i32), diffusion_stats: Option<&DiffusionStats>) {
+    let mut line = format!("step {}: {} symbols, {} parameters", step, symbol_string.symbols.len(), symbol_string.parameters.len());
+    // stats are only a report, so an unbalanced string is reported rather than stopping the run
+    match BranchTree::new(&symbol_string.borrow(), branch_symbols.0, branch_symbols.1, branch_symbols.0) {
+        Ok(branch_tree) => {
+            let max_depth = branch_tree.branches().iter().map(|branch| branch.depth).max().unwrap_or(0);
+            line.push_str(&format!(", {} branches, depth {}", branch_tree.branches().len() - 1, max_depth));
+        }
+        Err(error) => line.push_str(&format!(", {}", error)),
+    }
+    if let Some(diffusion_stats) = diffusion_stats {
+        for (resource_type, resource) in diffusion_stats.resources.iter().enumerate() {
+            line.push_str(&format!(", resource {} total {}", resource_type, resource.total_after_diffusion));
+        }
+    }
+    eprintln!("{}", line);
+}
+
+fn print_trace(step: u32, trace: &RewriteTrace, names: &SymbolNames) {
+    for entry in trace.entries.iter().filter(|entry| !entry.is_trivial) {
+        let mut line = format!("step {}: symbol {} ", step, entry.source_index);
+        match names.name_of(entry.source_symbol) {
+            Some(name) => line.push_str(name),
+            None => line.push_str(&entry.source_symbol.to_string()),
+        }
+        let captured: Vec<String> = trace.captured_parameters_of(entry).iter().map(|parameter| parameter.to_string()).collect();
+        if !captured.is_empty() {
+            line.push_str(&format!("({})", captured.join(",")));
+        }
+        line.push_str(&format!(" rule {} outcome {}", entry.matched_rule_index, entry.selected_replacement_pattern));
+        if entry.has_condition_value {
+            line.push_str(&format!(" condition {}", entry.condition_value));
+        }
+        let replacement = entry.replacement_symbols;
+        line.push_str(&format!(" -> symbols {}..{}", replacement.index, replacement.index + replacement.length as i32));
+        eprintln!("{}", line);
+    }
+}
+
+fn write_svg(path: &str, symbol_string: &SymbolStringOwned, names: &SymbolNames, branch_symbols: (i32, i32), options: &Options) -> Result<(), String> {
+    let mut table = PlanarTurtleTable::new(branch_symbols.0, branch_symbols.1);
+    for (symbol, name) in named_symbols(names, &["F", "G", "f", "+", "-", "!"]) {
+        let operation = match name {
+            "F" | "G" => PlanarOperation::Draw { default_length: options.step_length },
+            "f" => PlanarOperation::Move { default_length: options.step_length },
+            "+" => PlanarOperation::Turn { default_degrees: options.angle },
+            "-" => PlanarOperation::Turn { default_degrees: -options.angle },
+            _ => PlanarOperation::ScaleThickness { default_factor: 0.7 },
+        };
+        table.add(symbol, operation);
+    }
+    let lines = interpret_planar_turtle(&symbol_string.borrow(), &table, &PlanarTurtleState::default())
+        .map_err(|error| error.to_string())?;
+    fs::write(path, planar_lines_to_svg(&lines, options.radius * 2.0))
+        .map_err(|error| format!("Could not write {}: {}", path, error))
+}
+
+fn write_meshes(symbol_string: &SymbolStringOwned, names: &SymbolNames, branch_symbols: (i32, i32), options: &Options) -> Result<(), String> {
+    let mut table = TurtleOperationTable::new();
+    for (symbol, name) in named_symbols(names, &["F", "G", "+", "-", "&", "^", "\\", "/", "!"]) {
+        // the turtle moves along x, with y up
+        let rotate = |axis: Vec3, sign: f32| TurtleOperation::Rotate {
+            axis,
+            default_degrees: options.angle * sign,
+        };
+        let operation = match name {
+            "F" | "G" => TurtleOperation::AddStem(StemOperation {
+                stem_class_index: 0,
+                will_move: true,
+                scale_by_parameter: false,
+                scale_by_thickness: true,
+                length_radius_base: [options.step_length, options.radius],
+                length_radius_scale: [1.0, 1.0],
+                scale_is_additional: false,
+                scale_power: 1.0,
+            }),
+            "+" => rotate(Vec3::new(0.0, 1.0, 0.0), 1.0),
+            "-" => rotate(Vec3::new(0.0, 1.0, 0.0), -1.0),
+            "&" => rotate(Vec3::new(0.0, 0.0, 1.0), 1.0),
+            "^" => rotate(Vec3::new(0.0, 0.0, 1.0), -1.0),
+            "\\" => rotate(Vec3::RIGHT, 1.0),
+            "/" => rotate(Vec3::RIGHT, -1.0),
+            _ => TurtleOperation::ScaleThickness { default_factor: 0.7 },
+        };
+        table.add(symbol, operation);
+    }
+    let symbols = TurtleSymbols {
+        branch_open_symbol: branch_symbols.0,
+        branch_close_symbol: branch_symbols.1,
+        organ_identity_symbol: None,
+        custom_data_symbol: None,
+    };
+    // start facing up
+    let default_state = TurtleState {
+        transformation: Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0),
+        ..TurtleState::default()
+    };
+    let output = interpret_turtle(&symbol_string.borrow(), &table, &symbols, &default_state)
+        .map_err(|error| error.to_string())?;
+    let stem_classes = [StemClass {
+        material_index: 0,
+        radial_resolution: 8,
+    }];
+    let mesh = build_plant_mesh(&output, &[], &stem_classes);
+
+    if let Some(path) = &options.obj {
+        fs::write(path, plant_mesh_to_obj(&mesh))
+            .map_err(|error| format!("Could not write {}: {}", path, error))?;
+    }
+    if let Some(path) = &options.glb {
+        fs::write(path, plant_mesh_to_glb(&mesh))
+            .map_err(|error| format!("Could not write {}: {}", path, error))?;
+    }
+    Ok(())
+}
+
+/// an .lsystem file and the files it includes, adding the names of their symbols
+fn load_lsystem(path: &str, names: &mut SymbolNames) -> Result<LSystemFile, String> {
+    load_lsystem_file(path, names)
+        .map_err(|error| format!("Could not load {}: {}", path, error))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let input = fs::read(&options.input)
+        .map_err(|error| format!("Could not read {}: {}", options.input, error))?;
+    let mut names = SymbolNames::new();
+    let mut metadata = SymbolStringMetadata::default();
+    let mut binary_symbol_string = None;
+    if input.starts_with(&SYMBOL_STRING_MAGIC) {
+        let serialized = read_symbol_string(&input)
+            .map_err(|error| format!("Could not read {}: {:?}", options.input, error))?;
+        binary_symbol_string = Some(serialized.symbol_string);
+        metadata = serialized.metadata.unwrap_or_default();
+        names = serialized.names.unwrap_or_default();
+    }
+    if let Some(names_path) = &options.names {
+        let names_text = fs::read_to_string(names_path)
+            .map_err(|error| format!("Could not read {}: {}", names_path, error))?;
+        parse_names(&names_text, &mut names)?;
+    }
+    // text and rules are parsed after loading the names file, so they can use those names
+    let mut rules = None;
+    let mut default_steps = 0;
+    let mut file_diffusion_symbols = None;
+    let mut symbol_string = match binary_symbol_string {
+        Some(symbol_string) => symbol_string,
+        None if options.input.ends_with(".lsystem") => {
+            let lsystem = load_lsystem(&options.input, &mut names)?;
+            rules = Some(lsystem.rules);
+            file_diffusion_symbols = lsystem.diffusion_symbols;
+            default_steps = lsystem.iterations.unwrap_or(0);
+            lsystem.axiom.ok_or_else(|| format!("{} has no #axiom", options.input))?
+        }
+        None => {
+            let text = String::from_utf8(input)
+                .map_err(|_| format!("{} is neither a binary symbol string nor text", options.input))?;
+            parse_symbol_string(&text, Some(&names))
+                .map_err(|error| format!("Could not parse {}: {}", options.input, error))?
+        }
+    };
+    if let Some(rules_path) = &options.rules {
+        let lsystem = load_lsystem(rules_path, &mut names)?;
+        rules = Some(lsystem.rules);
+        file_diffusion_symbols = lsystem.diffusion_symbols;
+    }
+
+    let branch_symbols = match &options.branch_symbols {
+        Some((open, close)) => (resolve_symbol(&names, open)?, resolve_symbol(&names, close)?),
+        None => {
+            let named_branch_symbol = |name| resolve_symbol(&names, name)
+                .map_err(|error| format!("{}, name the branch symbols or pass --branch-symbols", error));
+            (named_branch_symbol("[")?, named_branch_symbol("]")?)
+        }
+    };
+    let diffusion_symbols = match &options.diffusion_symbols {
+        Some((node, amount)) => Some((resolve_symbol(&names, node)?, resolve_symbol(&names, amount)?)),
+        None => file_diffusion_symbols,
+    };
+    let steps = options.steps.unwrap_or(default_steps);
+    if steps > 0 && rules.is_none() && diffusion_symbols.is_none() {
+        return Err("Nothing to step, load an .lsystem file, or pass --rules or --diffusion".to_string());
+    }
+    if let Some(seed) = options.seed {
+        metadata.seed = seed;
+    }
+    // one seed is drawn for every step, so a saved string picks up the sequence where it stopped
+    let mut step_random = RewriteRandom::new(metadata.seed);
+    for _ in 0..metadata.step_count {
+        step_random.next_u32();
+    }
+
+    if options.stats {
+        print_stats(0, &symbol_string, branch_symbols, None);
+    }
+    for step in 1..=steps {
+        let step_seed = step_random.next_u32();
+        if let Some(rules) = &rules {
+            if options.trace {
+                let (rewritten, trace) = rewrite_traced(&symbol_string.borrow(), rules, step_seed);
+                print_trace(step, &trace, &names);
+                symbol_string = rewritten;
+            } else {
+                symbol_string = rewrite(&symbol_string.borrow(), rules, step_seed);
+            }
+        }
+        let mut diffusion_stats = DiffusionStats::new();
+        if let Some((node_symbol, amount_symbol)) = diffusion_symbols {
+            let diffused = perform_in_place_diffusion_internal(
+                &mut symbol_string.borrow_mut(),
+                node_symbol,
+                amount_symbol,
+                branch_symbols.0,
+                branch_symbols.1,
+                options.diffusion_steps,
+                options.diffusion_multiplier,
+                &DiffusionOptions::default(),
+                Some(&mut diffusion_stats),
+            );
+            if !diffused {
+                return Err(format!("Could not run diffusion on step {}, the branch symbols of the string do not match", step));
+            }
+        }
+        metadata.step_count += 1;
+        if options.stats {
+            print_stats(step, &symbol_string, branch_symbols, diffusion_symbols.map(|_| &diffusion_stats));
+        }
+    }
+
+    if !options.quiet {
+        println!("{}", symbol_string.borrow().display_with(&names));
+    }
+    if let Some(path) = &options.save {
+        let names = (!names.is_empty()).then_some(&names);
+        let bytes = write_symbol_string(&symbol_string.borrow(), Some(&metadata), names)
+            .map_err(|error| format!("Could not save {}: {:?}", path, error))?;
+        fs::write(path, bytes)
+            .map_err(|error| format!("Could not write {}: {}", path, error))?;
+    }
+    if let Some(path) = &options.svg {
+        write_svg(path, &symbol_string, &names, branch_symbols, options)?;
+    }
+    if options.obj.is_some() || options.glb.is_some() {
+        write_meshes(&symbol_string, &names, branch_symbols, options)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let result = parse_options(&args).and_then(|options| run(&options));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,4 +1,15 @@
 pub mod extract_graph;
 pub mod diffusion_job;
 pub mod apply_results;
-pub mod symbol_element_remap;
\ No newline at end of file
+pub mod symbol_element_remap;
+pub mod diffusion_stats;
+pub mod diffusion_options;
+pub mod reactions;
+pub mod forest;
+pub mod voxel_exchange;
+pub mod graph_export;
+pub mod symbol_string_builder;
+pub mod symbol_string_text;
+pub mod symbol_string_binary;
+pub mod branch_tree;
+pub mod symbol_string_edit;
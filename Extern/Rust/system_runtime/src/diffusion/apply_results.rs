@@ -1,6 +1,8 @@
 use crate::diffusion::diffusion_job::{DiffusionAmountData, DiffusionJob};
-use crate::diffusion::extract_graph::{SymbolStringMut, SymbolStringWrite};
+use crate::diffusion::extract_graph::{DiffusionNode, SymbolStringMut, SymbolStringWrite};
 
+/// only the parameters of node symbols are written, through the range stored on each node.
+///     clear_amounts is set when diffusing in place, amount symbols in the target then lose their parameters
 pub fn apply_diffusion_results<'a>(
     diffusion_job: DiffusionJob,
     double_buffered_data: &DiffusionAmountData,
@@ -9,32 +11,43 @@ pub fn apply_diffusion_results<'a>(
     diffusion_amount_symbol: i32,
     clear_amounts: bool,
 ) -> Option<()>{
-    
+
     let amount_data = double_buffered_data.get_latest_data();
 
     for node in diffusion_job.nodes {
         let node_index_in_target = node.index_in_target as usize;
         target_symbols.symbols[node_index_in_target] = diffusion_node_symbol;
         target_symbols.param_indexing[node_index_in_target] = node.target_parameters;
-        
+
         let param_slice = target_symbols.take_param_slice_mut(node_index_in_target);
-        param_slice[0] = node.diffusion_constant;
-        
-        for resource_type in 0..node.total_resource_types {
-            let partial_param = (resource_type * 2 + 1) as usize;
-            let resource_index = (node.index_in_temp_amount_list + resource_type) as usize;
-            param_slice[partial_param] = amount_data[resource_index];
-            param_slice[partial_param + 1] = diffusion_job.node_max_capacities[resource_index];
-        }
+        write_node_params(&diffusion_job, amount_data, node, param_slice);
     }
+
     if clear_amounts {
         for symbol_index in 0..target_symbols.symbols.len() {
             let symbol: i32 = target_symbols.symbols[symbol_index];
             if symbol == diffusion_amount_symbol {
                 target_symbols.param_indexing[symbol_index].length = 0;
             }
-        }   
+        }
     }
 
     Some(())
-}
\ No newline at end of file
+}
+
+fn write_node_params(diffusion_job: &DiffusionJob, amount_data: &[f32], node: &DiffusionNode, param_slice: &mut [f32]) {
+    param_slice[0] = node.diffusion_constant;
+
+    let layout = diffusion_job.node_layout;
+    for resource_type in 0..node.total_resource_types as usize {
+        let resource_index = node.index_in_temp_amount_list as usize + resource_type;
+        param_slice[layout.amount_param(resource_type)] = amount_data[resource_index];
+        param_slice[layout.capacity_param(resource_type)] = diffusion_job.node_max_capacities[resource_index];
+        if let Some(source_rate_param) = layout.source_rate_param(resource_type) {
+            param_slice[source_rate_param] = diffusion_job.node_source_rates[resource_index];
+        }
+        if let Some(decay_rate_param) = layout.decay_rate_param(resource_type) {
+            param_slice[decay_rate_param] = diffusion_job.node_decay_rates[resource_index];
+        }
+    }
+}
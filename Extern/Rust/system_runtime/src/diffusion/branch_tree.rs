@@ -0,0 +1,331 @@
+use std::fmt;
+use std::ops::Range;
+use crate::diffusion::extract_graph::SymbolString;
+
+/// A single branch of the string, from its open symbol to its matching close symbol
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Branch {
+    /// -1 for the root branch
+    pub parent_branch: i32,
+    /// index of the branch open symbol. -1 for the root branch
+    pub open_index: i32,
+    /// index of the matching branch close symbol. -1 for the root branch, and for a branch which is never closed
+    ///     in a tree built by new_tolerant
+    pub close_index: i32,
+    /// 0 for the root branch
+    pub depth: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BranchTreeError {
+    /// a branch close symbol at this index has no branch open symbol before it
+    UnmatchedClose(usize),
+    /// the branch open symbol at this index is never closed
+    UnclosedOpen(usize),
+}
+
+impl fmt::Display for BranchTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BranchTreeError::UnmatchedClose(index) => write!(f, "Branch close symbol at {} has no matching open symbol", index),
+            BranchTreeError::UnclosedOpen(index) => write!(f, "Branch open symbol at {} is never closed", index),
+        }
+    }
+}
+
+impl std::error::Error for BranchTreeError {}
+
+/// The branching structure of a symbol string, built once so it can be shared instead of every algorithm
+///     walking the branch symbols with its own stack.
+/// branch 0 is the root branch, which holds every symbol outside of any branch symbols.
+///     branch open and close symbols belong to the branch they delimit
+pub struct BranchTree {
+    branches: Vec<Branch>,
+    symbol_branches: Vec<i32>,
+    previous_nodes: Vec<i32>,
+    /// the children of branch n are child_ids[child_starts[n]..child_starts[n + 1]]
+    child_starts: Vec<usize>,
+    child_ids: Vec<usize>,
+}
+
+impl BranchTree {
+    /// node_symbol is the symbol tracked by previous_node, such as the diffusion node symbol
+    pub fn new(
+        symbol_string: &SymbolString,
+        branch_open_symbol: i32,
+        branch_close_symbol: i32,
+        node_symbol: i32) -> Result<Self, BranchTreeError> {
+        Self::build(symbol_string, branch_open_symbol, branch_close_symbol, node_symbol, false)
+    }
+
+    /// build the tree of a string whose branch symbols may not match, reading them the way the unity walk does.
+    ///     a close symbol without an open symbol is a plain symbol of the branch it is in,
+    ///     and a branch which is never closed runs to the end of the string
+    pub fn new_tolerant(
+        symbol_string: &SymbolString,
+        branch_open_symbol: i32,
+        branch_close_symbol: i32,
+        node_symbol: i32) -> Self {
+        match Self::build(symbol_string, branch_open_symbol, branch_close_symbol, node_symbol, true) {
+            Ok(branch_tree) => branch_tree,
+            Err(error) => unreachable!("a tolerant branch tree failed to build: {}", error),
+        }
+    }
+
+    fn build(
+        symbol_string: &SymbolString,
+        branch_open_symbol: i32,
+        branch_close_symbol: i32,
+        node_symbol: i32,
+        tolerant: bool) -> Result<Self, BranchTreeError> {
+
+        let mut branches = vec![Branch {
+            parent_branch: -1,
+            open_index: -1,
+            close_index: -1,
+            depth: 0,
+        }];
+        let mut symbol_branches = Vec::with_capacity(symbol_string.symbols.len());
+        let mut previous_nodes = Vec::with_capacity(symbol_string.symbols.len());
+
+        // the enclosing branch, and the last node before the branch opened
+        let mut open_branch_stack: Vec<(i32, i32)> = Vec::with_capacity(5);
+        let mut current_branch: i32 = 0;
+        let mut previous_node: i32 = -1;
+
+        for (symbol_index, symbol) in symbol_string.symbols.iter().enumerate() {
+            if *symbol == branch_open_symbol {
+                open_branch_stack.push((current_branch, previous_node));
+                let parent_depth = branches[current_branch as usize].depth;
+                branches.push(Branch {
+                    parent_branch: current_branch,
+                    open_index: symbol_index as i32,
+                    close_index: -1,
+                    depth: parent_depth + 1,
+                });
+                current_branch = branches.len() as i32 - 1;
+            }
+
+            symbol_branches.push(current_branch);
+            previous_nodes.push(previous_node);
+
+            if *symbol == branch_close_symbol {
+                match open_branch_stack.pop() {
+                    Some((enclosing_branch, enclosing_previous_node)) => {
+                        branches[current_branch as usize].close_index = symbol_index as i32;
+                        current_branch = enclosing_branch;
+                        previous_node = enclosing_previous_node;
+                    }
+                    None if tolerant => {}
+                    None => return Err(BranchTreeError::UnmatchedClose(symbol_index)),
+                }
+            } else if *symbol == node_symbol {
+                previous_node = symbol_index as i32;
+            }
+        }
+
+        if !tolerant && open_branch_stack.pop().is_some() {
+            return Err(BranchTreeError::UnclosedOpen(branches[current_branch as usize].open_index as usize));
+        }
+
+        let mut child_starts = vec![0; branches.len() + 1];
+        for branch in branches.iter().skip(1) {
+            child_starts[branch.parent_branch as usize + 1] += 1;
+        }
+        for branch_id in 0..branches.len() {
+            child_starts[branch_id + 1] += child_starts[branch_id];
+        }
+        // branches are visited in order, so each list of children stays in the order the children open
+        let mut next_child_slot = child_starts.clone();
+        let mut child_ids = vec![0; branches.len() - 1];
+        for (branch_id, branch) in branches.iter().enumerate().skip(1) {
+            let slot = &mut next_child_slot[branch.parent_branch as usize];
+            child_ids[*slot] = branch_id;
+            *slot += 1;
+        }
+
+        Ok(BranchTree {
+            branches,
+            symbol_branches,
+            previous_nodes,
+            child_starts,
+            child_ids,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbol_branches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbol_branches.is_empty()
+    }
+
+    /// every branch, indexed by branch id, in the order their open symbols appear
+    pub fn branches(&self) -> &[Branch] {
+        &self.branches
+    }
+
+    pub fn branch(&self, branch_id: usize) -> &Branch {
+        &self.branches[branch_id]
+    }
+
+    /// the id of the branch holding the symbol
+    pub fn branch_of(&self, symbol_index: usize) -> usize {
+        self.symbol_branches[symbol_index] as usize
+    }
+
+    /// the id of the branch enclosing the branch holding the symbol. None for symbols in the root branch
+    pub fn parent_branch_of(&self, symbol_index: usize) -> Option<usize> {
+        usize::try_from(self.branches[self.branch_of(symbol_index)].parent_branch).ok()
+    }
+
+    /// the number of branches enclosing the symbol
+    pub fn depth_of(&self, symbol_index: usize) -> u32 {
+        self.branches[self.branch_of(symbol_index)].depth
+    }
+
+    /// the index of the matching close symbol for a branch open symbol, or the matching open symbol for a branch close symbol
+    pub fn matching_bracket(&self, symbol_index: usize) -> Option<usize> {
+        let branch = &self.branches[self.branch_of(symbol_index)];
+        if branch.open_index == symbol_index as i32 {
+            usize::try_from(branch.close_index).ok()
+        } else if branch.close_index == symbol_index as i32 {
+            Some(branch.open_index as usize)
+        } else {
+            None
+        }
+    }
+
+    /// the index of the nearest node symbol before this symbol along the path back to the root,
+    ///     skipping over any branches in between. a node's previous node is its parent in the diffusion graph
+    pub fn previous_node(&self, symbol_index: usize) -> Option<usize> {
+        usize::try_from(self.previous_nodes[symbol_index]).ok()
+    }
+
+    /// the symbol indexes spanned by the branch, including its open and close symbols
+    pub fn symbol_range(&self, branch_id: usize) -> Range<usize> {
+        let branch = &self.branches[branch_id];
+        if branch.open_index < 0 {
+            return 0..self.len();
+        }
+        if branch.close_index < 0 {
+            return branch.open_index as usize..self.len();
+        }
+        branch.open_index as usize..branch.close_index as usize + 1
+    }
+
+    /// the ids of the branches directly inside this branch
+    pub fn child_branches(&self, branch_id: usize) -> impl Iterator<Item = usize> + '_ {
+        self.child_ids[self.child_starts[branch_id]..self.child_starts[branch_id + 1]].iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interop_extern::data::JaggedIndexing;
+
+    const NODE: i32 = 1;
+    const PLAIN: i32 = 2;
+    const OPEN: i32 = 10;
+    const CLOSE: i32 = 11;
+
+    fn build(symbols: &[i32], tolerant: bool) -> Result<BranchTree, BranchTreeError> {
+        let param_indexing = vec![JaggedIndexing { index: 0, length: 0 }; symbols.len()];
+        let symbol_string = SymbolString {
+            symbols,
+            param_indexing: &param_indexing,
+            parameters: &[],
+        };
+        if tolerant {
+            Ok(BranchTree::new_tolerant(&symbol_string, OPEN, CLOSE, NODE))
+        } else {
+            BranchTree::new(&symbol_string, OPEN, CLOSE, NODE)
+        }
+    }
+
+    /// A N [ N [ N ] A ] [ N ] N
+    fn nested() -> BranchTree {
+        build(&[PLAIN, NODE, OPEN, NODE, OPEN, NODE, CLOSE, PLAIN, CLOSE, OPEN, NODE, CLOSE, NODE], false).unwrap()
+    }
+
+    #[test]
+    fn branches_record_parent_and_depth() {
+        let branch_tree = nested();
+        assert_eq!(branch_tree.len(), 13);
+        assert_eq!(branch_tree.branches(), &[
+            Branch { parent_branch: -1, open_index: -1, close_index: -1, depth: 0 },
+            Branch { parent_branch: 0, open_index: 2, close_index: 8, depth: 1 },
+            Branch { parent_branch: 1, open_index: 4, close_index: 6, depth: 2 },
+            Branch { parent_branch: 0, open_index: 9, close_index: 11, depth: 1 },
+        ]);
+
+        let symbol_branches: Vec<usize> = (0..branch_tree.len()).map(|index| branch_tree.branch_of(index)).collect();
+        assert_eq!(symbol_branches, vec![0, 0, 1, 1, 2, 2, 2, 1, 1, 3, 3, 3, 0]);
+        assert_eq!(branch_tree.parent_branch_of(0), None);
+        assert_eq!(branch_tree.parent_branch_of(5), Some(1));
+        assert_eq!(branch_tree.parent_branch_of(10), Some(0));
+        assert_eq!(branch_tree.depth_of(12), 0);
+        assert_eq!(branch_tree.depth_of(7), 1);
+        assert_eq!(branch_tree.depth_of(5), 2);
+    }
+
+    #[test]
+    fn brackets_match_across_nesting() {
+        let branch_tree = nested();
+        assert_eq!(branch_tree.matching_bracket(2), Some(8));
+        assert_eq!(branch_tree.matching_bracket(8), Some(2));
+        assert_eq!(branch_tree.matching_bracket(4), Some(6));
+        assert_eq!(branch_tree.matching_bracket(6), Some(4));
+        assert_eq!(branch_tree.matching_bracket(11), Some(9));
+        assert_eq!(branch_tree.matching_bracket(3), None);
+
+        assert_eq!(branch_tree.symbol_range(0), 0..13);
+        assert_eq!(branch_tree.symbol_range(1), 2..9);
+        assert_eq!(branch_tree.symbol_range(2), 4..7);
+    }
+
+    #[test]
+    fn previous_node_skips_closed_branches() {
+        let branch_tree = nested();
+        let previous_nodes: Vec<Option<usize>> = (0..branch_tree.len()).map(|index| branch_tree.previous_node(index)).collect();
+        assert_eq!(previous_nodes, vec![
+            None, None,
+            Some(1), Some(1), Some(3), Some(3), Some(5), Some(3), Some(3),
+            Some(1), Some(1), Some(10),
+            Some(1),
+        ]);
+    }
+
+    #[test]
+    fn child_branches_are_in_open_order() {
+        let branch_tree = nested();
+        assert_eq!(branch_tree.child_branches(0).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(branch_tree.child_branches(1).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(branch_tree.child_branches(2).count(), 0);
+        assert_eq!(branch_tree.child_branches(3).count(), 0);
+    }
+
+    #[test]
+    fn unbalanced_strings_are_rejected() {
+        assert_eq!(build(&[PLAIN, CLOSE, PLAIN], false).err(), Some(BranchTreeError::UnmatchedClose(1)));
+        assert_eq!(build(&[OPEN, NODE, CLOSE, CLOSE], false).err(), Some(BranchTreeError::UnmatchedClose(3)));
+        assert_eq!(build(&[OPEN, PLAIN, OPEN, PLAIN, CLOSE], false).err(), Some(BranchTreeError::UnclosedOpen(0)));
+        assert_eq!(build(&[PLAIN, OPEN, PLAIN], false).err(), Some(BranchTreeError::UnclosedOpen(1)));
+    }
+
+    #[test]
+    fn tolerant_tree_reads_unbalanced_strings_like_unity() {
+        // N ] [ N
+        let branch_tree = build(&[NODE, CLOSE, OPEN, NODE], true).unwrap();
+        assert_eq!(branch_tree.branch_of(1), 0);
+        assert_eq!(branch_tree.matching_bracket(1), None);
+        assert_eq!(branch_tree.previous_node(1), Some(0));
+
+        assert_eq!(branch_tree.branch(1), &Branch { parent_branch: 0, open_index: 2, close_index: -1, depth: 1 });
+        assert_eq!(branch_tree.matching_bracket(2), None);
+        assert_eq!(branch_tree.symbol_range(1), 2..4);
+        assert_eq!(branch_tree.previous_node(3), Some(0));
+    }
+}
@@ -1,9 +1,35 @@
-﻿use crate::diffusion::extract_graph::{DiffusionNode};
+﻿use crate::diffusion::diffusion_options::{CapacityModel, DiffusionNodeLayout, MissingResourceMode};
+use crate::diffusion::diffusion_stats::DiffusionStats;
+use crate::diffusion::extract_graph::{DiffusionNode};
+use crate::diffusion::reactions::{apply_reactions, DiffusionReaction, ReactionTerm};
+
+/// An edge outside of the branch tree, such as a graft or root connection between two plants.
+/// diffuses symmetrically, without directional transport
+#[derive(Copy, Clone, Debug)]
+pub struct DiffusionEdge {
+    pub node_a_index: i32,
+    pub node_b_index: i32,
+    /// multiplies the diffusion constant across this edge
+    pub edge_conductance: f32,
+}
 
 #[derive(Copy, Clone)]
 pub struct DiffusionJob<'a> {
     pub nodes: &'a [DiffusionNode],
+    /// edges in addition to the edge between each node and its parent
+    pub extra_edges: &'a [DiffusionEdge],
     pub node_max_capacities: &'a [f32],
+    /// parallel to node_max_capacities. empty when the node layout carries no source rates
+    pub node_source_rates: &'a [f32],
+    /// parallel to node_max_capacities. empty when no resource decays
+    pub node_decay_rates: &'a [f32],
+    pub node_layout: DiffusionNodeLayout,
+    /// indexed by resource type. see DiffusionOptions::directional_transport
+    pub directional_transport: &'a [f32],
+    pub missing_resource_mode: MissingResourceMode,
+    pub capacity_model: CapacityModel,
+    pub reactions: &'a [DiffusionReaction],
+    pub reaction_terms: &'a [ReactionTerm],
     pub diffusion_global_multiplier: f32,
 }
 
@@ -31,27 +57,111 @@ impl DiffusionAmountData<'_> {
             self.node_amount_list_b
         }
     }
+
+    pub fn get_latest_data_mut(&mut self) -> &mut [f32] {
+        if self.latest_in_a {
+            self.node_amount_list_a
+        }else {
+            self.node_amount_list_b
+        }
+    }
 }
 
 impl DiffusionJob<'_> {
     pub fn diffuse_between(
         self,
         double_buffered_data: &mut DiffusionAmountData,
-        diffuse_steps: i32) -> bool {
+        diffuse_steps: i32,
+        mut stats: Option<&mut DiffusionStats>) -> bool {
         for _ in 0..diffuse_steps
         {
             let (source_amounts, target_amounts) = double_buffered_data.take_buffer_pair();
 
             (*target_amounts).copy_from_slice(source_amounts);
 
+            if !self.node_source_rates.is_empty() {
+                self.apply_source_rates(source_amounts, target_amounts, stats.as_deref_mut());
+            }
+
+            if !self.node_decay_rates.is_empty() {
+                self.apply_decay(source_amounts, target_amounts, stats.as_deref_mut());
+            }
+
+            if !self.reactions.is_empty() {
+                for node in self.nodes {
+                    apply_reactions(
+                        self.reactions,
+                        self.reaction_terms,
+                        node,
+                        self.node_max_capacities,
+                        target_amounts,
+                        stats.as_deref_mut());
+                }
+            }
+
             for node in self.nodes {
-                self.diffuse_across_edge(node, source_amounts, target_amounts);
+                self.diffuse_across_edge(node, source_amounts, target_amounts, stats.as_deref_mut());
             }
+
+            for edge in self.extra_edges {
+                self.diffuse_across_extra_edge(edge, source_amounts, target_amounts, stats.as_deref_mut());
+            }
+        }
+        if let Some(stats) = stats {
+            stats.record_after_diffusion(self.nodes, double_buffered_data.get_latest_data());
         }
         false
     }
 
-    fn diffuse_across_edge(self, node: &DiffusionNode, source_amounts: &[f32], target_amounts: &mut [f32]) {
+    fn apply_source_rates(self, source_amounts: &[f32], target_amounts: &mut [f32], mut stats: Option<&mut DiffusionStats>) {
+        for node in self.nodes {
+            let node_start = node.index_in_temp_amount_list as usize;
+            for resource in 0..node.total_resource_types as usize {
+                let resource_index = node_start + resource;
+                let rate = self.node_source_rates[resource_index];
+                let old_value = source_amounts[resource_index];
+
+                // production never fills the node past its capacity, and consumption never takes more than the node still holds
+                let applied_amount = if rate > 0.0 {
+                    rate.min((self.node_max_capacities[resource_index] - old_value).max(0.0))
+                } else {
+                    rate.max(-target_amounts[resource_index].max(0.0))
+                };
+
+                target_amounts[resource_index] += applied_amount;
+                if let Some(stats) = stats.as_deref_mut() {
+                    stats.record_source(resource, applied_amount);
+                }
+            }
+        }
+    }
+
+    fn apply_decay(self, source_amounts: &[f32], target_amounts: &mut [f32], mut stats: Option<&mut DiffusionStats>) {
+        for node in self.nodes {
+            let node_start = node.index_in_temp_amount_list as usize;
+            for resource in 0..node.total_resource_types as usize {
+                let resource_index = node_start + resource;
+                let rate = self.node_decay_rates[resource_index];
+                if rate <= 0.0 {
+                    continue;
+                }
+                let decayed_amount = (source_amounts[resource_index].max(0.0) * (1.0 - (-rate).exp()))
+                    .min(target_amounts[resource_index].max(0.0));
+
+                target_amounts[resource_index] -= decayed_amount;
+                if let Some(stats) = stats.as_deref_mut() {
+                    stats.resource_mut(resource).decayed += decayed_amount;
+                }
+            }
+        }
+    }
+
+    fn diffuse_across_edge(
+        self,
+        node: &DiffusionNode,
+        source_amounts: &[f32],
+        target_amounts: &mut [f32],
+        mut stats: Option<&mut DiffusionStats>) {
         if node.parent_node_index < 0 {
             return;
         }
@@ -59,7 +169,7 @@ impl DiffusionJob<'_> {
         let node_b = &self.nodes[node.parent_node_index as usize];
 
         let diffusion_constant =
-            self.diffusion_global_multiplier *
+            self.diffusion_global_multiplier * node_a.edge_conductance *
                 (node_a.diffusion_constant + node_b.diffusion_constant) / 2.0;
 
         let blended_resource_num = node_a.total_resource_types.min(node_b.total_resource_types);
@@ -68,32 +178,198 @@ impl DiffusionJob<'_> {
         let node_b_temp_amt_index = node_b.index_in_temp_amount_list as usize;
         
         for resource in 0..blended_resource_num as usize {
-            let node_a_resource_index = node_a_temp_amt_index + resource;
-            let node_b_resource_index = node_b_temp_amt_index + resource;
-            
-            let old_node_a_value = source_amounts[node_a_resource_index];
-            let old_node_b_value = source_amounts[node_b_resource_index];
-            
-            let a_to_b_transferred_amount = diffusion_constant * (old_node_b_value - old_node_a_value);
-            let is_towards_b = a_to_b_transferred_amount < 0.0;
-            
-            let node_b_value_cap = self.node_max_capacities[node_b_resource_index];
-            if is_towards_b && old_node_b_value >= node_b_value_cap {
-                // the direction of flow is towards node B, and also node B is above its value cap. skip updating the resource on this connection completely.
-                continue;
+            self.transfer_resource(
+                resource,
+                node_a_temp_amt_index + resource,
+                node_b_temp_amt_index + resource,
+                diffusion_constant,
+                self.directional_transport.get(resource).copied().unwrap_or(0.0),
+                source_amounts,
+                target_amounts,
+                stats.as_deref_mut());
+        }
+
+        if node_a.total_resource_types != node_b.total_resource_types {
+            self.diffuse_mismatched_resources(node_a, node_b, diffusion_constant, source_amounts, target_amounts, stats);
+        }
+    }
+
+    fn diffuse_across_extra_edge(
+        self,
+        edge: &DiffusionEdge,
+        source_amounts: &[f32],
+        target_amounts: &mut [f32],
+        mut stats: Option<&mut DiffusionStats>) {
+        let node_a = &self.nodes[edge.node_a_index as usize];
+        let node_b = &self.nodes[edge.node_b_index as usize];
+
+        let diffusion_constant =
+            self.diffusion_global_multiplier * edge.edge_conductance *
+                (node_a.diffusion_constant + node_b.diffusion_constant) / 2.0;
+
+        let blended_resource_num = node_a.total_resource_types.min(node_b.total_resource_types);
+        for resource in 0..blended_resource_num as usize {
+            self.transfer_resource(
+                resource,
+                node_a.index_in_temp_amount_list as usize + resource,
+                node_b.index_in_temp_amount_list as usize + resource,
+                diffusion_constant,
+                0.0,
+                source_amounts,
+                target_amounts,
+                stats.as_deref_mut());
+        }
+    }
+
+    /// move one resource between node A and node B. on tree edges node A is the child, and directional transport
+    /// moves resources from A to B when positive
+    #[allow(clippy::too_many_arguments)]
+    fn transfer_resource(
+        self,
+        resource: usize,
+        node_a_resource_index: usize,
+        node_b_resource_index: usize,
+        diffusion_constant: f32,
+        directional_transport: f32,
+        source_amounts: &[f32],
+        target_amounts: &mut [f32],
+        stats: Option<&mut DiffusionStats>) {
+        let old_node_a_value = source_amounts[node_a_resource_index];
+        let old_node_b_value = source_amounts[node_b_resource_index];
+        
+        let mut a_to_b_transferred_amount = diffusion_constant * (old_node_b_value - old_node_a_value);
+        if directional_transport != 0.0 {
+            // active transport draws from whichever node it points away from
+            a_to_b_transferred_amount -= if directional_transport > 0.0 {
+                directional_transport * old_node_a_value
+            } else {
+                directional_transport * old_node_b_value
+            };
+        }
+        let is_towards_b = a_to_b_transferred_amount < 0.0;
+
+        if self.capacity_model == CapacityModel::Soft {
+            let (receiver_index, old_receiver_value, inflow) = if is_towards_b {
+                (node_b_resource_index, old_node_b_value, -a_to_b_transferred_amount)
+            } else {
+                (node_a_resource_index, old_node_a_value, a_to_b_transferred_amount)
+            };
+            let receiver_cap = self.node_max_capacities[receiver_index];
+            let headroom_fraction = if receiver_cap > 0.0 {
+                (1.0 - old_receiver_value / receiver_cap).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            // other edges may have already moved resources into the receiver during this step
+            let room_left = (receiver_cap - target_amounts[receiver_index]).max(0.0);
+            let applied_inflow = (inflow * headroom_fraction).min(room_left);
+
+            if let Some(stats) = stats {
+                stats.record_blocked(resource, inflow - applied_inflow);
+            }
+            let applied_a_to_b = limit_to_donor(
+                if is_towards_b { -applied_inflow } else { applied_inflow },
+                node_a_resource_index,
+                node_b_resource_index,
+                target_amounts);
+            target_amounts[node_a_resource_index] += applied_a_to_b;
+            target_amounts[node_b_resource_index] -= applied_a_to_b;
+            return;
+        }
+        
+        let node_b_value_cap = self.node_max_capacities[node_b_resource_index];
+        if is_towards_b && old_node_b_value >= node_b_value_cap {
+            // the direction of flow is towards node B, and also node B is above its value cap. skip updating the resource on this connection completely.
+            if let Some(stats) = stats {
+                stats.record_blocked(resource, a_to_b_transferred_amount);
+            }
+            return;
+        }
+        let node_a_value_cap = self.node_max_capacities[node_a_resource_index];
+        if !is_towards_b && old_node_a_value >= node_a_value_cap {
+            // the direction of flow is towards node A, and also node A is above its value cap. skip updating the resource on this connection completely.
+            if let Some(stats) = stats {
+                stats.record_blocked(resource, a_to_b_transferred_amount);
+            }
+            return;
+        }
+        
+        let a_to_b_transferred_amount = limit_to_donor(
+            a_to_b_transferred_amount,
+            node_a_resource_index,
+            node_b_resource_index,
+            target_amounts);
+        target_amounts[node_a_resource_index] += a_to_b_transferred_amount;
+        target_amounts[node_b_resource_index] -= a_to_b_transferred_amount;
+    }
+
+    /// handle the resources held by only one side of the edge between node A and its parent node B
+    fn diffuse_mismatched_resources(
+        self,
+        node_a: &DiffusionNode,
+        node_b: &DiffusionNode,
+        diffusion_constant: f32,
+        source_amounts: &[f32],
+        target_amounts: &mut [f32],
+        mut stats: Option<&mut DiffusionStats>) {
+        let blended_resource_num = node_a.total_resource_types.min(node_b.total_resource_types) as usize;
+        match self.missing_resource_mode {
+            MissingResourceMode::ZeroCapacity => {}
+            MissingResourceMode::ZeroAmount => {
+                let holder = if node_a.total_resource_types > node_b.total_resource_types { node_a } else { node_b };
+                let holder_temp_amt_index = holder.index_in_temp_amount_list as usize;
+                for resource in blended_resource_num..holder.total_resource_types as usize {
+                    let resource_index = holder_temp_amt_index + resource;
+                    // the missing slot is always empty and never fills, so this is lost from the graph
+                    let drained_amount = (diffusion_constant * source_amounts[resource_index].max(0.0))
+                        .min(target_amounts[resource_index].max(0.0));
+                    target_amounts[resource_index] -= drained_amount;
+                    if let Some(stats) = stats.as_deref_mut() {
+                        stats.resource_mut(resource).drained_to_missing_slots += drained_amount;
+                    }
+                }
             }
-            let node_a_value_cap = self.node_max_capacities[node_a_resource_index];
-            if !is_towards_b && old_node_a_value >= node_a_value_cap {
-                // the direction of flow is towards node A, and also node A is above its value cap. skip updating the resource on this connection completely.
-                continue;
+            MissingResourceMode::PassThrough => {
+                let node_a_temp_amt_index = node_a.index_in_temp_amount_list as usize;
+                // resources only the parent holds will be passed through node A by the children of node A which hold them
+                for resource in blended_resource_num..node_a.total_resource_types as usize {
+                    let mut ancestor_index = node_b.parent_node_index;
+                    while ancestor_index >= 0 && self.nodes[ancestor_index as usize].total_resource_types as usize <= resource {
+                        ancestor_index = self.nodes[ancestor_index as usize].parent_node_index;
+                    }
+                    if ancestor_index < 0 {
+                        continue;
+                    }
+                    let ancestor = &self.nodes[ancestor_index as usize];
+                    let pass_through_constant =
+                        self.diffusion_global_multiplier * node_a.edge_conductance *
+                            (node_a.diffusion_constant + ancestor.diffusion_constant) / 2.0;
+                    self.transfer_resource(
+                        resource,
+                        node_a_temp_amt_index + resource,
+                        ancestor.index_in_temp_amount_list as usize + resource,
+                        pass_through_constant,
+                        self.directional_transport.get(resource).copied().unwrap_or(0.0),
+                        source_amounts,
+                        target_amounts,
+                        stats.as_deref_mut());
+                }
             }
-            
-            target_amounts[node_a_resource_index] += a_to_b_transferred_amount;
-            target_amounts[node_b_resource_index] -= a_to_b_transferred_amount;
         }
     }
 }
 
+/// limit a transfer so that the donor never gives more than it still holds during this step.
+///     diffusion and directional transport are both computed from the amounts before the step,
+///     so without this a node with several edges, or strong transport, could be drained below zero
+fn limit_to_donor(a_to_b_transferred_amount: f32, node_a_resource_index: usize, node_b_resource_index: usize, target_amounts: &[f32]) -> f32 {
+    if a_to_b_transferred_amount < 0.0 {
+        a_to_b_transferred_amount.max(-target_amounts[node_a_resource_index].max(0.0))
+    } else {
+        a_to_b_transferred_amount.min(target_amounts[node_b_resource_index].max(0.0))
+    }
+}
+
 impl DiffusionNode {
     pub fn get_resource_slice<'a>(&'a self, data: &'a [f32]) -> &[f32] {
         let index_in_list = self.index_in_temp_amount_list as usize;
@@ -124,3 +400,102 @@ fn _get_exclusive_slices<'a>(node_a: &DiffusionNode, node_b: &DiffusionNode, dat
         (trunc_higher, trunc_lower)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::diffusion::diffusion_options::{DiffusionOptions, MissingResourceMode};
+    use crate::diffusion::extract_graph::extract_edges_and_nodes_read_only;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+
+    const NODE: i32 = 1;
+    const AMOUNT: i32 = 2;
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+
+    /// diffuse the graph of the string, returning the amounts of each node after every step
+    fn diffuse(text: &str, options: &DiffusionOptions, steps: i32) -> Vec<Vec<f32>> {
+        let symbol_string = parse_symbol_string(text, None).unwrap();
+        let (mut job, mut amounts) = extract_edges_and_nodes_read_only(
+            &symbol_string.borrow(), NODE, AMOUNT, OPEN, CLOSE, options, None).unwrap();
+        job.configure(1.0, options);
+        let mut amount_data = amounts.borrowed_mut();
+        job.borrowed().diffuse_between(&mut amount_data, steps, None);
+        job.nodes.iter()
+            .map(|node| node.get_resource_slice(amount_data.get_latest_data()).to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn basipetal_transport_takes_no_more_than_the_child_holds() {
+        let options = DiffusionOptions {
+            directional_transport: &[1.0],
+            ..Default::default()
+        };
+        // diffusion alone would move 0.5 to the parent, transport adds the whole amount of the child on top
+        let amounts = diffuse("1(0.5,0,10) -10 1(0.5,1,10) -11", &options, 1);
+
+        assert_eq!(amounts, vec![vec![1.0], vec![0.0]]);
+    }
+
+    #[test]
+    fn acropetal_transport_takes_no_more_than_the_parent_holds() {
+        let options = DiffusionOptions {
+            directional_transport: &[-1.0],
+            ..Default::default()
+        };
+        // each child edge asks for more than the parent holds, so the first child takes it all and the second gets nothing
+        let amounts = diffuse("1(0.5,1,10) -10 1(0.5,0,10) -11 -10 1(0.5,0,10) -11", &options, 1);
+
+        assert_eq!(amounts, vec![vec![0.0], vec![1.0], vec![0.0]]);
+    }
+
+    #[test]
+    fn donors_never_go_negative_over_many_steps() {
+        let options = DiffusionOptions {
+            directional_transport: &[0.8, -0.8],
+            ..Default::default()
+        };
+        let text = "1(0.9,3,10,1,10) -10 1(0.9,0,10,2,10) -10 1(0.9,1,10,0,10) -11 -11 -10 1(0.9,2,10,0,10) -11";
+        for steps in 1..8 {
+            let amounts = diffuse(text, &options, steps);
+            for resource in 0..2 {
+                let total: f32 = amounts.iter().map(|node| node[resource]).sum();
+                assert!((total - 6.0 + 3.0 * resource as f32).abs() < 1e-4, "resource {} totals {} after {} steps", resource, total, steps);
+            }
+            assert!(amounts.iter().flatten().all(|amount| *amount >= 0.0), "{:?} after {} steps", amounts, steps);
+        }
+    }
+
+    /// the root and the last node hold two resources, the node between them only the first
+    const MISMATCHED_CHAIN: &str = "1(0.5,0,10,4,10) 1(0.5,0,10) 1(0.5,0,10,0,10)";
+
+    fn diffuse_missing(missing_resource_mode: MissingResourceMode) -> Vec<Vec<f32>> {
+        let options = DiffusionOptions {
+            missing_resource_mode,
+            ..Default::default()
+        };
+        diffuse(MISMATCHED_CHAIN, &options, 1)
+    }
+
+    #[test]
+    fn zero_capacity_stops_missing_resources_at_the_edge() {
+        let amounts = diffuse_missing(MissingResourceMode::ZeroCapacity);
+
+        assert_eq!(amounts, vec![vec![0.0, 4.0], vec![0.0], vec![0.0, 0.0]]);
+    }
+
+    #[test]
+    fn zero_amount_drains_missing_resources_out_of_the_graph() {
+        let amounts = diffuse_missing(MissingResourceMode::ZeroAmount);
+
+        // the root loses what would flow into an empty slot, the last node has nothing to lose
+        assert_eq!(amounts, vec![vec![0.0, 2.0], vec![0.0], vec![0.0, 0.0]]);
+    }
+
+    #[test]
+    fn pass_through_connects_to_the_nearest_ancestor_holding_the_resource() {
+        let amounts = diffuse_missing(MissingResourceMode::PassThrough);
+
+        assert_eq!(amounts, vec![vec![0.0, 2.0], vec![0.0], vec![0.0, 2.0]]);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::diffusion::reactions::{DiffusionReaction, ReactionTerm};
+
+/// Describes how the parameters of a diffusion node symbol are laid out.
+/// the first parameter is always the diffusion constant, followed by a fixed size group of parameters per resource.
+/// each group always begins with the amount and the capacity of that resource, optional parameters follow in the order declared here
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DiffusionNodeLayout {
+    /// when set, each resource group carries a signed source rate after the capacity.
+    ///     positive values produce the resource, negative values consume it, once per diffusion step
+    pub has_source_rate: bool,
+    /// when set, each resource group carries an exponential decay rate after the source rate, if any.
+    ///     overrides DiffusionOptions::decay_rates for that node
+    pub has_decay_rate: bool,
+}
+
+impl DiffusionNodeLayout {
+    pub fn params_per_resource(&self) -> usize {
+        2 + self.has_source_rate as usize + self.has_decay_rate as usize
+    }
+
+    pub fn resource_count(&self, param_count: usize) -> usize {
+        param_count.saturating_sub(1) / self.params_per_resource()
+    }
+
+    pub fn amount_param(&self, resource_type: usize) -> usize {
+        1 + resource_type * self.params_per_resource()
+    }
+
+    pub fn capacity_param(&self, resource_type: usize) -> usize {
+        self.amount_param(resource_type) + 1
+    }
+
+    pub fn source_rate_param(&self, resource_type: usize) -> Option<usize> {
+        if self.has_source_rate {
+            Some(self.capacity_param(resource_type) + 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn decay_rate_param(&self, resource_type: usize) -> Option<usize> {
+        if self.has_decay_rate {
+            Some(self.capacity_param(resource_type) + 1 + self.has_source_rate as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// How the length of the path between a node and its parent node is measured
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EdgeLengthMode {
+    /// every edge conducts equally, regardless of what lies between the nodes
+    #[default]
+    Uniform = 0,
+    /// the length is one more than the number of symbols on the path between the nodes,
+    ///     so directly adjacent nodes conduct as if unweighted
+    SymbolCount = 1,
+    /// the length is the sum of a designated parameter on every length symbol on the path between the nodes
+    LengthParameter = 2,
+}
+
+/// Weights the conductance of each edge by the inverse of the path length between the parent and child node.
+/// only symbols on the path between the two nodes count, symbols inside side branches are skipped
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EdgeWeighting {
+    pub mode: EdgeLengthMode,
+    /// used by LengthParameter, the symbol which carries a length parameter. such as a stem segment
+    pub length_symbol: i32,
+    /// used by LengthParameter, the index of the length in the parameters of length_symbol
+    pub length_parameter_index: i32,
+    /// edges shorter than this are treated as this long. values below f32::EPSILON are raised to f32::EPSILON,
+    ///     so that zero length edges conduct strongly but finitely
+    pub min_edge_length: f32,
+}
+
+impl EdgeWeighting {
+    /// the length added to the current path by the symbol
+    pub fn length_of(&self, symbol: i32, params: &[f32]) -> f32 {
+        match self.mode {
+            EdgeLengthMode::Uniform => 0.0,
+            EdgeLengthMode::SymbolCount => 1.0,
+            EdgeLengthMode::LengthParameter => {
+                if symbol != self.length_symbol {
+                    return 0.0;
+                }
+                params.get(self.length_parameter_index as usize).copied().unwrap_or(0.0)
+            }
+        }
+    }
+
+    pub fn conductance_for(&self, path_length: f32) -> f32 {
+        let edge_length = match self.mode {
+            EdgeLengthMode::Uniform => return 1.0,
+            EdgeLengthMode::SymbolCount => path_length + 1.0,
+            EdgeLengthMode::LengthParameter => path_length,
+        };
+        1.0 / edge_length.max(self.min_edge_length).max(f32::EPSILON)
+    }
+}
+
+/// How a resource behaves on an edge where only one of the two nodes holds that resource type
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MissingResourceMode {
+    /// the missing slot holds nothing and accepts nothing, so the resource stops at the edge.
+    ///     this is the original behavior
+    #[default]
+    ZeroCapacity = 0,
+    /// the missing slot is an empty reservoir which never fills. the resource drains out of the graph through the edge
+    ZeroAmount = 1,
+    /// the resource skips past nodes which do not hold it, connecting to the nearest ancestor node which does
+    PassThrough = 2,
+}
+
+/// How node capacities limit the flow of resources into a node
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CapacityModel {
+    /// when a node is at or above its capacity, flow towards it is skipped entirely for that edge.
+    ///     nodes may overshoot their capacity within a step. this is the original behavior
+    #[default]
+    HardSkip = 0,
+    /// inflow is scaled down as the receiving node approaches capacity,
+    ///     and clamped so that the node never rises above its capacity
+    Soft = 1,
+}
+
+/// Optional behaviors of a diffusion pass. the default matches the original behavior of diffusion
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DiffusionOptions<'a> {
+    pub node_layout: DiffusionNodeLayout,
+    /// indexed by resource type. biases flow along each edge of the branch tree, proportional to the amount in the donor node.
+    ///     positive values move resources from child to parent (basipetal), negative values from parent to child (acropetal).
+    ///     resource types beyond the end of this list are not actively transported
+    pub directional_transport: &'a [f32],
+    /// reactions run inside every node, in order, once per diffusion step
+    pub reactions: &'a [DiffusionReaction],
+    /// the inputs and outputs of every reaction, indexed by DiffusionReaction::inputs and DiffusionReaction::outputs
+    pub reaction_terms: &'a [ReactionTerm],
+    pub edge_weighting: EdgeWeighting,
+    /// indexed by resource type. the fraction of a resource which decays each diffusion step is 1 - e^(-rate).
+    ///     used for every node unless the node layout carries its own decay rates
+    pub decay_rates: &'a [f32],
+    pub missing_resource_mode: MissingResourceMode,
+    pub capacity_model: CapacityModel,
+}
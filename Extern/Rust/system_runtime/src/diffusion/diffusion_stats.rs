@@ -0,0 +1,191 @@
+﻿use crate::diffusion::extract_graph::DiffusionNode;
+
+/// Mass accounting for a single resource type over one diffusion pass.
+/// all values are totals summed across every node in the graph
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DiffusionResourceStats {
+    /// the amount held in diffusion nodes plus every diffusion amount symbol, before extraction
+    pub total_before_extraction: f32,
+    /// the amount held in the extracted nodes, after diffusion amount symbols are folded into their parent node
+    pub total_after_extraction: f32,
+    /// the amount held in the nodes after all diffusion steps have run
+    pub total_after_diffusion: f32,
+    /// the amount carried by diffusion amount symbols which did not attach to any node.
+    ///     includes amounts with no parent node, and amounts for resource types the parent node does not hold
+    pub dropped_orphan_amount: f32,
+    /// the amount which would have flowed across an edge, but was skipped because the receiving node was at capacity.
+    ///     summed over every diffusion step
+    pub blocked_by_capacity: f32,
+    /// the amount added by node source rates, summed over every diffusion step
+    pub produced_by_sources: f32,
+    /// the amount removed by node source rates, summed over every diffusion step
+    pub consumed_by_sinks: f32,
+    /// the amount created as the output of reactions, summed over every diffusion step
+    pub produced_by_reactions: f32,
+    /// the amount used up as the input of reactions, summed over every diffusion step
+    pub consumed_by_reactions: f32,
+    /// the amount lost to decay, summed over every diffusion step
+    pub decayed: f32,
+    /// the amount lost through edges into nodes without this resource type, when using MissingResourceMode::ZeroAmount.
+    ///     summed over every diffusion step
+    pub drained_to_missing_slots: f32,
+    /// the amount moved out of voxel layers into nodes by the plant-soil exchange
+    pub absorbed_from_voxels: f32,
+    /// the amount moved out of nodes into voxel layers by the plant-soil exchange
+    pub released_to_voxels: f32,
+}
+
+/// An edge between two nodes which hold a different number of resource types
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MismatchedResourceEdge {
+    /// index of the child node symbol in the source string
+    pub node_symbol_index: i32,
+    /// index of the parent node symbol in the source string
+    pub parent_symbol_index: i32,
+    pub node_resource_types: i32,
+    pub parent_resource_types: i32,
+}
+
+/// Diagnostics collected while extracting and diffusing a graph. indexed by resource type
+#[derive(Clone, Debug, Default)]
+pub struct DiffusionStats {
+    pub resources: Vec<DiffusionResourceStats>,
+    /// every edge found during extraction whose nodes disagree on the number of resource types
+    pub mismatched_edges: Vec<MismatchedResourceEdge>,
+}
+
+impl DiffusionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// get the stats for the resource type, growing the list if this resource type has not been seen yet
+    pub fn resource_mut(&mut self, resource_type: usize) -> &mut DiffusionResourceStats {
+        if resource_type >= self.resources.len() {
+            self.resources.resize(resource_type + 1, DiffusionResourceStats::default());
+        }
+        &mut self.resources[resource_type]
+    }
+
+    pub fn record_before_extraction(&mut self, amounts: &[f32]) {
+        for (resource_type, amount) in amounts.iter().enumerate() {
+            self.resource_mut(resource_type).total_before_extraction += amount;
+        }
+    }
+
+    pub fn record_dropped(&mut self, first_resource_type: usize, amounts: &[f32]) {
+        for (offset, amount) in amounts.iter().enumerate() {
+            self.resource_mut(first_resource_type + offset).dropped_orphan_amount += amount;
+        }
+    }
+
+    pub fn record_blocked(&mut self, resource_type: usize, amount: f32) {
+        self.resource_mut(resource_type).blocked_by_capacity += amount.abs();
+    }
+
+    pub fn record_source(&mut self, resource_type: usize, applied_amount: f32) {
+        let resource_stats = self.resource_mut(resource_type);
+        if applied_amount > 0.0 {
+            resource_stats.produced_by_sources += applied_amount;
+        } else {
+            resource_stats.consumed_by_sinks -= applied_amount;
+        }
+    }
+
+    pub fn record_after_extraction(&mut self, nodes: &[DiffusionNode], amounts: &[f32]) {
+        for node in nodes {
+            for (resource_type, amount) in node.get_resource_slice(amounts).iter().enumerate() {
+                self.resource_mut(resource_type).total_after_extraction += amount;
+            }
+        }
+    }
+
+    pub fn record_after_diffusion(&mut self, nodes: &[DiffusionNode], amounts: &[f32]) {
+        for node in nodes {
+            for (resource_type, amount) in node.get_resource_slice(amounts).iter().enumerate() {
+                self.resource_mut(resource_type).total_after_diffusion += amount;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::diffusion_options::{CapacityModel, DiffusionNodeLayout, DiffusionOptions};
+    use crate::diffusion::extract_graph::extract_edges_and_nodes_read_only;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+
+    const NODE: i32 = 1;
+    const AMOUNT: i32 = 2;
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+
+    fn diffuse_with_stats(text: &str, options: &DiffusionOptions, steps: i32) -> DiffusionStats {
+        let symbol_string = parse_symbol_string(text, None).unwrap();
+        let mut stats = DiffusionStats::new();
+        let (mut job, mut amounts) = extract_edges_and_nodes_read_only(
+            &symbol_string.borrow(), NODE, AMOUNT, OPEN, CLOSE, options, Some(&mut stats)).unwrap();
+        job.configure(1.0, options);
+        job.borrowed().diffuse_between(&mut amounts.borrowed_mut(), steps, Some(&mut stats));
+        stats
+    }
+
+    /// every amount which enters or leaves the graph is accounted for
+    fn assert_conserved(stats: &DiffusionStats) {
+        for (resource_type, resource) in stats.resources.iter().enumerate() {
+            let expected_after = resource.total_before_extraction
+                - resource.dropped_orphan_amount
+                + resource.produced_by_sources
+                - resource.consumed_by_sinks
+                + resource.produced_by_reactions
+                - resource.consumed_by_reactions
+                - resource.decayed
+                - resource.drained_to_missing_slots;
+            assert!((resource.total_after_diffusion - expected_after).abs() < 1e-4,
+                "resource {} ends with {} but {} is accounted for: {:?}", resource_type, resource.total_after_diffusion, expected_after, resource);
+        }
+    }
+
+    #[test]
+    fn orphan_amounts_are_dropped_and_accounted_for() {
+        // the first amount has no node before it, and the second carries a resource its node does not hold
+        let stats = diffuse_with_stats("2(3,1) 1(0.5,1,10) -10 2(2,4) 1(0.5,0,10) -11", &DiffusionOptions::default(), 3);
+
+        assert_eq!(stats.resources.len(), 2);
+        assert_eq!(stats.resources[0].total_before_extraction, 6.0);
+        assert_eq!(stats.resources[0].dropped_orphan_amount, 3.0);
+        assert_eq!(stats.resources[0].total_after_extraction, 3.0);
+        assert_eq!(stats.resources[1].total_before_extraction, 5.0);
+        assert_eq!(stats.resources[1].dropped_orphan_amount, 5.0);
+        assert_eq!(stats.resources[1].total_after_extraction, 0.0);
+        assert_conserved(&stats);
+    }
+
+    #[test]
+    fn capped_edges_with_sources_sinks_and_decay_are_conserved() {
+        // the parent produces into a nearly full node, and the first child consumes and pushes into the full parent
+        let text = "1(0.5,9,10,2) -10 1(0.5,20,30,-1) -11 -10 1(0.5,0,1,0) 1(0.5,4,4,0) -11";
+        for capacity_model in [CapacityModel::HardSkip, CapacityModel::Soft] {
+            let options = DiffusionOptions {
+                node_layout: DiffusionNodeLayout {
+                    has_source_rate: true,
+                    has_decay_rate: false,
+                },
+                decay_rates: &[0.05],
+                capacity_model,
+                ..Default::default()
+            };
+            let stats = diffuse_with_stats(text, &options, 6);
+
+            let resource = &stats.resources[0];
+            assert!(resource.blocked_by_capacity > 0.0, "{:?}", capacity_model);
+            assert!(resource.produced_by_sources > 0.0 && resource.produced_by_sources < 12.0, "{:?}", capacity_model);
+            assert_eq!(resource.consumed_by_sinks, 6.0);
+            assert!(resource.decayed > 0.0);
+            assert_conserved(&stats);
+        }
+    }
+}
@@ -1,5 +1,8 @@
-use std::collections::vec_deque::VecDeque;
-use crate::diffusion::diffusion_job::{DiffusionAmountData, DiffusionJob};
+﻿use crate::diffusion::branch_tree::{BranchTree, BranchTreeError};
+use crate::diffusion::diffusion_job::{DiffusionAmountData, DiffusionEdge, DiffusionJob};
+use crate::diffusion::diffusion_options::{CapacityModel, DiffusionNodeLayout, DiffusionOptions, EdgeLengthMode, MissingResourceMode};
+use crate::diffusion::reactions::{DiffusionReaction, ReactionTerm};
+use crate::diffusion::diffusion_stats::{DiffusionStats, MismatchedResourceEdge};
 use crate::interop_extern::data::JaggedIndexing;
 use crate::interop_extern::diffusion::{ LSystemSingleSymbolMatchData};
 
@@ -50,6 +53,19 @@ pub struct SymbolStringMut<'a> {
     pub parameters: &'a mut [f32],
 }
 
+impl<'a> SymbolString<'a> {
+    /// iterate over every symbol as (index, symbol, parameters), borrowing the parameters in place.
+    ///     symbols without parameters, or with a negative parameter index, have empty parameters
+    pub fn iter(&self) -> SymbolStringIter<'a> {
+        SymbolStringIter {
+            symbols: self.symbols,
+            param_indexing: self.param_indexing,
+            parameters: self.parameters,
+            next_index: 0,
+        }
+    }
+}
+
 impl SymbolStringMut<'_> {
     pub fn borrowed(&self) -> SymbolString{
         SymbolString {
@@ -58,8 +74,44 @@ impl SymbolStringMut<'_> {
             parameters: self.parameters
         }
     }
+
+}
+
+pub struct SymbolStringIter<'a> {
+    symbols: &'a [i32],
+    param_indexing: &'a [JaggedIndexing],
+    parameters: &'a [f32],
+    next_index: usize,
+}
+
+impl<'a> Iterator for SymbolStringIter<'a> {
+    type Item = (usize, i32, &'a [f32]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next_index;
+        let symbol = *self.symbols.get(index)?;
+        let param_indexing = self.param_indexing[index];
+        self.next_index += 1;
+        if param_indexing.index < 0 || param_indexing.length == 0 {
+            return Some((index, symbol, &[]));
+        }
+        let start = param_indexing.index as usize;
+        Some((index, symbol, &self.parameters[start..start + param_indexing.length as usize]))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.next_index = self.next_index.saturating_add(n).min(self.symbols.len());
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.symbols.len() - self.next_index;
+        (remaining, Some(remaining))
+    }
 }
 
+impl ExactSizeIterator for SymbolStringIter<'_> {}
+
 impl SymbolStringRead for SymbolStringMut<'_>{
     fn param_for(&self, param_index: JaggedIndexing, index_in_param: usize) -> f32 {
         if index_in_param > param_index.length as usize {
@@ -106,25 +158,58 @@ pub struct DiffusionNode{
     /// will be negative if there is no parent node
     pub parent_node_index: i32,
     
+    /// index of the node symbol in the string the graph was extracted from
+    pub index_in_source: i32,
     pub index_in_target: i32,
     pub target_parameters: JaggedIndexing,
     pub index_in_temp_amount_list: i32,
 
     pub total_resource_types: i32,
-    pub diffusion_constant: f32
+    pub diffusion_constant: f32,
+    /// multiplies the diffusion constant of the edge between this node and its parent. 1 unless edges are weighted
+    pub edge_conductance: f32,
 }
 
 pub struct DiffusionJobOwned {
     pub nodes: Vec<DiffusionNode>,
+    pub extra_edges: Vec<DiffusionEdge>,
     pub node_max_capacities: Vec<f32>,
+    /// parallel to node_max_capacities. empty when the node layout carries no source rates
+    pub node_source_rates: Vec<f32>,
+    /// parallel to node_max_capacities. empty when no resource decays
+    pub node_decay_rates: Vec<f32>,
+    pub node_layout: DiffusionNodeLayout,
+    /// indexed by resource type. see DiffusionOptions::directional_transport
+    pub directional_transport: Vec<f32>,
+    pub missing_resource_mode: MissingResourceMode,
+    pub capacity_model: CapacityModel,
+    pub reactions: Vec<DiffusionReaction>,
+    pub reaction_terms: Vec<ReactionTerm>,
     pub diffusion_global_multiplier: f32,
 }
 
 impl DiffusionJobOwned {
+    /// copy the options which only apply while diffusing into this job
+    pub fn configure(&mut self, diffusion_global_multiplier: f32, options: &DiffusionOptions) {
+        self.diffusion_global_multiplier = diffusion_global_multiplier;
+        self.directional_transport = options.directional_transport.to_vec();
+        self.reactions = options.reactions.to_vec();
+        self.reaction_terms = options.reaction_terms.to_vec();
+    }
+
     pub fn borrowed(&self) -> DiffusionJob{
         DiffusionJob {
             nodes: &self.nodes,
+            extra_edges: &self.extra_edges,
             node_max_capacities: &self.node_max_capacities,
+            node_source_rates: &self.node_source_rates,
+            node_decay_rates: &self.node_decay_rates,
+            node_layout: self.node_layout,
+            directional_transport: &self.directional_transport,
+            missing_resource_mode: self.missing_resource_mode,
+            capacity_model: self.capacity_model,
+            reactions: &self.reactions,
+            reaction_terms: &self.reaction_terms,
             diffusion_global_multiplier: self.diffusion_global_multiplier,
         }
     }
@@ -146,37 +231,66 @@ impl DiffusionAmountDataOwned {
     }
 }
 
-struct BranchEvent {
-    pub _open_branch_symbol_index: i32,
-    pub current_node_parent: usize
-}
-
 pub fn extract_edges_and_nodes_in_place(
     in_place_symbols: &mut SymbolStringMut,
     diffusion_node_symbol: i32,
     diffusion_amount_symbol: i32,
     branch_open_symbol: i32,
     branch_close_symbol: i32,
-) -> (DiffusionJobOwned, DiffusionAmountDataOwned) {
+    options: &DiffusionOptions,
+    stats: Option<&mut DiffusionStats>,
+) -> Result<(DiffusionJobOwned, DiffusionAmountDataOwned), BranchTreeError> {
+
+    extract_edges_and_nodes_read_only(
+        &in_place_symbols.borrowed(),
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        options,
+        stats,
+    )
+}
 
-    let read_borrow = &in_place_symbols.borrowed();
-    let graph_estimate = count_nodes_and_params(read_borrow, diffusion_node_symbol);
+/// extract the graph without modifying the source string, such as for inspecting the graph.
+///     node indexes in the target are the same as in the source
+pub fn extract_edges_and_nodes_read_only(
+    source_symbols: &SymbolString,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    options: &DiffusionOptions,
+    stats: Option<&mut DiffusionStats>,
+) -> Result<(DiffusionJobOwned, DiffusionAmountDataOwned), BranchTreeError> {
 
-    
-    extract_edges_and_nodes(
-        &read_borrow,
+    let graph_estimate = count_nodes_and_params(source_symbols, diffusion_node_symbol, options.node_layout);
+    let branch_tree = BranchTree::new(source_symbols, branch_open_symbol, branch_close_symbol, diffusion_node_symbol)?;
+
+    Ok(extract_edges_and_nodes(
+        source_symbols,
         graph_estimate,
+        &branch_tree,
         diffusion_node_symbol,
         diffusion_amount_symbol,
         branch_open_symbol,
         branch_close_symbol,
+        options,
         |_| {},
         |symbol_index, param_indexing| {
             (symbol_index as i32, param_indexing)
-        }
-    )
+        },
+        stats,
+    ))
 }
 
+/// the graph of a parallel step, and the error found in the branch symbols of the source, if any
+pub type ParallelExtraction = (DiffusionJobOwned, DiffusionAmountDataOwned, Option<BranchTreeError>);
+
+/// extract the graph while writing the diffusion amount symbols of the target.
+///     the target is incomplete until the node parameters are written back into it, so the graph is extracted even when
+///     the branch symbols do not match, reading them as BranchTree::new_tolerant does. the error is returned alongside the graph
+#[allow(clippy::too_many_arguments)]
 pub fn extract_edges_and_nodes_in_parallel<'a>(
     source_symbols: &SymbolString,
     target_symbols: &mut SymbolStringMut,
@@ -185,17 +299,25 @@ pub fn extract_edges_and_nodes_in_parallel<'a>(
     diffusion_amount_symbol: i32,
     branch_open_symbol: i32,
     branch_close_symbol: i32,
-) -> (DiffusionJobOwned, DiffusionAmountDataOwned) {
-    
-    let graph_estimate = count_nodes_and_params(source_symbols, diffusion_node_symbol);
+    options: &DiffusionOptions,
+    stats: Option<&mut DiffusionStats>,
+) -> ParallelExtraction {
     
-    extract_edges_and_nodes(
+    let graph_estimate = count_nodes_and_params(source_symbols, diffusion_node_symbol, options.node_layout);
+    let (branch_tree, branch_error) = match BranchTree::new(source_symbols, branch_open_symbol, branch_close_symbol, diffusion_node_symbol) {
+        Ok(branch_tree) => (branch_tree, None),
+        Err(error) => (BranchTree::new_tolerant(source_symbols, branch_open_symbol, branch_close_symbol, diffusion_node_symbol), Some(error)),
+    };
+
+    let (diffusion_job, diffusion_amounts) = extract_edges_and_nodes(
         source_symbols,
         graph_estimate,
+        &branch_tree,
         diffusion_node_symbol,
         diffusion_amount_symbol,
         branch_open_symbol,
         branch_close_symbol,
+        options,
         |symbol_index| {
 
             let node_singleton = &match_singletons[symbol_index];
@@ -215,8 +337,10 @@ pub fn extract_edges_and_nodes_in_parallel<'a>(
                     length: param_indexing.length,
                 }
             )
-        }
-    )
+        },
+        stats,
+    );
+    (diffusion_job, diffusion_amounts, branch_error)
 }
 
 struct GraphEstimate {
@@ -226,13 +350,14 @@ struct GraphEstimate {
 
 fn count_nodes_and_params(
     source_symbols: &SymbolString,
-    diffusion_node_symbol: i32) -> GraphEstimate {
+    diffusion_node_symbol: i32,
+    node_layout: DiffusionNodeLayout) -> GraphEstimate {
 
     let mut total_resource_need = 0 as u32;
     let mut total_nodes = 0 as u32;
     for (symbol, indexing) in source_symbols.symbols.iter().zip(source_symbols.param_indexing){
         let is_node = (*symbol == diffusion_node_symbol) as u32;
-        total_resource_need += ((indexing.length - 1) / 2) as u32 * is_node;
+        total_resource_need += node_layout.resource_count(indexing.length as usize) as u32 * is_node;
         total_nodes += is_node;
     }
     
@@ -242,65 +367,116 @@ fn count_nodes_and_params(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn extract_edges_and_nodes<'a, FDiffusionAmountCaptured, FGetSymbolAndParamIndex>(
     source_symbols: &SymbolString,
     graph_estimate: GraphEstimate,
+    branch_tree: &BranchTree,
     diffusion_node_symbol: i32,
     diffusion_amount_symbol: i32,
     branch_open_symbol: i32,
     branch_close_symbol: i32,
+    options: &DiffusionOptions,
     mut on_diffusion_amount_captured: FDiffusionAmountCaptured,
-    get_symbol_and_param_index: FGetSymbolAndParamIndex) -> (DiffusionJobOwned, DiffusionAmountDataOwned) 
+    get_symbol_and_param_index: FGetSymbolAndParamIndex,
+    mut stats: Option<&mut DiffusionStats>) -> (DiffusionJobOwned, DiffusionAmountDataOwned)
 where FDiffusionAmountCaptured: FnMut(usize) -> (), FGetSymbolAndParamIndex: Fn(usize, JaggedIndexing) -> (i32, JaggedIndexing){
     
+    let node_layout = options.node_layout;
+    let edge_weighting = options.edge_weighting;
     let mut nodes = Vec::with_capacity(graph_estimate.node_count);
     let mut node_capacities = Vec::with_capacity(graph_estimate.param_count);
     let mut node_amounts = Vec::with_capacity(graph_estimate.param_count);
-    
-    let mut branch_symbol_parent_stack = VecDeque::with_capacity(5);
-    let mut current_node_parent: i32 = -1;
-
-    for symbol_index in 0..source_symbols.len() {
-        let symbol: i32 = source_symbols.symbol_at(symbol_index);
-
+    let mut node_source_rates = Vec::with_capacity(if node_layout.has_source_rate { graph_estimate.param_count } else { 0 });
+    let has_decay = node_layout.has_decay_rate || !options.decay_rates.is_empty();
+    let mut node_decay_rates = Vec::with_capacity(if has_decay { graph_estimate.param_count } else { 0 });
+
+    // the index of the node nearest before the symbol, along the path back to the root
+    let node_before = |nodes: &[DiffusionNode], symbol_index: usize| -> i32 {
+        branch_tree.previous_node(symbol_index)
+            .and_then(|node_symbol_index| nodes.binary_search_by_key(&(node_symbol_index as i32), |node| node.index_in_source).ok())
+            .map_or(-1, |node_index| node_index as i32)
+    };
+    // indexed by branch, the length of the path walked along the branch since the last node before it
+    let mut branch_path_lengths = vec![0.0f32; branch_tree.branches().len()];
+
+    for (symbol_index, symbol, params_slice) in source_symbols.iter() {
+        let branch_id = branch_tree.branch_of(symbol_index);
         if symbol == diffusion_node_symbol {
-            let parent_node_index = current_node_parent;
-            current_node_parent = nodes.len() as i32;
+            let parent_node_index = node_before(&nodes, symbol_index);
 
             let node_params = source_symbols.param_indexing[symbol_index];
-            let params_slice = source_symbols.take_slice(node_params);
             let (symbol_in_target, param_in_target) = get_symbol_and_param_index(symbol_index, node_params);
 
             let new_node = DiffusionNode {
                 parent_node_index,
                 
+                index_in_source: symbol_index as i32,
                 index_in_target: symbol_in_target,
                 target_parameters: param_in_target,
                 index_in_temp_amount_list: node_amounts.len() as i32,
 
-                total_resource_types: ((param_in_target.length - 1) / 2) as i32,
+                total_resource_types: node_layout.resource_count(param_in_target.length as usize) as i32,
                 diffusion_constant: params_slice[0],
+                edge_conductance: edge_weighting.conductance_for(branch_path_lengths[branch_id]),
             };
+            branch_path_lengths[branch_id] = 0.0;
 
             for i in 0..new_node.total_resource_types as usize {
-                node_amounts   .push(params_slice[i * 2 + 1]);
-                node_capacities.push(params_slice[i * 2 + 1 + 1]);
+                node_amounts   .push(params_slice[node_layout.amount_param(i)]);
+                node_capacities.push(params_slice[node_layout.capacity_param(i)]);
+                if let Some(source_rate_param) = node_layout.source_rate_param(i) {
+                    node_source_rates.push(params_slice[source_rate_param]);
+                }
+                if let Some(decay_rate_param) = node_layout.decay_rate_param(i) {
+                    node_decay_rates.push(params_slice[decay_rate_param]);
+                } else if has_decay {
+                    node_decay_rates.push(options.decay_rates.get(i).copied().unwrap_or(0.0));
+                }
+            }
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.record_before_extraction(new_node.get_resource_slice(&node_amounts));
+                if parent_node_index >= 0 {
+                    let parent_node: &DiffusionNode = &nodes[parent_node_index as usize];
+                    if parent_node.total_resource_types != new_node.total_resource_types {
+                        stats.mismatched_edges.push(MismatchedResourceEdge {
+                            node_symbol_index: symbol_index as i32,
+                            parent_symbol_index: parent_node.index_in_source,
+                            node_resource_types: new_node.total_resource_types,
+                            parent_resource_types: parent_node.total_resource_types,
+                        });
+                    }
+                }
             }
             
             nodes.push(new_node);
             
         } else if symbol == diffusion_amount_symbol {
-            let source_slice = source_symbols.take_param_slice(symbol_index);
+            let source_slice = params_slice;
             if source_slice.len() == 0 {
                 continue;
             }
 
             on_diffusion_amount_captured(symbol_index);
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.record_before_extraction(source_slice);
+            }
 
+            let current_node_parent = node_before(&nodes, symbol_index);
             if current_node_parent < 0 {
+                if let Some(stats) = stats.as_deref_mut() {
+                    stats.record_dropped(0, source_slice);
+                }
                 continue;
             }
             let modified_node = &mut nodes[current_node_parent as usize];
+            if let Some(stats) = stats.as_deref_mut() {
+                // amounts beyond the resources held by the parent node are truncated away below
+                let held_resources = modified_node.total_resource_types as usize;
+                if source_slice.len() > held_resources {
+                    stats.record_dropped(held_resources, &source_slice[held_resources..]);
+                }
+            }
             
             let target_start_index = modified_node.index_in_temp_amount_list as usize;
             let target_end_index = target_start_index + modified_node.total_resource_types as usize;
@@ -310,23 +486,34 @@ where FDiffusionAmountCaptured: FnMut(usize) -> (), FGetSymbolAndParamIndex: Fn(
                 *target_amount += amount_in_source;
             }
         } else if symbol == branch_open_symbol {
-            branch_symbol_parent_stack.push_back(BranchEvent {
-                _open_branch_symbol_index: symbol_index as i32,
-                current_node_parent: current_node_parent as usize,
-            });
+            // a branch continues the path of the branch around it
+            let parent_branch = branch_tree.branch(branch_id).parent_branch as usize;
+            branch_path_lengths[branch_id] = branch_path_lengths[parent_branch];
         } else if symbol == branch_close_symbol {
-            if let Some(last_branch_state) = branch_symbol_parent_stack.pop_back() {
-                current_node_parent = last_branch_state.current_node_parent as i32;
-            }
+            continue;
+        } else if edge_weighting.mode != EdgeLengthMode::Uniform {
+            branch_path_lengths[branch_id] += edge_weighting.length_of(symbol, params_slice);
         }
     }
 
     let amount_len = node_amounts.len();
+    if let Some(stats) = stats {
+        stats.record_after_extraction(&nodes, &node_amounts);
+    }
 
     (
         DiffusionJobOwned {
             nodes,
+            extra_edges: Vec::new(),
             node_max_capacities: node_capacities,
+            node_source_rates,
+            node_decay_rates,
+            node_layout,
+            directional_transport: Vec::new(),
+            missing_resource_mode: options.missing_resource_mode,
+            capacity_model: options.capacity_model,
+            reactions: Vec::new(),
+            reaction_terms: Vec::new(),
             diffusion_global_multiplier: 1.0,
         },
         DiffusionAmountDataOwned {
@@ -335,4 +522,59 @@ where FDiffusionAmountCaptured: FnMut(usize) -> (), FGetSymbolAndParamIndex: Fn(
             latest_in_a: true,
         }
     )
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol_string<'a>(symbols: &'a [i32], param_indexing: &'a [JaggedIndexing], parameters: &'a [f32]) -> SymbolString<'a> {
+        SymbolString { symbols, param_indexing, parameters }
+    }
+
+    #[test]
+    fn iter_borrows_parameters_by_indexing() {
+        let parameters = [1.0, 2.0, 3.0];
+        let param_indexing = [
+            JaggedIndexing { index: 1, length: 2 },
+            JaggedIndexing { index: 0, length: 1 },
+        ];
+        let symbols = symbol_string(&[10, 11], &param_indexing, &parameters);
+
+        let items: Vec<_> = symbols.iter().collect();
+        assert_eq!(items, vec![(0, 10, &[2.0, 3.0][..]), (1, 11, &[1.0][..])]);
+    }
+
+    #[test]
+    fn iter_reads_negative_index_and_zero_length_as_empty() {
+        let parameters = [1.0];
+        let param_indexing = [
+            JaggedIndexing { index: -1, length: 3 },
+            JaggedIndexing { index: 5, length: 0 },
+            JaggedIndexing { index: 0, length: 1 },
+        ];
+        let symbols = symbol_string(&[10, 11, 12], &param_indexing, &parameters);
+
+        let items: Vec<_> = symbols.iter().collect();
+        assert_eq!(items, vec![(0, 10, &[][..]), (1, 11, &[][..]), (2, 12, &[1.0][..])]);
+    }
+
+    #[test]
+    fn iter_nth_skips_symbols() {
+        let parameters = [1.0, 2.0, 3.0];
+        let param_indexing = [
+            JaggedIndexing { index: 0, length: 1 },
+            JaggedIndexing { index: 1, length: 1 },
+            JaggedIndexing { index: 2, length: 1 },
+        ];
+        let symbols = symbol_string(&[10, 11, 12], &param_indexing, &parameters);
+
+        let mut iter = symbols.iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.nth(1), Some((1, 11, &[2.0][..])));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.nth(4), None);
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+    }
+}
@@ -0,0 +1,198 @@
+use std::ops::Range;
+use crate::diffusion::diffusion_job::{DiffusionEdge, DiffusionJob};
+use crate::diffusion::extract_graph::{DiffusionAmountDataOwned, DiffusionJobOwned};
+
+/// A connection between diffusion nodes in two different symbol strings, such as a graft or a shared root network
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ForestEdge {
+    pub string_a_index: i32,
+    /// index of a diffusion node symbol inside string A
+    pub node_symbol_a: i32,
+    pub string_b_index: i32,
+    /// index of a diffusion node symbol inside string B
+    pub node_symbol_b: i32,
+    /// multiplies the diffusion constant across this edge
+    pub edge_conductance: f32,
+}
+
+/// The diffusion graphs of several symbol strings combined into a single job
+pub struct DiffusionForest {
+    pub job: DiffusionJobOwned,
+    pub amounts: DiffusionAmountDataOwned,
+    /// the range of nodes in the combined job which were extracted from each string
+    pub node_ranges: Vec<Range<usize>>,
+}
+
+/// The parts of a forest which were left out of a forest diffusion step
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ForestReport {
+    /// indexes of the strings whose branch symbols do not match
+    pub unbalanced_strings: Vec<usize>,
+    /// indexes of the forest edges which do not point at a diffusion node
+    pub unconnected_edges: Vec<usize>,
+}
+
+impl ForestReport {
+    pub fn is_complete(&self) -> bool {
+        self.unbalanced_strings.is_empty() && self.unconnected_edges.is_empty()
+    }
+}
+
+/// concatenate the graphs extracted from several strings. all graphs must have been extracted with the same options.
+///     a string without a graph gets an empty node range, so no forest edge can connect to it.
+/// returns None when there are no graphs
+pub fn merge_graphs(graphs: Vec<Option<(DiffusionJobOwned, DiffusionAmountDataOwned)>>) -> Option<DiffusionForest> {
+    let first_graph = graphs.iter().position(Option::is_some)?;
+    let mut graphs = graphs.into_iter().skip(first_graph);
+    let (mut job, mut amounts) = graphs.next().flatten()?;
+    let mut node_ranges = vec![0..0; first_graph];
+    node_ranges.push(0..job.nodes.len());
+
+    for next_graph in graphs {
+        let node_offset = job.nodes.len() as i32;
+        let Some((next_job, next_amounts)) = next_graph else {
+            node_ranges.push(job.nodes.len()..job.nodes.len());
+            continue;
+        };
+        let amount_offset = amounts.node_amount_list_a.len() as i32;
+
+        for mut node in next_job.nodes {
+            if node.parent_node_index >= 0 {
+                node.parent_node_index += node_offset;
+            }
+            node.index_in_temp_amount_list += amount_offset;
+            job.nodes.push(node);
+        }
+        for mut edge in next_job.extra_edges {
+            edge.node_a_index += node_offset;
+            edge.node_b_index += node_offset;
+            job.extra_edges.push(edge);
+        }
+        job.node_max_capacities.extend(next_job.node_max_capacities);
+        job.node_source_rates.extend(next_job.node_source_rates);
+        job.node_decay_rates.extend(next_job.node_decay_rates);
+
+        // freshly extracted graphs always hold their amounts in list a
+        amounts.node_amount_list_a.extend(next_amounts.node_amount_list_a);
+        node_ranges.push(node_offset as usize..job.nodes.len());
+    }
+
+    amounts.node_amount_list_b = vec![0.0; amounts.node_amount_list_a.len()];
+
+    Some(DiffusionForest {
+        job,
+        amounts,
+        node_ranges,
+    })
+}
+
+impl DiffusionForest {
+    /// find the index in the combined job of the node at the symbol index in the string
+    pub fn find_node(&self, string_index: i32, node_symbol_index: i32) -> Option<usize> {
+        let node_range = self.node_ranges.get(usize::try_from(string_index).ok()?)?.clone();
+        let string_nodes = &self.job.nodes[node_range.clone()];
+        // nodes are extracted in the order they appear in the string
+        let index_in_string = string_nodes
+            .binary_search_by_key(&node_symbol_index, |node| node.index_in_source)
+            .ok()?;
+        Some(node_range.start + index_in_string)
+    }
+
+    /// add an extra edge for every forest edge. returns the indexes of the edges which do not point at a diffusion node,
+    /// those edges are skipped
+    pub fn connect(&mut self, forest_edges: &[ForestEdge]) -> Vec<usize> {
+        let mut unconnected_edges = Vec::new();
+        for (edge_index, forest_edge) in forest_edges.iter().enumerate() {
+            let node_a = self.find_node(forest_edge.string_a_index, forest_edge.node_symbol_a);
+            let node_b = self.find_node(forest_edge.string_b_index, forest_edge.node_symbol_b);
+            match (node_a, node_b) {
+                (Some(node_a), Some(node_b)) => self.job.extra_edges.push(DiffusionEdge {
+                    node_a_index: node_a as i32,
+                    node_b_index: node_b as i32,
+                    edge_conductance: forest_edge.edge_conductance,
+                }),
+                _ => unconnected_edges.push(edge_index),
+            }
+        }
+        unconnected_edges
+    }
+}
+
+impl<'a> DiffusionJob<'a> {
+    /// a view of this job which only includes a range of its nodes, and none of the extra edges.
+    ///     used to apply the results of a forest back into the string each node came from
+    pub fn subset(self, node_range: Range<usize>) -> DiffusionJob<'a> {
+        DiffusionJob {
+            nodes: &self.nodes[node_range],
+            extra_edges: &[],
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::diffusion_options::DiffusionOptions;
+    use crate::diffusion::extract_graph::extract_edges_and_nodes_read_only;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+
+    const NODE: i32 = 1;
+    const AMOUNT: i32 = 2;
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+
+    fn graph(text: &str) -> Option<(DiffusionJobOwned, DiffusionAmountDataOwned)> {
+        let symbol_string = parse_symbol_string(text, None).unwrap();
+        extract_edges_and_nodes_read_only(
+            &symbol_string.borrow(), NODE, AMOUNT, OPEN, CLOSE, &DiffusionOptions::default(), None).ok()
+    }
+
+    fn edge(string_a_index: i32, node_symbol_a: i32, string_b_index: i32, node_symbol_b: i32) -> ForestEdge {
+        ForestEdge { string_a_index, node_symbol_a, string_b_index, node_symbol_b, edge_conductance: 1.0 }
+    }
+
+    #[test]
+    fn strings_without_a_graph_keep_an_empty_node_range() {
+        let unbalanced = graph("1(0.5,4,10) -11");
+        assert!(unbalanced.is_none());
+        let forest = merge_graphs(vec![
+            None,
+            graph("1(0.5,4,10) -10 1(0.5,0,10) -11"),
+            unbalanced,
+            graph("1(0.5,0,10)"),
+        ]).unwrap();
+
+        assert_eq!(forest.node_ranges, vec![0..0, 0..2, 2..2, 2..3]);
+        assert_eq!(forest.amounts.node_amount_list_a, vec![4.0, 0.0, 0.0]);
+        assert_eq!(forest.job.nodes[1].parent_node_index, 0);
+        assert_eq!(forest.job.nodes[2].parent_node_index, -1);
+        assert_eq!(forest.find_node(1, 2), Some(1));
+        assert_eq!(forest.find_node(3, 0), Some(2));
+        assert_eq!(forest.find_node(2, 0), None);
+
+        assert!(merge_graphs(vec![None, None]).is_none());
+    }
+
+    #[test]
+    fn connect_reports_each_unconnected_edge() {
+        let mut forest = merge_graphs(vec![
+            graph("1(0.5,4,10)"),
+            None,
+            graph("0 1(0.5,0,10)"),
+        ]).unwrap();
+
+        let unconnected_edges = forest.connect(&[
+            edge(0, 0, 2, 1),
+            edge(0, 0, 1, 0),
+            edge(0, 0, 2, 0),
+            edge(5, 0, 0, 0),
+            edge(2, 1, 0, 0),
+        ]);
+        assert_eq!(unconnected_edges, vec![1, 2, 3]);
+        assert_eq!(forest.job.extra_edges.len(), 2);
+        assert_eq!((forest.job.extra_edges[0].node_a_index, forest.job.extra_edges[0].node_b_index), (0, 1));
+        assert_eq!((forest.job.extra_edges[1].node_a_index, forest.job.extra_edges[1].node_b_index), (1, 0));
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+use crate::diffusion::extract_graph::{DiffusionJobOwned, DiffusionNode};
+
+/// The text format used when exporting a diffusion graph
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DiffusionGraphFormat {
+    /// a graphviz digraph, with an edge from each child node to its parent
+    #[default]
+    Dot = 0,
+    /// see diffusion_graph_to_json for the schema
+    Json = 1,
+}
+
+pub fn export_diffusion_graph(job: &DiffusionJobOwned, amounts: &[f32], format: DiffusionGraphFormat) -> String {
+    match format {
+        DiffusionGraphFormat::Dot => diffusion_graph_to_dot(job, amounts),
+        DiffusionGraphFormat::Json => diffusion_graph_to_json(job, amounts),
+    }
+}
+
+/// one vertex per node labeled with its indexes, diffusion constant and amount / capacity of each resource.
+///     solid edges point from child to parent, dashed edges are extra edges such as forest edges
+pub fn diffusion_graph_to_dot(job: &DiffusionJobOwned, amounts: &[f32]) -> String {
+    let mut dot = String::new();
+    dot.push_str("digraph diffusion {\n");
+    dot.push_str("    node [shape=box];\n");
+    for (node_index, node) in job.nodes.iter().enumerate() {
+        write!(dot, "    n{} [label=\"node {}\\nsource {} target {}\\nD={}",
+            node_index, node_index, node.index_in_source, node.index_in_target, node.diffusion_constant).unwrap();
+        for resource_type in 0..node.total_resource_types as usize {
+            let resource_index = node.index_in_temp_amount_list as usize + resource_type;
+            write!(dot, "\\nr{}: {} / {}", resource_type, amounts[resource_index], job.node_max_capacities[resource_index]).unwrap();
+        }
+        dot.push_str("\"];\n");
+    }
+    for (node_index, node) in job.nodes.iter().enumerate() {
+        if node.parent_node_index >= 0 {
+            writeln!(dot, "    n{} -> n{} [label=\"{}\"];", node_index, node.parent_node_index, node.edge_conductance).unwrap();
+        }
+    }
+    for edge in job.extra_edges.iter() {
+        writeln!(dot, "    n{} -> n{} [style=dashed, dir=none, label=\"{}\"];", edge.node_a_index, edge.node_b_index, edge.edge_conductance).unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// ```json
+/// {
+///   "nodes": [{
+///     "index": 0, "parent": -1, "index_in_source": 3, "index_in_target": 3,
+///     "diffusion_constant": 0.5, "edge_conductance": 1,
+///     "resources": [{"amount": 2, "capacity": 10, "source_rate": 0, "decay_rate": 0}]
+///   }],
+///   "extra_edges": [{"node_a": 0, "node_b": 4, "edge_conductance": 1}]
+/// }
+/// ```
+/// source_rate and decay_rate are only present when the graph carries them. non finite numbers are written as null
+pub fn diffusion_graph_to_json(job: &DiffusionJobOwned, amounts: &[f32]) -> String {
+    let mut json = String::new();
+    json.push_str("{\"nodes\":[");
+    for (node_index, node) in job.nodes.iter().enumerate() {
+        if node_index > 0 {
+            json.push(',');
+        }
+        write_node_json(&mut json, job, amounts, node_index, node);
+    }
+    json.push_str("],\"extra_edges\":[");
+    for (edge_index, edge) in job.extra_edges.iter().enumerate() {
+        if edge_index > 0 {
+            json.push(',');
+        }
+        write!(json, "{{\"node_a\":{},\"node_b\":{},\"edge_conductance\":", edge.node_a_index, edge.node_b_index).unwrap();
+        write_json_number(&mut json, edge.edge_conductance);
+        json.push('}');
+    }
+    json.push_str("]}");
+    json
+}
+
+fn write_node_json(json: &mut String, job: &DiffusionJobOwned, amounts: &[f32], node_index: usize, node: &DiffusionNode) {
+    write!(json, "{{\"index\":{},\"parent\":{},\"index_in_source\":{},\"index_in_target\":{},\"diffusion_constant\":",
+        node_index, node.parent_node_index, node.index_in_source, node.index_in_target).unwrap();
+    write_json_number(json, node.diffusion_constant);
+    json.push_str(",\"edge_conductance\":");
+    write_json_number(json, node.edge_conductance);
+    json.push_str(",\"resources\":[");
+    for resource_type in 0..node.total_resource_types as usize {
+        if resource_type > 0 {
+            json.push(',');
+        }
+        let resource_index = node.index_in_temp_amount_list as usize + resource_type;
+        json.push_str("{\"amount\":");
+        write_json_number(json, amounts[resource_index]);
+        json.push_str(",\"capacity\":");
+        write_json_number(json, job.node_max_capacities[resource_index]);
+        if let Some(source_rate) = job.node_source_rates.get(resource_index) {
+            json.push_str(",\"source_rate\":");
+            write_json_number(json, *source_rate);
+        }
+        if let Some(decay_rate) = job.node_decay_rates.get(resource_index) {
+            json.push_str(",\"decay_rate\":");
+            write_json_number(json, *decay_rate);
+        }
+        json.push('}');
+    }
+    json.push_str("]}");
+}
+
+fn write_json_number(json: &mut String, value: f32) {
+    if value.is_finite() {
+        write!(json, "{}", value).unwrap();
+    } else {
+        json.push_str("null");
+    }
+}
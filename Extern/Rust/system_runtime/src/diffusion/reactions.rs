@@ -0,0 +1,211 @@
+use crate::diffusion::diffusion_stats::DiffusionStats;
+use crate::diffusion::extract_graph::DiffusionNode;
+use crate::interop_extern::data::{IndexesIn, JaggedIndexing};
+
+/// One side of a reaction: the resource type involved, and how much of it is consumed or produced per unit of reaction
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ReactionTerm {
+    pub resource_type: i32,
+    pub coefficient: f32,
+}
+
+/// Converts resources inside a single diffusion node, such as 2 water + 1 light -> 1 sugar.
+/// runs once per node in every diffusion step, before resources diffuse across edges
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct DiffusionReaction {
+    /// indexing into the reaction term list
+    pub inputs: JaggedIndexing,
+    /// indexing into the reaction term list
+    pub outputs: JaggedIndexing,
+    /// the most units of this reaction which can run in one node per diffusion step
+    pub rate: f32,
+    /// when positive, the reaction will not raise any output above this amount.
+    ///     outputs are always limited by the capacity of the node
+    pub output_cap: f32,
+}
+
+impl DiffusionReaction {
+    /// find how many units of this reaction can run, limited by the rate, the inputs available and the room left for outputs.
+    /// returns None if the node does not hold every resource type this reaction uses
+    fn units_available(
+        &self,
+        terms: &[ReactionTerm],
+        node_amounts: &[f32],
+        node_capacities: &[f32]) -> Option<f32> {
+        let mut units = self.rate.max(0.0);
+        for input in self.inputs.to_slice_ref(terms) {
+            let amount = *node_amounts.get(input.resource_type as usize)?;
+            if input.coefficient > 0.0 {
+                units = units.min(amount.max(0.0) / input.coefficient);
+            }
+        }
+        for output in self.outputs.to_slice_ref(terms) {
+            let amount = *node_amounts.get(output.resource_type as usize)?;
+            let mut limit = node_capacities[output.resource_type as usize];
+            if self.output_cap > 0.0 {
+                limit = limit.min(self.output_cap);
+            }
+            if output.coefficient > 0.0 {
+                units = units.min((limit - amount).max(0.0) / output.coefficient);
+            }
+        }
+        Some(units)
+    }
+}
+
+/// true when the inputs and outputs of every reaction lie inside the terms. a negative index is read as no terms
+pub fn reactions_index_terms(reactions: &[DiffusionReaction], terms: &[ReactionTerm]) -> bool {
+    let indexes_terms = |indexing: JaggedIndexing| indexing.index < 0 || indexing.index as usize + indexing.length as usize <= terms.len();
+    reactions.iter().all(|reaction| indexes_terms(reaction.inputs) && indexes_terms(reaction.outputs))
+}
+
+/// run every reaction in order against the amounts held by the node. each reaction sees the results of the reactions before it
+pub fn apply_reactions(
+    reactions: &[DiffusionReaction],
+    terms: &[ReactionTerm],
+    node: &DiffusionNode,
+    node_max_capacities: &[f32],
+    amounts: &mut [f32],
+    mut stats: Option<&mut DiffusionStats>) {
+    let node_capacities = node.get_resource_slice(node_max_capacities);
+    let node_amounts = node.get_resource_slice_mut(amounts);
+
+    for reaction in reactions {
+        let units = match reaction.units_available(terms, node_amounts, node_capacities) {
+            Some(units) if units > 0.0 => units,
+            _ => continue,
+        };
+
+        for input in reaction.inputs.to_slice_ref(terms) {
+            let consumed = units * input.coefficient;
+            node_amounts[input.resource_type as usize] -= consumed;
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.resource_mut(input.resource_type as usize).consumed_by_reactions += consumed;
+            }
+        }
+        for output in reaction.outputs.to_slice_ref(terms) {
+            let produced = units * output.coefficient;
+            node_amounts[output.resource_type as usize] += produced;
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.resource_mut(output.resource_type as usize).produced_by_reactions += produced;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WATER: i32 = 0;
+    const LIGHT: i32 = 1;
+    const SUGAR: i32 = 2;
+
+    /// 2 water + 1 light -> 1 sugar
+    const PHOTOSYNTHESIS_TERMS: [ReactionTerm; 3] = [
+        ReactionTerm { resource_type: WATER, coefficient: 2.0 },
+        ReactionTerm { resource_type: LIGHT, coefficient: 1.0 },
+        ReactionTerm { resource_type: SUGAR, coefficient: 1.0 },
+    ];
+
+    fn photosynthesis(rate: f32, output_cap: f32) -> DiffusionReaction {
+        DiffusionReaction {
+            inputs: JaggedIndexing { index: 0, length: 2 },
+            outputs: JaggedIndexing { index: 2, length: 1 },
+            rate,
+            output_cap,
+        }
+    }
+
+    fn single_node() -> DiffusionNode {
+        DiffusionNode {
+            parent_node_index: -1,
+            index_in_source: 0,
+            index_in_target: 0,
+            target_parameters: JaggedIndexing { index: 0, length: 7 },
+            index_in_temp_amount_list: 0,
+            total_resource_types: 3,
+            diffusion_constant: 0.0,
+            edge_conductance: 1.0,
+        }
+    }
+
+    fn react(reaction: DiffusionReaction, amounts: &mut [f32]) -> DiffusionStats {
+        let mut stats = DiffusionStats::new();
+        apply_reactions(
+            &[reaction],
+            &PHOTOSYNTHESIS_TERMS,
+            &single_node(),
+            &[100.0, 100.0, 100.0],
+            amounts,
+            Some(&mut stats));
+        stats
+    }
+
+    #[test]
+    fn converts_inputs_to_outputs_by_coefficient() {
+        let mut amounts = [10.0, 10.0, 0.0];
+        let stats = react(photosynthesis(1.5, 0.0), &mut amounts);
+
+        assert_eq!(amounts, [7.0, 8.5, 1.5]);
+        assert_eq!(stats.resources[WATER as usize].consumed_by_reactions, 3.0);
+        assert_eq!(stats.resources[LIGHT as usize].consumed_by_reactions, 1.5);
+        assert_eq!(stats.resources[SUGAR as usize].produced_by_reactions, 1.5);
+    }
+
+    #[test]
+    fn output_cap_limits_units() {
+        let mut amounts = [10.0, 10.0, 0.25];
+        react(photosynthesis(5.0, 1.0), &mut amounts);
+
+        assert_eq!(amounts, [8.5, 9.25, 1.0]);
+    }
+
+    #[test]
+    fn node_capacity_limits_units() {
+        let mut amounts = [10.0, 10.0, 99.5];
+        react(photosynthesis(5.0, 0.0), &mut amounts);
+
+        assert_eq!(amounts, [9.0, 9.5, 100.0]);
+    }
+
+    #[test]
+    fn limiting_reactant_bounds_units() {
+        let mut amounts = [3.0, 10.0, 0.0];
+        react(photosynthesis(5.0, 0.0), &mut amounts);
+
+        assert_eq!(amounts, [0.0, 8.5, 1.5]);
+    }
+
+    #[test]
+    fn missing_resource_type_skips_reaction() {
+        let mut node_amounts = [10.0, 10.0];
+        let mut node = single_node();
+        node.total_resource_types = 2;
+        apply_reactions(
+            &[photosynthesis(1.0, 0.0)],
+            &PHOTOSYNTHESIS_TERMS,
+            &node,
+            &[100.0, 100.0],
+            &mut node_amounts,
+            None);
+
+        assert_eq!(node_amounts, [10.0, 10.0]);
+    }
+
+    #[test]
+    fn reactions_must_index_inside_the_terms() {
+        assert!(reactions_index_terms(&[photosynthesis(1.0, 0.0)], &PHOTOSYNTHESIS_TERMS));
+
+        let mut empty_outputs = photosynthesis(1.0, 0.0);
+        empty_outputs.outputs = JaggedIndexing { index: -1, length: 4 };
+        assert!(reactions_index_terms(&[empty_outputs], &PHOTOSYNTHESIS_TERMS));
+
+        let mut past_the_end = photosynthesis(1.0, 0.0);
+        past_the_end.outputs = JaggedIndexing { index: 2, length: 2 };
+        assert!(!reactions_index_terms(&[photosynthesis(1.0, 0.0), past_the_end], &PHOTOSYNTHESIS_TERMS));
+        assert!(!reactions_index_terms(&[photosynthesis(1.0, 0.0)], &PHOTOSYNTHESIS_TERMS[..2]));
+    }
+}
@@ -1,5 +1,5 @@
-﻿use crate::diffusion::extract_graph::{SymbolString, SymbolStringMut, SymbolStringRead};
-use crate::interop_extern::data::{IndexesIn, JaggedIndexing};
+﻿use crate::diffusion::extract_graph::{SymbolString, SymbolStringMut};
+use crate::interop_extern::data::JaggedIndexing;
 
 pub struct SymbolElement<'a>{
     pub symbol: i32,
@@ -12,18 +12,14 @@ pub struct SymbolElementOwned{
     pub params: Vec<f32>,
 }
 
+/// collect every symbol into a list. prefer SymbolString::iter, which borrows in place without allocating
 pub fn to_elements<'a>(symbol_string: &'a SymbolString) -> Vec<SymbolElement<'a>>{
-    let mut elements = Vec::with_capacity(symbol_string.symbols.len());
-    for symbol_index in 0..symbol_string.len(){
-        let symbol = symbol_string.symbols[symbol_index];
-        let param_indexing = symbol_string.param_indexing[symbol_index];
-        let params = param_indexing.to_slice_ref(symbol_string.parameters);
-        elements.push(SymbolElement{
+    symbol_string.iter()
+        .map(|(_, symbol, params)| SymbolElement {
             symbol,
             params,
-        });
-    }
-    elements
+        })
+        .collect()
 }
 
 pub struct SymbolStringOwned {
@@ -0,0 +1,442 @@
+use crate::diffusion::extract_graph::SymbolString;
+use crate::diffusion::symbol_element_remap::SymbolStringOwned;
+use crate::diffusion::symbol_string_text::SymbolNames;
+use crate::interop_extern::data::JaggedIndexing;
+
+pub const SYMBOL_STRING_MAGIC: [u8; 4] = *b"LSYS";
+pub const SYMBOL_STRING_FORMAT_VERSION: u16 = 1;
+
+const FLAG_HAS_METADATA: u16 = 1 << 0;
+const FLAG_HAS_SYMBOL_TABLE: u16 = 1 << 1;
+const KNOWN_FLAGS: u16 = FLAG_HAS_METADATA | FLAG_HAS_SYMBOL_TABLE;
+
+const HEADER_SIZE: usize = 4 + 2 + 2 + 4 + 4;
+const METADATA_SIZE: usize = 4 + 4;
+const SYMBOL_TABLE_HEADER_SIZE: usize = 4 + 4;
+const PARAM_INDEXING_SIZE: usize = 4 + 2;
+const SYMBOL_TABLE_ENTRY_SIZE: usize = 4 + 2;
+const CHECKSUM_SIZE: usize = 4;
+
+/// Optional state of the L-system saved alongside the symbol string
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolStringMetadata {
+    pub step_count: u32,
+    pub seed: u32,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymbolStringReadError {
+    None = 0,
+    /// the data ends before the end of the string, or continues past it
+    Truncated = 1,
+    /// the data does not start with SYMBOL_STRING_MAGIC
+    BadMagic = 2,
+    /// written by a newer version of the format, or uses flags this version does not know
+    UnsupportedVersion = 3,
+    ChecksumMismatch = 4,
+    /// a symbol's parameters lie outside of the parameter list
+    InvalidParameterIndexing = 5,
+    /// a symbol name is not valid utf-8, or the name lengths do not add up
+    InvalidSymbolTable = 6,
+    /// the caller allocated buffers are too small to hold the string
+    TargetTooSmall = 7,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymbolStringWriteError {
+    None = 0,
+    /// a symbol name is longer than u16::MAX bytes
+    NameTooLong = 1,
+    /// the string, its parameters or the symbol table have more than i32::MAX entries
+    StringTooLong = 2,
+    /// the caller allocated buffer is too small to hold the saved data
+    TargetTooSmall = 3,
+    /// the symbol table does not have a name for every symbol, a name indexes past the name bytes, or a name is not valid utf-8
+    InvalidSymbolTable = 4,
+}
+
+/// Everything read back from a saved symbol string
+pub struct SerializedSymbolString {
+    pub symbol_string: SymbolStringOwned,
+    pub metadata: Option<SymbolStringMetadata>,
+    pub names: Option<SymbolNames>,
+}
+
+/// The sizes of a saved symbol string, read from the header without reading the body
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SerializedSymbolStringSizes {
+    pub symbol_count: i32,
+    pub parameter_count: i32,
+    pub has_metadata: bool,
+    pub has_symbol_table: bool,
+    pub symbol_table_entries: i32,
+    pub symbol_table_name_bytes: i32,
+}
+
+impl SerializedSymbolStringSizes {
+    fn total_bytes(&self) -> usize {
+        let mut total = HEADER_SIZE
+            + self.symbol_count as usize * (4 + PARAM_INDEXING_SIZE)
+            + self.parameter_count as usize * 4
+            + CHECKSUM_SIZE;
+        if self.has_metadata {
+            total += METADATA_SIZE;
+        }
+        if self.has_symbol_table {
+            total += SYMBOL_TABLE_HEADER_SIZE
+                + self.symbol_table_entries as usize * SYMBOL_TABLE_ENTRY_SIZE
+                + self.symbol_table_name_bytes as usize;
+        }
+        total
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in bytes {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+/// A compact little-endian format for saving symbol strings. diffusion state is held in the parameters of the string,
+///     so saving the string also saves the diffusion buffers.
+///
+/// header: magic "LSYS", version u16, flags u16, symbol count u32, parameter count u32,
+///     then the metadata (step count u32, seed u32) if flagged,
+///     then the symbol table entry count u32 and total name bytes u32 if flagged.
+/// body: symbols i32 each, param indexing as index i32 and length u16 each, parameters f32 each,
+///     then if flagged every symbol table entry as symbol i32 and name length u16, followed by all names as utf-8.
+/// ends with an FNV-1a checksum u32 of everything before it
+pub fn write_symbol_string(
+    symbol_string: &SymbolString,
+    metadata: Option<&SymbolStringMetadata>,
+    names: Option<&SymbolNames>) -> Result<Vec<u8>, SymbolStringWriteError> {
+    let fits_count = |count: usize| i32::try_from(count).is_ok();
+    if !fits_count(symbol_string.symbols.len()) || !fits_count(symbol_string.parameters.len()) {
+        return Err(SymbolStringWriteError::StringTooLong);
+    }
+    if let Some(names) = names {
+        if names.iter().any(|(_, name)| name.len() > u16::MAX as usize) {
+            return Err(SymbolStringWriteError::NameTooLong);
+        }
+        let name_bytes: usize = names.iter().map(|(_, name)| name.len()).sum();
+        if !fits_count(names.len()) || !fits_count(name_bytes) {
+            return Err(SymbolStringWriteError::StringTooLong);
+        }
+    }
+
+    let mut flags = 0;
+    if metadata.is_some() {
+        flags |= FLAG_HAS_METADATA;
+    }
+    if names.is_some() {
+        flags |= FLAG_HAS_SYMBOL_TABLE;
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE
+        + symbol_string.symbols.len() * (4 + PARAM_INDEXING_SIZE)
+        + symbol_string.parameters.len() * 4
+        + CHECKSUM_SIZE);
+    bytes.extend_from_slice(&SYMBOL_STRING_MAGIC);
+    bytes.extend_from_slice(&SYMBOL_STRING_FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&flags.to_le_bytes());
+    bytes.extend_from_slice(&(symbol_string.symbols.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(symbol_string.parameters.len() as u32).to_le_bytes());
+    if let Some(metadata) = metadata {
+        bytes.extend_from_slice(&metadata.step_count.to_le_bytes());
+        bytes.extend_from_slice(&metadata.seed.to_le_bytes());
+    }
+    if let Some(names) = names {
+        let name_bytes: usize = names.iter().map(|(_, name)| name.len()).sum();
+        bytes.extend_from_slice(&(names.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(name_bytes as u32).to_le_bytes());
+    }
+
+    for symbol in symbol_string.symbols {
+        bytes.extend_from_slice(&symbol.to_le_bytes());
+    }
+    for param_indexing in symbol_string.param_indexing {
+        bytes.extend_from_slice(&param_indexing.index.to_le_bytes());
+        bytes.extend_from_slice(&param_indexing.length.to_le_bytes());
+    }
+    for parameter in symbol_string.parameters {
+        bytes.extend_from_slice(&parameter.to_le_bytes());
+    }
+    if let Some(names) = names {
+        for (symbol, name) in names.iter() {
+            bytes.extend_from_slice(&symbol.to_le_bytes());
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        }
+        for (_, name) in names.iter() {
+            bytes.extend_from_slice(name.as_bytes());
+        }
+    }
+
+    let checksum = checksum(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    Ok(bytes)
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SymbolStringReadError> {
+        let end = self.position.checked_add(length).ok_or(SymbolStringReadError::Truncated)?;
+        let taken = self.bytes.get(self.position..end).ok_or(SymbolStringReadError::Truncated)?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], SymbolStringReadError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn read_u16(&mut self) -> Result<u16, SymbolStringReadError> {
+        Ok(u16::from_le_bytes(self.read()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, SymbolStringReadError> {
+        Ok(u32::from_le_bytes(self.read()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, SymbolStringReadError> {
+        Ok(i32::from_le_bytes(self.read()?))
+    }
+
+    fn read_f32(&mut self) -> Result<f32, SymbolStringReadError> {
+        Ok(f32::from_le_bytes(self.read()?))
+    }
+
+    fn read_count(&mut self) -> Result<i32, SymbolStringReadError> {
+        i32::try_from(self.read_u32()?).map_err(|_| SymbolStringReadError::Truncated)
+    }
+}
+
+/// read the header, and check that the data is complete and uncorrupted
+fn read_header(bytes: &[u8]) -> Result<(SerializedSymbolStringSizes, Option<SymbolStringMetadata>, ByteReader<'_>), SymbolStringReadError> {
+    let mut reader = ByteReader { bytes, position: 0 };
+    if reader.read::<4>()? != SYMBOL_STRING_MAGIC {
+        return Err(SymbolStringReadError::BadMagic);
+    }
+    let version = reader.read_u16()?;
+    let flags = reader.read_u16()?;
+    if version > SYMBOL_STRING_FORMAT_VERSION || flags & !KNOWN_FLAGS != 0 {
+        return Err(SymbolStringReadError::UnsupportedVersion);
+    }
+
+    let mut sizes = SerializedSymbolStringSizes {
+        symbol_count: reader.read_count()?,
+        parameter_count: reader.read_count()?,
+        has_metadata: flags & FLAG_HAS_METADATA != 0,
+        ..Default::default()
+    };
+    let metadata = if sizes.has_metadata {
+        Some(SymbolStringMetadata {
+            step_count: reader.read_u32()?,
+            seed: reader.read_u32()?,
+        })
+    } else {
+        None
+    };
+    sizes.has_symbol_table = flags & FLAG_HAS_SYMBOL_TABLE != 0;
+    if sizes.has_symbol_table {
+        sizes.symbol_table_entries = reader.read_count()?;
+        sizes.symbol_table_name_bytes = reader.read_count()?;
+    }
+
+    if sizes.total_bytes() != bytes.len() {
+        return Err(SymbolStringReadError::Truncated);
+    }
+    let (content, stored_checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+    if checksum(content) != u32::from_le_bytes(stored_checksum.try_into().unwrap()) {
+        return Err(SymbolStringReadError::ChecksumMismatch);
+    }
+    Ok((sizes, metadata, reader))
+}
+
+/// read only the sizes of the saved string, so that buffers can be allocated to read it into
+pub fn read_symbol_string_sizes(bytes: &[u8]) -> Result<SerializedSymbolStringSizes, SymbolStringReadError> {
+    read_header(bytes).map(|(sizes, _, _)| sizes)
+}
+
+pub fn read_symbol_string(bytes: &[u8]) -> Result<SerializedSymbolString, SymbolStringReadError> {
+    let (sizes, metadata, mut reader) = read_header(bytes)?;
+    let symbol_count = sizes.symbol_count as usize;
+    let parameter_count = sizes.parameter_count as usize;
+
+    let symbols = (0..symbol_count)
+        .map(|_| reader.read_i32())
+        .collect::<Result<Vec<_>, _>>()?;
+    let param_indexing = (0..symbol_count)
+        .map(|_| Ok(JaggedIndexing {
+            index: reader.read_i32()?,
+            length: reader.read_u16()?,
+        }))
+        .collect::<Result<Vec<_>, _>>()?;
+    let parameters = (0..parameter_count)
+        .map(|_| reader.read_f32())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for indexing in param_indexing.iter() {
+        if indexing.length == 0 {
+            continue;
+        }
+        if indexing.index < 0 || indexing.index as usize + indexing.length as usize > parameter_count {
+            return Err(SymbolStringReadError::InvalidParameterIndexing);
+        }
+    }
+
+    let names = if sizes.has_symbol_table {
+        let entries = (0..sizes.symbol_table_entries)
+            .map(|_| Ok((reader.read_i32()?, reader.read_u16()? as usize)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if entries.iter().map(|(_, name_length)| name_length).sum::<usize>() != sizes.symbol_table_name_bytes as usize {
+            return Err(SymbolStringReadError::InvalidSymbolTable);
+        }
+        let mut names = SymbolNames::new();
+        for (symbol, name_length) in entries {
+            let name = std::str::from_utf8(reader.take(name_length)?)
+                .map_err(|_| SymbolStringReadError::InvalidSymbolTable)?;
+            names.add(symbol, name);
+        }
+        Some(names)
+    } else {
+        None
+    };
+
+    Ok(SerializedSymbolString {
+        symbol_string: SymbolStringOwned {
+            symbols,
+            param_indexing,
+            parameters,
+        },
+        metadata,
+        names,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_string() -> SymbolStringOwned {
+        SymbolStringOwned {
+            symbols: vec![2, -1, 3, -2, 2],
+            param_indexing: vec![
+                JaggedIndexing { index: 0, length: 3 },
+                JaggedIndexing { index: 0, length: 0 },
+                JaggedIndexing { index: 3, length: 1 },
+                JaggedIndexing { index: -1, length: 0 },
+                JaggedIndexing { index: 4, length: 2 },
+            ],
+            parameters: vec![0.5, 20.0, 1000.0, -3.25, f32::MAX, 0.0],
+        }
+    }
+
+    fn sample_bytes() -> Vec<u8> {
+        let mut names = SymbolNames::with_branches(-1, -2);
+        names.add(2, "node").add(3, "F");
+        let metadata = SymbolStringMetadata { step_count: 7, seed: 42 };
+        write_symbol_string(&sample_string().borrow(), Some(&metadata), Some(&names)).unwrap()
+    }
+
+    fn read_error(bytes: &[u8]) -> SymbolStringReadError {
+        match read_symbol_string(bytes) {
+            Ok(_) => SymbolStringReadError::None,
+            Err(error) => error,
+        }
+    }
+
+    /// replace the checksum so that a deliberate edit is not reported as corruption
+    fn with_fixed_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        let content_length = bytes.len() - CHECKSUM_SIZE;
+        let checksum = checksum(&bytes[..content_length]);
+        bytes[content_length..].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn round_trips_string_metadata_and_names() {
+        let read = read_symbol_string(&sample_bytes()).unwrap();
+        let expected = sample_string();
+        assert_eq!(read.symbol_string.symbols, expected.symbols);
+        assert_eq!(read.symbol_string.parameters, expected.parameters);
+        for (read_indexing, expected_indexing) in read.symbol_string.param_indexing.iter().zip(expected.param_indexing.iter()) {
+            assert_eq!((read_indexing.index, read_indexing.length), (expected_indexing.index, expected_indexing.length));
+        }
+        assert_eq!(read.metadata, Some(SymbolStringMetadata { step_count: 7, seed: 42 }));
+        let names = read.names.unwrap();
+        assert_eq!(names.name_of(2), Some("node"));
+        assert_eq!(names.name_of(-1), Some("["));
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let bytes = sample_bytes();
+        for length in 0..bytes.len() {
+            let error = read_error(&bytes[..length]);
+            assert_eq!(error, SymbolStringReadError::Truncated, "truncated to ({}) bytes", length);
+        }
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(read_error(&extended), SymbolStringReadError::Truncated);
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let bytes = sample_bytes();
+        for byte_index in SYMBOL_STRING_MAGIC.len()..bytes.len() {
+            let mut corrupt = bytes.clone();
+            corrupt[byte_index] ^= 0x10;
+            assert_ne!(read_error(&corrupt), SymbolStringReadError::None, "flipped byte ({})", byte_index);
+        }
+
+        let mut corrupt = bytes.clone();
+        let last_parameter = bytes.len() - CHECKSUM_SIZE - 1;
+        corrupt[last_parameter] ^= 0x01;
+        assert_eq!(read_error(&corrupt), SymbolStringReadError::ChecksumMismatch);
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(read_error(&bad_magic), SymbolStringReadError::BadMagic);
+    }
+
+    #[test]
+    fn rejects_parameters_outside_the_parameter_list() {
+        let mut bytes = sample_bytes();
+        // the index of the last symbol's parameters
+        let symbol_count = sample_string().symbols.len();
+        let last_indexing = HEADER_SIZE + METADATA_SIZE + SYMBOL_TABLE_HEADER_SIZE + symbol_count * 4 + (symbol_count - 1) * PARAM_INDEXING_SIZE;
+        bytes[last_indexing..last_indexing + 4].copy_from_slice(&5i32.to_le_bytes());
+        assert_eq!(read_error(&with_fixed_checksum(bytes)), SymbolStringReadError::InvalidParameterIndexing);
+    }
+
+    #[test]
+    fn rejects_future_versions_and_unknown_flags() {
+        let mut future_version = sample_bytes();
+        future_version[4..6].copy_from_slice(&(SYMBOL_STRING_FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(read_error(&with_fixed_checksum(future_version)), SymbolStringReadError::UnsupportedVersion);
+
+        let mut unknown_flag = sample_bytes();
+        let flags = u16::from_le_bytes([unknown_flag[6], unknown_flag[7]]) | 1 << 15;
+        unknown_flag[6..8].copy_from_slice(&flags.to_le_bytes());
+        assert_eq!(read_error(&with_fixed_checksum(unknown_flag)), SymbolStringReadError::UnsupportedVersion);
+    }
+
+    #[test]
+    fn rejects_names_too_long_to_write() {
+        let mut names = SymbolNames::new();
+        names.add(2, "n".repeat(u16::MAX as usize + 1));
+        let error = write_symbol_string(&sample_string().borrow(), None, Some(&names)).err();
+        assert_eq!(error, Some(SymbolStringWriteError::NameTooLong));
+    }
+}
@@ -0,0 +1,170 @@
+use std::ops::Range;
+use crate::diffusion::extract_graph::SymbolString;
+use crate::diffusion::symbol_element_remap::SymbolStringOwned;
+use crate::interop_extern::data::JaggedIndexing;
+
+/// Builds a symbol string one symbol at a time, packing parameters directly into shared storage
+pub struct SymbolStringBuilder {
+    symbols: Vec<i32>,
+    param_indexing: Vec<JaggedIndexing>,
+    parameters: Vec<f32>,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+}
+
+impl SymbolStringBuilder {
+    pub fn new(branch_open_symbol: i32, branch_close_symbol: i32) -> Self {
+        Self::with_capacity(branch_open_symbol, branch_close_symbol, 0, 0)
+    }
+
+    pub fn with_capacity(
+        branch_open_symbol: i32,
+        branch_close_symbol: i32,
+        symbol_capacity: usize,
+        parameter_capacity: usize) -> Self {
+        SymbolStringBuilder {
+            symbols: Vec::with_capacity(symbol_capacity),
+            param_indexing: Vec::with_capacity(symbol_capacity),
+            parameters: Vec::with_capacity(parameter_capacity),
+            branch_open_symbol,
+            branch_close_symbol,
+        }
+    }
+
+    pub fn reserve(&mut self, additional_symbols: usize, additional_parameters: usize) {
+        self.symbols.reserve(additional_symbols);
+        self.param_indexing.reserve(additional_symbols);
+        self.parameters.reserve(additional_parameters);
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// panics if the symbol has more than u16::MAX parameters, or the string would hold more than i32::MAX parameters
+    pub fn push(&mut self, symbol: i32, params: &[f32]) -> &mut Self {
+        let Ok(length) = u16::try_from(params.len()) else {
+            panic!("Symbol ({}) has ({}) parameters, more than the ({}) one symbol can hold", symbol, params.len(), u16::MAX)
+        };
+        let Ok(index) = i32::try_from(self.parameters.len()) else {
+            panic!("String of ({}) parameters is too long to index", self.parameters.len())
+        };
+        self.symbols.push(symbol);
+        self.param_indexing.push(JaggedIndexing {
+            index,
+            length,
+        });
+        self.parameters.extend_from_slice(params);
+        self
+    }
+
+    /// wrap everything pushed by build_branch in the branch open and close symbols
+    pub fn push_branch(&mut self, build_branch: impl FnOnce(&mut Self)) -> &mut Self {
+        self.push(self.branch_open_symbol, &[]);
+        build_branch(self);
+        self.push(self.branch_close_symbol, &[])
+    }
+
+    /// copy the symbols in range from another string, repacking their parameters
+    pub fn extend_from(&mut self, source: &SymbolString, range: Range<usize>) -> &mut Self {
+        let symbol_count = range.len();
+        let param_count = source.param_indexing[range.clone()].iter()
+            .map(|indexing| indexing.length as usize)
+            .sum();
+        self.reserve(symbol_count, param_count);
+        for (_, symbol, params) in source.iter().skip(range.start).take(symbol_count) {
+            self.push(symbol, params);
+        }
+        self
+    }
+
+    pub fn build(self) -> SymbolStringOwned {
+        SymbolStringOwned {
+            symbols: self.symbols,
+            param_indexing: self.param_indexing,
+            parameters: self.parameters,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+
+    /// the built string holds the expected symbols, with parameters packed in symbol order
+    fn assert_built_as(built: &SymbolStringOwned, expected: &str) {
+        let expected = parse_symbol_string(expected, None).unwrap();
+        let indexing = |symbol_string: &SymbolStringOwned| symbol_string.param_indexing.iter()
+            .map(|indexing| (indexing.index, indexing.length))
+            .collect::<Vec<_>>();
+        assert_eq!(built.symbols, expected.symbols);
+        assert_eq!(built.parameters, expected.parameters);
+        assert_eq!(indexing(built), indexing(&expected));
+    }
+
+    #[test]
+    fn push_packs_parameters_in_order() {
+        let mut builder = SymbolStringBuilder::new(OPEN, CLOSE);
+        assert!(builder.is_empty());
+        builder.push(1, &[1.0]).push(2, &[]).push(3, &[2.0, 3.0]);
+
+        assert_eq!(builder.len(), 3);
+        assert_built_as(&builder.build(), "1(1) 2 3(2,3)");
+    }
+
+    #[test]
+    fn push_branch_nests_inside_the_enclosing_branch() {
+        let mut builder = SymbolStringBuilder::new(OPEN, CLOSE);
+        builder
+            .push(1, &[1.0])
+            .push_branch(|outer| {
+                outer.push(2, &[2.0]).push_branch(|inner| {
+                    inner.push(3, &[3.0, 4.0]);
+                });
+                outer.push(4, &[]);
+            })
+            .push_branch(|_| {})
+            .push(5, &[5.0]);
+
+        assert_built_as(&builder.build(), "1(1) -10 2(2) -10 3(3,4) -11 4 -11 -10 -11 5(5)");
+    }
+
+    #[test]
+    fn extend_from_repacks_a_sub_range_of_a_scattered_string() {
+        // the parameters of 1(1) 2(2,3) 3 4(4) 5(5), stored out of order with unused gaps between them
+        let scattered = SymbolStringOwned {
+            symbols: vec![1, 2, 3, 4, 5],
+            param_indexing: vec![
+                JaggedIndexing { index: 8, length: 1 },
+                JaggedIndexing { index: 4, length: 2 },
+                JaggedIndexing { index: -1, length: 0 },
+                JaggedIndexing { index: 0, length: 1 },
+                JaggedIndexing { index: 2, length: 1 },
+            ],
+            parameters: vec![4.0, -1.0, 5.0, -1.0, 2.0, 3.0, -1.0, -1.0, 1.0],
+        };
+        let mut builder = SymbolStringBuilder::new(OPEN, CLOSE);
+        builder.push(9, &[9.0]);
+        builder.extend_from(&scattered.borrow(), 1..4);
+
+        assert_built_as(&builder.build(), "9(9) 2(2,3) 3 4(4)");
+    }
+
+    #[test]
+    fn extend_from_an_empty_range_adds_nothing() {
+        let source = parse_symbol_string("1(1) 2(2)", None).unwrap();
+        let mut builder = SymbolStringBuilder::new(OPEN, CLOSE);
+        builder.extend_from(&source.borrow(), 1..1);
+
+        assert!(builder.is_empty());
+        assert!(builder.build().parameters.is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+use std::ops::Range;
+use crate::diffusion::extract_graph::SymbolString;
+use crate::diffusion::symbol_element_remap::SymbolStringOwned;
+use crate::diffusion::symbol_string_builder::SymbolStringBuilder;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymbolStringEditError {
+    None = 0,
+    IndexOutOfRange = 1,
+    /// the branch open symbol the subtree starts at is never closed
+    MismatchedBranches = 2,
+    /// the removed range or the inserted string do not hold matching branch symbols, so the edit would unbalance the string
+    UnbalancedEdit = 3,
+    /// the caller allocated buffers are too small to hold the edited string
+    TargetTooSmall = 4,
+    /// the source or the inserted string has parameter indexing outside of its parameters,
+    ///     or a different number of parameter indexes than symbols
+    InvalidParameterIndexing = 5,
+}
+
+/// whether every branch symbol is matched within the symbols
+fn is_balanced(symbols: &[i32], branch_open_symbol: i32, branch_close_symbol: i32) -> bool {
+    let mut depth = 0;
+    for symbol in symbols {
+        if *symbol == branch_open_symbol {
+            depth += 1;
+        } else if *symbol == branch_close_symbol {
+            if depth == 0 {
+                return false;
+            }
+            depth -= 1;
+        }
+    }
+    depth == 0
+}
+
+/// whether every symbol has parameter indexing which lies inside the parameters. empty indexing may point anywhere
+fn has_valid_parameters(symbol_string: &SymbolString) -> bool {
+    symbol_string.param_indexing.len() == symbol_string.symbols.len() &&
+        symbol_string.param_indexing.iter().all(|indexing| indexing.length == 0 ||
+            (indexing.index >= 0 && indexing.index as usize + indexing.length as usize <= symbol_string.parameters.len()))
+}
+
+/// The symbols cut away by pruning at the index. at a branch open symbol, that is the whole branch including its branch symbols.
+///     anywhere else, that is the symbol and everything after it up to the end of the branch holding it, including any nested branches.
+///     the close symbol of the branch is left in place, so nothing is cut at a branch close symbol
+pub fn subtree_range(
+    symbol_string: &SymbolString,
+    index: usize,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32) -> Result<Range<usize>, SymbolStringEditError> {
+
+    if index >= symbol_string.symbols.len() {
+        return Err(SymbolStringEditError::IndexOutOfRange);
+    }
+    let starts_branch = symbol_string.symbols[index] == branch_open_symbol;
+    let mut depth = 0;
+    for (symbol_index, symbol) in symbol_string.symbols.iter().enumerate().skip(index) {
+        if *symbol == branch_open_symbol {
+            depth += 1;
+        } else if *symbol == branch_close_symbol {
+            if depth == 0 {
+                return Ok(index..symbol_index);
+            }
+            depth -= 1;
+            if starts_branch && depth == 0 {
+                return Ok(index..symbol_index + 1);
+            }
+        }
+    }
+    if starts_branch {
+        return Err(SymbolStringEditError::MismatchedBranches);
+    }
+    Ok(index..symbol_string.symbols.len())
+}
+
+/// a copy of the source with the symbols in range replaced by every symbol in the replacement, with all parameters repacked.
+///     both the range and the replacement must hold matching branch symbols, and both strings must have valid parameter indexing
+pub fn replace_range(
+    source: &SymbolString,
+    range: Range<usize>,
+    replacement: &SymbolString,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32) -> Result<SymbolStringOwned, SymbolStringEditError> {
+
+    if range.start > range.end || range.end > source.symbols.len() {
+        return Err(SymbolStringEditError::IndexOutOfRange);
+    }
+    if !has_valid_parameters(source) || !has_valid_parameters(replacement) {
+        return Err(SymbolStringEditError::InvalidParameterIndexing);
+    }
+    if !is_balanced(&source.symbols[range.clone()], branch_open_symbol, branch_close_symbol) ||
+        !is_balanced(replacement.symbols, branch_open_symbol, branch_close_symbol) {
+        return Err(SymbolStringEditError::UnbalancedEdit);
+    }
+
+    let kept_params: usize = source.param_indexing[..range.start].iter()
+        .chain(&source.param_indexing[range.end..])
+        .map(|indexing| indexing.length as usize)
+        .sum();
+    let mut builder = SymbolStringBuilder::with_capacity(
+        branch_open_symbol,
+        branch_close_symbol,
+        source.symbols.len() - range.len() + replacement.symbols.len(),
+        kept_params + replacement.parameters.len());
+    builder
+        .extend_from(source, 0..range.start)
+        .extend_from(replacement, 0..replacement.symbols.len())
+        .extend_from(source, range.end..source.symbols.len());
+    Ok(builder.build())
+}
+
+/// a copy of the source with the scion inserted as a new branch before the index, wrapped in branch symbols
+pub fn graft(
+    source: &SymbolString,
+    index: usize,
+    scion: &SymbolString,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32) -> Result<SymbolStringOwned, SymbolStringEditError> {
+
+    if index > source.symbols.len() {
+        return Err(SymbolStringEditError::IndexOutOfRange);
+    }
+    if !has_valid_parameters(scion) {
+        return Err(SymbolStringEditError::InvalidParameterIndexing);
+    }
+    let mut branch = SymbolStringBuilder::with_capacity(
+        branch_open_symbol,
+        branch_close_symbol,
+        scion.symbols.len() + 2,
+        scion.parameters.len());
+    branch.push_branch(|branch| {
+        branch.extend_from(scion, 0..scion.symbols.len());
+    });
+    replace_range(source, index..index, &branch.build().borrow(), branch_open_symbol, branch_close_symbol)
+}
+
+impl SymbolStringOwned {
+    /// replace the symbols in range with every symbol in the replacement, repacking all parameters.
+    ///     both the range and the replacement must hold matching branch symbols
+    pub fn replace_range(
+        &mut self,
+        range: Range<usize>,
+        replacement: &SymbolString,
+        branch_open_symbol: i32,
+        branch_close_symbol: i32) -> Result<(), SymbolStringEditError> {
+        *self = replace_range(&self.borrow(), range, replacement, branch_open_symbol, branch_close_symbol)?;
+        Ok(())
+    }
+
+    /// insert every symbol of the inserted string before the index. the index may be the length of the string, to append
+    pub fn insert(
+        &mut self,
+        index: usize,
+        inserted: &SymbolString,
+        branch_open_symbol: i32,
+        branch_close_symbol: i32) -> Result<(), SymbolStringEditError> {
+        self.replace_range(index..index, inserted, branch_open_symbol, branch_close_symbol)
+    }
+
+    /// prune the subtree starting at the index, as found by subtree_range. returns the range of symbols which were removed
+    pub fn remove_subtree(
+        &mut self,
+        index: usize,
+        branch_open_symbol: i32,
+        branch_close_symbol: i32) -> Result<Range<usize>, SymbolStringEditError> {
+        let removed = subtree_range(&self.borrow(), index, branch_open_symbol, branch_close_symbol)?;
+        let empty = SymbolString {
+            symbols: &[],
+            param_indexing: &[],
+            parameters: &[],
+        };
+        self.replace_range(removed.clone(), &empty, branch_open_symbol, branch_close_symbol)?;
+        Ok(removed)
+    }
+
+    /// insert the scion as a new branch before the index, wrapped in branch symbols
+    pub fn graft(
+        &mut self,
+        index: usize,
+        scion: &SymbolString,
+        branch_open_symbol: i32,
+        branch_close_symbol: i32) -> Result<(), SymbolStringEditError> {
+        *self = graft(&self.borrow(), index, scion, branch_open_symbol, branch_close_symbol)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+    use crate::interop_extern::data::JaggedIndexing;
+
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+
+    fn parse(text: &str) -> SymbolStringOwned {
+        parse_symbol_string(text, None).unwrap()
+    }
+
+    /// the parameters of 1(1) 2(2,3) 3 4(4), stored in reverse with unused gaps between them
+    fn scattered() -> SymbolStringOwned {
+        SymbolStringOwned {
+            symbols: vec![1, 2, 3, 4],
+            param_indexing: vec![
+                JaggedIndexing { index: 6, length: 1 },
+                JaggedIndexing { index: 3, length: 2 },
+                JaggedIndexing { index: -1, length: 0 },
+                JaggedIndexing { index: 0, length: 1 },
+            ],
+            parameters: vec![4.0, -1.0, -1.0, 2.0, 3.0, -1.0, 1.0, -1.0],
+        }
+    }
+
+    /// the edited string holds the expected symbols, with parameters packed in symbol order
+    fn assert_packed_as(edited: &SymbolStringOwned, expected: &str) {
+        let expected = parse(expected);
+        let indexing = |symbol_string: &SymbolStringOwned| symbol_string.param_indexing.iter()
+            .map(|indexing| (indexing.index, indexing.length))
+            .collect::<Vec<_>>();
+        assert_eq!(edited.symbols, expected.symbols);
+        assert_eq!(edited.parameters, expected.parameters);
+        assert_eq!(indexing(edited), indexing(&expected));
+    }
+
+    #[test]
+    fn subtree_at_open_symbol_is_the_whole_branch() {
+        let symbol_string = parse("1 -10 2 -10 3 -11 4 -11 5");
+
+        assert_eq!(subtree_range(&symbol_string.borrow(), 1, OPEN, CLOSE), Ok(1..8));
+        assert_eq!(subtree_range(&symbol_string.borrow(), 3, OPEN, CLOSE), Ok(3..6));
+    }
+
+    #[test]
+    fn subtree_at_plain_symbol_runs_to_the_end_of_its_branch() {
+        let symbol_string = parse("1 -10 2 -10 3 -11 4 -11 5");
+
+        assert_eq!(subtree_range(&symbol_string.borrow(), 2, OPEN, CLOSE), Ok(2..7));
+        assert_eq!(subtree_range(&symbol_string.borrow(), 0, OPEN, CLOSE), Ok(0..9));
+    }
+
+    #[test]
+    fn subtree_at_close_symbol_is_empty() {
+        let symbol_string = parse("1 -10 2 -11 3");
+
+        assert_eq!(subtree_range(&symbol_string.borrow(), 3, OPEN, CLOSE), Ok(3..3));
+    }
+
+    #[test]
+    fn subtree_errors() {
+        let symbol_string = parse("1 -10 2");
+
+        assert_eq!(subtree_range(&symbol_string.borrow(), 1, OPEN, CLOSE), Err(SymbolStringEditError::MismatchedBranches));
+        assert_eq!(subtree_range(&symbol_string.borrow(), 3, OPEN, CLOSE), Err(SymbolStringEditError::IndexOutOfRange));
+    }
+
+    #[test]
+    fn replace_range_repacks_scattered_parameters() {
+        let replacement = scattered();
+        let edited = replace_range(&scattered().borrow(), 1..3, &replacement.borrow(), OPEN, CLOSE).unwrap();
+
+        assert_packed_as(&edited, "1(1) 1(1) 2(2,3) 3 4(4) 4(4)");
+    }
+
+    #[test]
+    fn insert_repacks_scattered_parameters() {
+        let mut edited = parse("5(5) 6(6)");
+        edited.insert(1, &scattered().borrow(), OPEN, CLOSE).unwrap();
+        assert_packed_as(&edited, "5(5) 1(1) 2(2,3) 3 4(4) 6(6)");
+
+        edited.insert(6, &parse("7(7)").borrow(), OPEN, CLOSE).unwrap();
+        assert_packed_as(&edited, "5(5) 1(1) 2(2,3) 3 4(4) 6(6) 7(7)");
+    }
+
+    #[test]
+    fn graft_wraps_the_scion_in_a_branch() {
+        let mut edited = scattered();
+        edited.graft(2, &parse("7(7) 8").borrow(), OPEN, CLOSE).unwrap();
+
+        assert_packed_as(&edited, "1(1) 2(2,3) -10 7(7) 8 -11 3 4(4)");
+    }
+
+    #[test]
+    fn remove_subtree_repacks_what_is_left() {
+        let mut edited = parse("1(1) -10 2(2) -11 3(3)");
+        let removed = edited.remove_subtree(1, OPEN, CLOSE).unwrap();
+
+        assert_eq!(removed, 1..4);
+        assert_packed_as(&edited, "1(1) 3(3)");
+    }
+
+    #[test]
+    fn unbalanced_edits_are_rejected() {
+        let source = parse("1 -10 2 -11 3");
+
+        let cuts_branch_open = replace_range(&source.borrow(), 0..2, &parse("4").borrow(), OPEN, CLOSE);
+        assert_eq!(cuts_branch_open.err(), Some(SymbolStringEditError::UnbalancedEdit));
+
+        let inserts_branch_close = replace_range(&source.borrow(), 4..4, &parse("-11").borrow(), OPEN, CLOSE);
+        assert_eq!(inserts_branch_close.err(), Some(SymbolStringEditError::UnbalancedEdit));
+
+        let reversed_branch = replace_range(&source.borrow(), 0..0, &parse("-11 -10").borrow(), OPEN, CLOSE);
+        assert_eq!(reversed_branch.err(), Some(SymbolStringEditError::UnbalancedEdit));
+
+        let balanced = replace_range(&source.borrow(), 1..4, &parse("-10 -11").borrow(), OPEN, CLOSE).unwrap();
+        assert_packed_as(&balanced, "1 -10 -11 3");
+    }
+}
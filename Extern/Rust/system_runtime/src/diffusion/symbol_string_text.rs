@@ -0,0 +1,315 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::diffusion::extract_graph::SymbolString;
+use crate::diffusion::symbol_element_remap::SymbolStringOwned;
+use crate::interop_extern::data::JaggedIndexing;
+
+/// Names used to print and parse symbols, such as "[" for the branch open symbol.
+/// symbols without a name are written as their raw id. names should not start with a digit or a '-' followed by a digit,
+///     or contain whitespace, '(' or ')'
+#[derive(Clone, Debug, Default)]
+pub struct SymbolNames {
+    names: Vec<(i32, String)>,
+}
+
+impl SymbolNames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// names the branch symbols "[" and "]"
+    pub fn with_branches(branch_open_symbol: i32, branch_close_symbol: i32) -> Self {
+        let mut names = Self::new();
+        names.add(branch_open_symbol, "[").add(branch_close_symbol, "]");
+        names
+    }
+
+    /// replaces any name already given to the symbol
+    pub fn add(&mut self, symbol: i32, name: impl Into<String>) -> &mut Self {
+        let name = name.into();
+        match self.names.iter_mut().find(|(named_symbol, _)| *named_symbol == symbol) {
+            Some(existing) => existing.1 = name,
+            None => self.names.push((symbol, name)),
+        }
+        self
+    }
+
+    pub fn name_of(&self, symbol: i32) -> Option<&str> {
+        self.names.iter()
+            .find(|(named_symbol, _)| *named_symbol == symbol)
+            .map(|(_, name)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &str)> {
+        self.names.iter().map(|(symbol, name)| (*symbol, name.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// the symbol with the longest name which the text starts with, and the length of that name
+    fn match_start(&self, text: &str) -> Option<(i32, usize)> {
+        self.names.iter()
+            .filter(|(_, name)| !name.is_empty() && text.starts_with(name.as_str()))
+            .max_by_key(|(_, name)| name.len())
+            .map(|(symbol, name)| (*symbol, name.len()))
+    }
+}
+
+/// Writes a symbol string as text, such as `2(0.5,20,1000)[2(0.5,0,1000)]`.
+/// symbols without parameters are separated by a space, unless they are named by a single punctuation character
+pub struct SymbolStringDisplay<'a> {
+    symbol_string: &'a SymbolString<'a>,
+    names: Option<&'a SymbolNames>,
+}
+
+impl<'a> SymbolString<'a> {
+    pub fn display_with(&'a self, names: &'a SymbolNames) -> SymbolStringDisplay<'a> {
+        SymbolStringDisplay {
+            symbol_string: self,
+            names: Some(names),
+        }
+    }
+}
+
+impl fmt::Display for SymbolStringDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // whether the last symbol written could run together with the next one
+        let mut needs_separator = false;
+        // a raw id directly after a name ending in '-' would be read back as a negative id
+        let mut ends_with_minus = false;
+        for (_, symbol, params) in self.symbol_string.iter() {
+            let name = self.names.and_then(|names| names.name_of(symbol));
+            let is_punctuation = matches!(name, Some(name) if name.chars().count() == 1 && name.chars().all(|c| c.is_ascii_punctuation()));
+            if (needs_separator && !is_punctuation) || (ends_with_minus && name.is_none()) {
+                f.write_str(" ")?;
+            }
+            match name {
+                Some(name) => f.write_str(name)?,
+                None => write!(f, "{}", symbol)?,
+            }
+            if !params.is_empty() {
+                f.write_str("(")?;
+                for (param_index, param) in params.iter().enumerate() {
+                    if param_index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                f.write_str(")")?;
+            }
+            needs_separator = params.is_empty() && !is_punctuation;
+            ends_with_minus = params.is_empty() && name.is_some_and(|name| name.ends_with('-'));
+        }
+        Ok(())
+    }
+}
+
+/// writes every symbol as its raw id
+impl fmt::Display for SymbolString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        SymbolStringDisplay {
+            symbol_string: self,
+            names: None,
+        }.fmt(f)
+    }
+}
+
+impl fmt::Display for SymbolStringOwned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.borrow().fmt(f)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolStringParseError {
+    /// byte offset into the text where parsing failed
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SymbolStringParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for SymbolStringParseError {}
+
+/// Parse text written by SymbolStringDisplay. whitespace between symbols is ignored.
+///     raw ids may be negative, and are matched before names. otherwise the longest name which matches is used.
+///     an empty parameter list "()" is the same as no parameter list
+pub fn parse_symbol_string(text: &str, names: Option<&SymbolNames>) -> Result<SymbolStringOwned, SymbolStringParseError> {
+    let mut parsed = SymbolStringOwned {
+        symbols: Vec::new(),
+        param_indexing: Vec::new(),
+        parameters: Vec::new(),
+    };
+    let error = |position: usize, message: &str| SymbolStringParseError {
+        position,
+        message: message.to_string(),
+    };
+
+    let mut position = 0;
+    while position < text.len() {
+        let remaining = &text[position..];
+        let trimmed = remaining.trim_start();
+        position += remaining.len() - trimmed.len();
+        if trimmed.is_empty() {
+            break;
+        }
+
+        let sign_length = trimmed.starts_with('-') as usize;
+        let digit_length = trimmed[sign_length..].find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len() - sign_length);
+        let symbol = if digit_length > 0 {
+            let id_length = sign_length + digit_length;
+            let symbol = trimmed[..id_length].parse::<i32>()
+                .map_err(|_| error(position, "Symbol id out of range"))?;
+            position += id_length;
+            symbol
+        } else if let Some((symbol, name_length)) = names.and_then(|names| names.match_start(trimmed)) {
+            position += name_length;
+            symbol
+        } else {
+            return Err(error(position, "Expected a symbol name or id"));
+        };
+
+        let param_start = parsed.parameters.len();
+        if text[position..].starts_with('(') {
+            let Some(close_offset) = text[position..].find(')') else {
+                return Err(error(position, "Unclosed parameter list"));
+            };
+            let param_text = &text[position + 1..position + close_offset];
+            let mut param_position = position + 1;
+            for param in param_text.split(',').filter(|_| !param_text.trim().is_empty()) {
+                let value = param.trim().parse::<f32>()
+                    .map_err(|_| error(param_position, "Invalid parameter"))?;
+                parsed.parameters.push(value);
+                param_position += param.len() + 1;
+            }
+            position += close_offset + 1;
+        }
+        let param_count = parsed.parameters.len() - param_start;
+        if param_count > u16::MAX as usize {
+            return Err(error(position, "Too many parameters"));
+        }
+
+        parsed.symbols.push(symbol);
+        parsed.param_indexing.push(JaggedIndexing {
+            index: param_start as i32,
+            length: param_count as u16,
+        });
+    }
+    Ok(parsed)
+}
+
+/// parses raw symbol ids only
+impl FromStr for SymbolStringOwned {
+    type Err = SymbolStringParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_symbol_string(text, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+
+    fn names() -> SymbolNames {
+        let mut names = SymbolNames::with_branches(OPEN, CLOSE);
+        names.add(1, "F").add(2, "+").add(3, "-").add(4, "leaf");
+        names
+    }
+
+    fn to_parts(symbol_string: &SymbolStringOwned) -> Vec<(i32, Vec<f32>)> {
+        symbol_string.borrow().iter()
+            .map(|(_, symbol, params)| (symbol, params.to_vec()))
+            .collect()
+    }
+
+    fn owned(parts: &[(i32, &[f32])]) -> SymbolStringOwned {
+        let mut symbol_string = SymbolStringOwned {
+            symbols: Vec::new(),
+            param_indexing: Vec::new(),
+            parameters: Vec::new(),
+        };
+        for (symbol, params) in parts {
+            symbol_string.symbols.push(*symbol);
+            symbol_string.param_indexing.push(JaggedIndexing {
+                index: symbol_string.parameters.len() as i32,
+                length: params.len() as u16,
+            });
+            symbol_string.parameters.extend_from_slice(params);
+        }
+        symbol_string
+    }
+
+    #[test]
+    fn raw_text_round_trips() {
+        let text = "2(0.5,20,1000) -10 2(0.5,-0.25,1e-7)-10 -3 5 -11 7(-1)-11 0";
+        let parsed = parse_symbol_string(text, None).unwrap();
+        let written = parsed.to_string();
+        assert_eq!(written, "2(0.5,20,1000)-10 2(0.5,-0.25,0.0000001)-10 -3 5 -11 7(-1)-11 0");
+        assert_eq!(to_parts(&parse_symbol_string(&written, None).unwrap()), to_parts(&parsed));
+        assert_eq!(parse_symbol_string(&written, None).unwrap().to_string(), written);
+    }
+
+    #[test]
+    fn named_text_round_trips() {
+        let names = names();
+        let text = "F(1)[+F(2)[-leaf(0.5) -5]-1 leaf]F -7 -3";
+        let parsed = parse_symbol_string(text, Some(&names)).unwrap();
+        assert_eq!(to_parts(&parsed), to_parts(&owned(&[
+            (1, &[1.0]), (OPEN, &[]), (2, &[]), (1, &[2.0]), (OPEN, &[]), (3, &[]), (4, &[0.5]), (-5, &[]), (CLOSE, &[]),
+            (-1, &[]), (4, &[]), (CLOSE, &[]), (1, &[]), (-7, &[]), (-3, &[]),
+        ])));
+        let written = parsed.borrow().display_with(&names).to_string();
+        assert_eq!(to_parts(&parse_symbol_string(&written, Some(&names)).unwrap()), to_parts(&parsed));
+    }
+
+    #[test]
+    fn string_round_trips() {
+        let names = names();
+        let symbol_string = owned(&[
+            (-1, &[]), (1, &[-2.5, 0.0]), (OPEN, &[]), (3, &[]), (1, &[]), (OPEN, &[]), (2, &[]), (-4, &[3.0]),
+            (CLOSE, &[]), (3, &[]), (-8, &[]), (CLOSE, &[]), (i32::MIN, &[]), (i32::MAX, &[f32::MAX, f32::MIN_POSITIVE]),
+        ]);
+
+        let raw = symbol_string.to_string();
+        assert_eq!(to_parts(&parse_symbol_string(&raw, None).unwrap()), to_parts(&symbol_string));
+
+        let named = symbol_string.borrow().display_with(&names).to_string();
+        assert_eq!(to_parts(&parse_symbol_string(&named, Some(&names)).unwrap()), to_parts(&symbol_string));
+    }
+
+    #[test]
+    fn empty_parameter_list_is_no_parameters() {
+        let parsed = parse_symbol_string("1() 2( ) 3", None).unwrap();
+        assert_eq!(to_parts(&parsed), to_parts(&owned(&[(1, &[]), (2, &[]), (3, &[])])));
+        assert_eq!(parsed.to_string(), "1 2 3");
+    }
+
+    fn error_position(text: &str) -> usize {
+        match parse_symbol_string(text, None) {
+            Ok(_) => panic!("Parsed malformed text ({})", text),
+            Err(error) => error.position,
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        assert_eq!(error_position("1(2"), 1);
+        assert_eq!(error_position("1(2,)"), 4);
+        assert_eq!(error_position("- 1"), 0);
+        assert_eq!(error_position("1 99999999999"), 2);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::diffusion::diffusion_job::DiffusionJob;
+use crate::diffusion::diffusion_stats::DiffusionStats;
+use crate::math::Vec3;
+use crate::voxel::layout::VoxelWorldVolumetricLayerData;
+
+/// the largest exchange rate which can not overshoot. at 0.5 the node and voxel meet in the middle
+const MAXIMUM_EXCHANGE_RATE: f32 = 0.5;
+
+/// Couples one resource type held by diffusion nodes to one layer of the voxel world, such as water in roots and soil
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct VoxelExchangeChannel {
+    pub resource_type: i32,
+    pub voxel_layer: i32,
+    /// the fraction of the difference moved from the voxel into the node per exchange, when the voxel holds more.
+    ///     clamped to 0..0.5
+    pub uptake_rate: f32,
+    /// the fraction of the difference moved from the node into the voxel per exchange, when the node holds more.
+    ///     clamped to 0..0.5
+    pub release_rate: f32,
+}
+
+/// Move resources between every node and the voxel which contains it. whatever leaves one side arrives in the other,
+///     neither side is drawn below zero, and nodes are not filled above their capacity.
+///     nodes which share a voxel exchange one after another, each seeing the voxel as left by the last.
+/// node_positions is indexed by the index of the node symbol in the source string, such as the positions produced by the turtle.
+///     nodes without a position, or outside of the voxel volume, do not exchange.
+/// returns false if any channel points at a voxel layer which does not exist, those channels are skipped
+pub fn exchange_with_voxels(
+    diffusion_job: DiffusionJob,
+    amounts: &mut [f32],
+    node_positions: &[Vec3],
+    voxels: &mut VoxelWorldVolumetricLayerData,
+    channels: &[VoxelExchangeChannel],
+    mut stats: Option<&mut DiffusionStats>) -> bool {
+    let volume = voxels.layout.volume;
+    let mut all_channels_valid = true;
+
+    for channel in channels {
+        if !voxels.is_valid_layer(channel.voxel_layer) || channel.resource_type < 0 {
+            all_channels_valid = false;
+            continue;
+        }
+        let layer = channel.voxel_layer as usize;
+        let resource_type = channel.resource_type as usize;
+        let uptake_rate = channel.uptake_rate.clamp(0.0, MAXIMUM_EXCHANGE_RATE);
+        let release_rate = channel.release_rate.clamp(0.0, MAXIMUM_EXCHANGE_RATE);
+
+        for node in diffusion_job.nodes {
+            if resource_type >= node.total_resource_types as usize {
+                continue;
+            }
+            let Some(voxel_index) = node_positions.get(node.index_in_source as usize)
+                .and_then(|position| volume.voxel_index_from_world_position(*position)) else {
+                continue;
+            };
+            let resource_index = node.index_in_temp_amount_list as usize + resource_type;
+            let node_amount = amounts[resource_index];
+            let voxel_amount = voxels.get(voxel_index, layer);
+
+            // positive flow moves into the node
+            let difference = voxel_amount - node_amount;
+            let flow = if difference > 0.0 {
+                let headroom = (diffusion_job.node_max_capacities[resource_index] - node_amount).max(0.0);
+                (difference * uptake_rate).min(headroom).min(voxel_amount.max(0.0))
+            } else {
+                (difference * release_rate).max(-node_amount.max(0.0))
+            };
+            if flow == 0.0 {
+                continue;
+            }
+
+            amounts[resource_index] += flow;
+            voxels.set(voxel_index, layer, voxel_amount - flow);
+            if let Some(stats) = stats.as_deref_mut() {
+                let resource_stats = stats.resource_mut(resource_type);
+                if flow > 0.0 {
+                    resource_stats.absorbed_from_voxels += flow;
+                } else {
+                    resource_stats.released_to_voxels -= flow;
+                }
+            }
+        }
+    }
+    all_channels_valid
+}
@@ -1,5 +1,14 @@
+// externs take raw pointers to native arrays owned by the C# caller, and keep the flat argument lists of their C# call sites.
+//  the original diffusion and expression externs follow the same pattern
+#![allow(clippy::not_unsafe_ptr_arg_deref, clippy::too_many_arguments)]
 
 pub mod data;
 pub mod diffusion;
 pub mod expressions;
 pub mod functions;
+pub mod voxel;
+pub mod serialization;
+pub mod editing;
+pub mod turtle;
+pub mod mesh;
+pub mod rewrite_trace;
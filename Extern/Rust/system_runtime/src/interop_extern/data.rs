@@ -41,6 +41,10 @@ macro_rules! native_array_interop {
         }
         impl<'a> $struct_name_mut{
             pub fn to_slice(&self) -> &'a mut [$typ]{
+                if self.len <= 0 {
+                    // empty arrays may be passed with a null data pointer
+                    return &mut [];
+                }
                 unsafe{
                     std::slice::from_raw_parts_mut(self.data, self.len as usize)
                 }
@@ -53,6 +57,10 @@ macro_rules! native_array_interop {
         }
         impl<'a> $struct_name{
             pub fn to_slice(&self) -> &'a [$typ]{
+                if self.len <= 0 {
+                    // empty arrays may be passed with a null data pointer
+                    return &[];
+                }
                 unsafe{
                     std::slice::from_raw_parts(self.data, self.len as usize)
                 }
@@ -66,3 +74,4 @@ pub(crate) use native_array_interop;
 native_array_interop!(i32, NativeArrayInteropi32, NativeArrayInteropi32Mut);
 native_array_interop!(f32, NativeArrayInteropf32, NativeArrayInteropf32Mut);
 native_array_interop!(JaggedIndexing, NativeArrayInteropJaggedIndexing, NativeArrayInteropJaggedIndexingMut);
+native_array_interop!(u8, NativeArrayInteropu8, NativeArrayInteropu8Mut);
@@ -1,6 +1,16 @@
 ﻿use crate::diffusion::apply_results::apply_diffusion_results;
-use crate::diffusion::extract_graph::{extract_edges_and_nodes_in_parallel, extract_edges_and_nodes_in_place, SymbolString, SymbolStringMut};
-use crate::interop_extern::data::{JaggedIndexing, native_array_interop, NativeArrayInteropf32, NativeArrayInteropf32Mut, NativeArrayInteropi32, NativeArrayInteropi32Mut, NativeArrayInteropJaggedIndexing, NativeArrayInteropJaggedIndexingMut};
+use crate::diffusion::diffusion_options::{CapacityModel, DiffusionNodeLayout, DiffusionOptions, EdgeWeighting, MissingResourceMode};
+use crate::diffusion::diffusion_stats::{DiffusionResourceStats, DiffusionStats, MismatchedResourceEdge};
+use crate::diffusion::reactions::{reactions_index_terms, DiffusionReaction, ReactionTerm};
+use crate::diffusion::forest::{DiffusionForest, ForestEdge, ForestReport, merge_graphs};
+use crate::diffusion::voxel_exchange::{exchange_with_voxels, VoxelExchangeChannel};
+use crate::diffusion::branch_tree::BranchTreeError;
+use crate::diffusion::extract_graph::{extract_edges_and_nodes_in_parallel, extract_edges_and_nodes_in_place, extract_edges_and_nodes_read_only, SymbolString, SymbolStringMut};
+use crate::diffusion::graph_export::{DiffusionGraphFormat, export_diffusion_graph};
+use crate::interop_extern::voxel::VoxelWorldVolumetricLayerDataInterop;
+use crate::math::Vec3;
+use crate::voxel::layout::VoxelWorldVolumetricLayerData;
+use crate::interop_extern::data::{JaggedIndexing, native_array_interop, NativeArrayInteropf32, NativeArrayInteropf32Mut, NativeArrayInteropi32, NativeArrayInteropi32Mut, NativeArrayInteropJaggedIndexing, NativeArrayInteropJaggedIndexingMut, NativeArrayInteropu8Mut};
 
 
 #[repr(C)]
@@ -105,7 +115,7 @@ pub struct LSystemSingleSymbolMatchData{
 native_array_interop!(LSystemSingleSymbolMatchData, NativeArrayInteropLSystemSingleSymbolMatchData, NativeArrayInteropLSystemSingleSymbolMatchDataMut);
 
 #[repr(u8)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LSystemMatchErrorCode
 {
     None = 0,
@@ -114,6 +124,135 @@ pub enum LSystemMatchErrorCode
     TrivialSymbolNotIndicatedAtReplacementTime = 3
 }
 
+native_array_interop!(DiffusionResourceStats, NativeArrayInteropDiffusionResourceStats, NativeArrayInteropDiffusionResourceStatsMut);
+native_array_interop!(MismatchedResourceEdge, NativeArrayInteropMismatchedResourceEdge, NativeArrayInteropMismatchedResourceEdgeMut);
+
+#[repr(C)]
+pub struct DiffusionStatsInterop {
+    /// caller allocated, indexed by resource type. resource types beyond the length of this array are not reported
+    pub resources: NativeArrayInteropDiffusionResourceStatsMut,
+    /// set to the number of resource types seen during diffusion, which may be more than the length of resources
+    pub observed_resource_types: i32,
+}
+
+impl DiffusionStatsInterop {
+    pub fn write_from(&mut self, stats: &DiffusionStats) {
+        let target = self.resources.to_slice();
+        for (target_stats, source_stats) in target.iter_mut().zip(stats.resources.iter()) {
+            *target_stats = *source_stats;
+        }
+        for target_stats in target.iter_mut().skip(stats.resources.len()) {
+            *target_stats = DiffusionResourceStats::default();
+        }
+        self.observed_resource_types = stats.resources.len() as i32;
+    }
+}
+
+/// diagnostics written by the externs which take DiffusionOptionsInterop.
+///     struct_size must be set to the size of this struct in bytes, calls with any other size are rejected
+#[repr(C)]
+pub struct DiffusionDiagnosticsInterop {
+    pub struct_size: i32,
+    pub stats: DiffusionStatsInterop,
+    /// caller allocated. edges beyond the length of this array are not reported,
+    ///     and entries past observed_mismatched_edges are cleared to zero
+    pub mismatched_edges: NativeArrayInteropMismatchedResourceEdgeMut,
+    /// set to the number of mismatched edges found, which may be more than the length of mismatched_edges
+    pub observed_mismatched_edges: i32,
+    /// caller allocated, written by the forest externs with the index of every string whose branch symbols do not match.
+    ///     strings beyond the length of this array are not reported, and entries past observed_unbalanced_strings are set to -1
+    pub unbalanced_strings: NativeArrayInteropi32Mut,
+    /// set to the number of unbalanced strings found. always 0 outside of the forest externs
+    pub observed_unbalanced_strings: i32,
+    /// caller allocated, written by the forest externs with the index of every forest edge which does not point at a diffusion node.
+    ///     edges beyond the length of this array are not reported, and entries past observed_unconnected_edges are set to -1
+    pub unconnected_edges: NativeArrayInteropi32Mut,
+    /// set to the number of unconnected forest edges found. always 0 outside of the forest externs
+    pub observed_unconnected_edges: i32,
+}
+
+fn write_indexes(target: &mut [i32], indexes: &[usize]) -> i32 {
+    for (target_index, index) in target.iter_mut().zip(indexes.iter()) {
+        *target_index = *index as i32;
+    }
+    for target_index in target.iter_mut().skip(indexes.len()) {
+        *target_index = -1;
+    }
+    indexes.len() as i32
+}
+
+impl DiffusionDiagnosticsInterop {
+    pub fn has_expected_size(&self) -> bool {
+        self.struct_size as usize == size_of::<Self>()
+    }
+
+    pub fn write_from(&mut self, stats: &DiffusionStats) {
+        self.stats.write_from(stats);
+
+        let target_edges = self.mismatched_edges.to_slice();
+        for (target_edge, source_edge) in target_edges.iter_mut().zip(stats.mismatched_edges.iter()) {
+            *target_edge = *source_edge;
+        }
+        for target_edge in target_edges.iter_mut().skip(stats.mismatched_edges.len()) {
+            *target_edge = MismatchedResourceEdge::default();
+        }
+        self.observed_mismatched_edges = stats.mismatched_edges.len() as i32;
+        self.write_forest_report(&ForestReport::default());
+    }
+
+    pub fn write_forest_report(&mut self, forest_report: &ForestReport) {
+        self.observed_unbalanced_strings = write_indexes(self.unbalanced_strings.to_slice(), &forest_report.unbalanced_strings);
+        self.observed_unconnected_edges = write_indexes(self.unconnected_edges.to_slice(), &forest_report.unconnected_edges);
+    }
+}
+
+native_array_interop!(DiffusionReaction, NativeArrayInteropDiffusionReaction, NativeArrayInteropDiffusionReactionMut);
+native_array_interop!(ReactionTerm, NativeArrayInteropReactionTerm, NativeArrayInteropReactionTermMut);
+
+/// struct_size must be set to the size of this struct in bytes, calls with any other size are rejected.
+///     calls are also rejected when a reaction indexes past the reaction terms
+#[repr(C)]
+pub struct DiffusionOptionsInterop {
+    pub struct_size: i32,
+    pub node_layout: DiffusionNodeLayout,
+    /// indexed by resource type, may be empty
+    pub directional_transport: NativeArrayInteropf32,
+    /// may be empty
+    pub reactions: NativeArrayInteropDiffusionReaction,
+    pub reaction_terms: NativeArrayInteropReactionTerm,
+    pub edge_weighting: EdgeWeighting,
+    /// indexed by resource type, may be empty
+    pub decay_rates: NativeArrayInteropf32,
+    pub missing_resource_mode: MissingResourceMode,
+    pub capacity_model: CapacityModel,
+}
+
+impl<'a> DiffusionOptionsInterop {
+    /// None if struct_size does not match the size of this struct, or a reaction indexes past the reaction terms
+    pub fn to_options(&self) -> Option<DiffusionOptions<'a>> {
+        if self.struct_size as usize != size_of::<Self>() {
+            return None;
+        }
+        let reactions = self.reactions.to_slice();
+        let reaction_terms = self.reaction_terms.to_slice();
+        if !reactions_index_terms(reactions, reaction_terms) {
+            return None;
+        }
+        Some(DiffusionOptions {
+            node_layout: self.node_layout,
+            directional_transport: self.directional_transport.to_slice(),
+            reactions,
+            reaction_terms,
+            edge_weighting: self.edge_weighting,
+            decay_rates: self.decay_rates.to_slice(),
+            missing_resource_mode: self.missing_resource_mode,
+            capacity_model: self.capacity_model,
+        })
+    }
+}
+
+/// returns false if the branch symbols in the string do not match. the string is still diffused into the target then,
+///     reading unmatched close symbols as plain symbols and unclosed branches as running to the end of the string
 #[no_mangle]
 pub extern "C" fn perform_parallel_diffusion(
     source_data: *mut SymbolStringInterop,
@@ -136,11 +275,7 @@ pub extern "C" fn perform_parallel_diffusion(
         target_data.as_ref().unwrap().to_symbol_str(),
         match_singleton_data.as_ref().unwrap().to_slice()
         )};
-    
-    // TODO: doing this remap costs a lot, likely in alloc time.
-    //  its more ergonomic, but not ready for doing this conversion yet.
-    //let source_elements = to_elements(&source_data_safe);
-    
+
     perform_parallel_diffusion_internal(
         &source_data_safe,
         &mut target_data_safe,
@@ -151,9 +286,117 @@ pub extern "C" fn perform_parallel_diffusion(
         branch_close_symbol,
         diffusion_steps,
         diffusion_global_multiplier,
+        &DiffusionOptions::default(),
+        None,
     )
 }
 
+/// same as perform_parallel_diffusion, but also reports mass conservation diagnostics into stats_data
+#[no_mangle]
+pub extern "C" fn perform_parallel_diffusion_with_stats(
+    source_data: *mut SymbolStringInterop,
+    target_data: *mut SymbolStringInteropMut,
+    match_singleton_data: *mut NativeArrayInteropLSystemSingleSymbolMatchData,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    diffusion_steps: i32,
+    diffusion_global_multiplier: f32,
+    stats_data: *mut DiffusionStatsInterop,
+) -> bool{
+
+    let (
+        source_data_safe,
+        mut target_data_safe,
+        match_singleton_data_safe,
+        stats_data_safe) =
+    unsafe {(
+        source_data.as_ref().unwrap().to_symbol_str(),
+        target_data.as_ref().unwrap().to_symbol_str(),
+        match_singleton_data.as_ref().unwrap().to_slice(),
+        stats_data.as_mut().unwrap(),
+        )};
+
+    let mut stats = DiffusionStats::new();
+    let result = perform_parallel_diffusion_internal(
+        &source_data_safe,
+        &mut target_data_safe,
+        match_singleton_data_safe,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        diffusion_steps,
+        diffusion_global_multiplier,
+        &DiffusionOptions::default(),
+        Some(&mut stats),
+    );
+    stats_data_safe.write_from(&stats);
+    result
+}
+
+/// same as perform_parallel_diffusion, with optional diffusion behaviors.
+/// when diagnostics_data is not null, mass conservation diagnostics are written into it.
+/// returns false without diffusing if options_data is invalid or diagnostics_data has an unexpected struct_size.
+///     returns false after diffusing if the branch symbols in the string do not match, as perform_parallel_diffusion does
+#[no_mangle]
+pub extern "C" fn perform_parallel_diffusion_with_options(
+    source_data: *mut SymbolStringInterop,
+    target_data: *mut SymbolStringInteropMut,
+    match_singleton_data: *mut NativeArrayInteropLSystemSingleSymbolMatchData,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    diffusion_steps: i32,
+    diffusion_global_multiplier: f32,
+    options_data: *const DiffusionOptionsInterop,
+    diagnostics_data: *mut DiffusionDiagnosticsInterop,
+) -> bool{
+
+    let (
+        source_data_safe,
+        mut target_data_safe,
+        match_singleton_data_safe,
+        options,
+        diagnostics_data_safe) =
+    unsafe {(
+        source_data.as_ref().unwrap().to_symbol_str(),
+        target_data.as_ref().unwrap().to_symbol_str(),
+        match_singleton_data.as_ref().unwrap().to_slice(),
+        options_data.as_ref().unwrap().to_options(),
+        diagnostics_data.as_mut(),
+        )};
+
+    let Some(options) = options else {
+        return false;
+    };
+    if diagnostics_data_safe.as_ref().is_some_and(|diagnostics| !diagnostics.has_expected_size()) {
+        return false;
+    }
+
+    let mut stats = DiffusionStats::new();
+    let result = perform_parallel_diffusion_internal(
+        &source_data_safe,
+        &mut target_data_safe,
+        match_singleton_data_safe,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        diffusion_steps,
+        diffusion_global_multiplier,
+        &options,
+        diagnostics_data_safe.is_some().then_some(&mut stats),
+    );
+    if let Some(diagnostics_data_safe) = diagnostics_data_safe {
+        diagnostics_data_safe.write_from(&stats);
+    }
+    result
+}
+
+/// the target is always written, returns false if the branch symbols in the source do not match
 pub fn perform_parallel_diffusion_internal(
     source_data: &SymbolString,
     target_data: &mut SymbolStringMut,
@@ -164,24 +407,29 @@ pub fn perform_parallel_diffusion_internal(
     branch_close_symbol: i32,
     diffusion_steps: i32,
     diffusion_global_multiplier: f32,
+    options: &DiffusionOptions,
+    mut stats: Option<&mut DiffusionStats>,
 ) -> bool {
 
-    let (mut diffusion_config, mut diffusion_amounts) = extract_edges_and_nodes_in_parallel(
+    let (mut diffusion_config, mut diffusion_amounts, branch_error) = extract_edges_and_nodes_in_parallel(
         source_data,
         target_data,
         match_singleton_data,
         diffusion_node_symbol,
         diffusion_amount_symbol,
         branch_open_symbol,
-        branch_close_symbol
+        branch_close_symbol,
+        options,
+        stats.as_deref_mut(),
     );
-    diffusion_config.diffusion_global_multiplier = diffusion_global_multiplier;
+    diffusion_config.configure(diffusion_global_multiplier, options);
     let diffuse_job_ref = diffusion_config.borrowed();
     let mut_diffuse_amount_data = &mut diffusion_amounts.borrowed_mut();
 
     diffuse_job_ref.diffuse_between(
         mut_diffuse_amount_data,
-        diffusion_steps);
+        diffusion_steps,
+        stats);
 
     apply_diffusion_results(
         diffuse_job_ref,
@@ -190,10 +438,11 @@ pub fn perform_parallel_diffusion_internal(
         diffusion_node_symbol,
         diffusion_amount_symbol,
         false);
-    true
+    branch_error.is_none()
 }
 
 
+/// returns false without diffusing if the branch symbols in the string do not match
 #[no_mangle]
 pub extern "C" fn perform_in_place_diffusion(
     source_data: *mut SymbolStringInteropMut,
@@ -211,10 +460,6 @@ pub extern "C" fn perform_in_place_diffusion(
             source_data.as_ref().unwrap().to_symbol_str(),
         )};
 
-    // TODO: doing this remap costs a lot, likely in alloc time.
-    //  its more ergonomic, but not ready for doing this conversion yet.
-    //let source_elements = to_elements(&source_data_safe);
-
     perform_in_place_diffusion_internal(
         &mut source_data_safe,
         diffusion_node_symbol,
@@ -223,9 +468,100 @@ pub extern "C" fn perform_in_place_diffusion(
         branch_close_symbol,
         diffusion_steps,
         diffusion_global_multiplier,
+        &DiffusionOptions::default(),
+        None,
     )
 }
 
+/// same as perform_in_place_diffusion, but also reports mass conservation diagnostics into stats_data
+#[no_mangle]
+pub extern "C" fn perform_in_place_diffusion_with_stats(
+    source_data: *mut SymbolStringInteropMut,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    diffusion_steps: i32,
+    diffusion_global_multiplier: f32,
+    stats_data: *mut DiffusionStatsInterop,
+) -> bool{
+
+    let (
+        mut source_data_safe,
+        stats_data_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            stats_data.as_mut().unwrap(),
+        )};
+
+    let mut stats = DiffusionStats::new();
+    let result = perform_in_place_diffusion_internal(
+        &mut source_data_safe,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        diffusion_steps,
+        diffusion_global_multiplier,
+        &DiffusionOptions::default(),
+        Some(&mut stats),
+    );
+    stats_data_safe.write_from(&stats);
+    result
+}
+
+/// same as perform_in_place_diffusion, with optional diffusion behaviors.
+/// when diagnostics_data is not null, mass conservation diagnostics are written into it.
+/// returns false without diffusing if options_data is invalid or diagnostics_data has an unexpected struct_size
+///     or if the branch symbols in the string do not match
+#[no_mangle]
+pub extern "C" fn perform_in_place_diffusion_with_options(
+    source_data: *mut SymbolStringInteropMut,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    diffusion_steps: i32,
+    diffusion_global_multiplier: f32,
+    options_data: *const DiffusionOptionsInterop,
+    diagnostics_data: *mut DiffusionDiagnosticsInterop,
+) -> bool{
+
+    let (
+        mut source_data_safe,
+        options,
+        diagnostics_data_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            options_data.as_ref().unwrap().to_options(),
+            diagnostics_data.as_mut(),
+        )};
+
+    let Some(options) = options else {
+        return false;
+    };
+    if diagnostics_data_safe.as_ref().is_some_and(|diagnostics| !diagnostics.has_expected_size()) {
+        return false;
+    }
+
+    let mut stats = DiffusionStats::new();
+    let result = perform_in_place_diffusion_internal(
+        &mut source_data_safe,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        diffusion_steps,
+        diffusion_global_multiplier,
+        &options,
+        diagnostics_data_safe.is_some().then_some(&mut stats),
+    );
+    if let Some(diagnostics_data_safe) = diagnostics_data_safe {
+        diagnostics_data_safe.write_from(&stats);
+    }
+    result
+}
+
 pub fn perform_in_place_diffusion_internal(
     source_data: &mut SymbolStringMut,
     diffusion_node_symbol: i32,
@@ -234,22 +570,421 @@ pub fn perform_in_place_diffusion_internal(
     branch_close_symbol: i32,
     diffusion_steps: i32,
     diffusion_global_multiplier: f32,
+    options: &DiffusionOptions,
+    mut stats: Option<&mut DiffusionStats>,
 ) -> bool {
 
-    let (mut diffusion_config, mut diffusion_amounts) = extract_edges_and_nodes_in_place(
+    let Ok((mut diffusion_config, mut diffusion_amounts)) = extract_edges_and_nodes_in_place(
         source_data,
         diffusion_node_symbol,
         diffusion_amount_symbol,
         branch_open_symbol,
-        branch_close_symbol
+        branch_close_symbol,
+        options,
+        stats.as_deref_mut(),
+    ) else {
+        return false;
+    };
+    diffusion_config.configure(diffusion_global_multiplier, options);
+    let diffuse_job_ref = diffusion_config.borrowed();
+    let mut_diffuse_amount_data = &mut diffusion_amounts.borrowed_mut();
+
+    diffuse_job_ref.diffuse_between(
+        mut_diffuse_amount_data,
+        diffusion_steps,
+        stats);
+
+    apply_diffusion_results(
+        diffuse_job_ref,
+        mut_diffuse_amount_data,
+        source_data,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        true);
+    true
+}
+native_array_interop!(SymbolStringInteropMut, NativeArrayInteropSymbolStringInteropMut, NativeArrayInteropSymbolStringInteropMutMut);
+native_array_interop!(ForestEdge, NativeArrayInteropForestEdge, NativeArrayInteropForestEdgeMut);
+
+/// diffuse across several symbol strings at once, such as every plant managed by one coordinator.
+/// each string is diffused in place, and resources also flow across the forest edges between strings.
+/// strings whose branch symbols do not match are left out of the forest and not modified,
+///     and forest edges which do not point at a diffusion node are skipped. both are reported in diagnostics_data.
+/// returns false without diffusing if options_data is invalid or diagnostics_data has an unexpected struct_size
+#[no_mangle]
+pub extern "C" fn perform_in_place_forest_diffusion(
+    source_strings: *mut NativeArrayInteropSymbolStringInteropMutMut,
+    forest_edges: *const NativeArrayInteropForestEdge,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    diffusion_steps: i32,
+    diffusion_global_multiplier: f32,
+    options_data: *const DiffusionOptionsInterop,
+    diagnostics_data: *mut DiffusionDiagnosticsInterop,
+) -> bool{
+
+    let (
+        source_strings_safe,
+        forest_edges_safe,
+        options,
+        diagnostics_data_safe) =
+        unsafe {(
+            source_strings.as_ref().unwrap().to_slice(),
+            forest_edges.as_ref().unwrap().to_slice(),
+            options_data.as_ref().unwrap().to_options(),
+            diagnostics_data.as_mut(),
+        )};
+
+    let Some(options) = options else {
+        return false;
+    };
+    if diagnostics_data_safe.as_ref().is_some_and(|diagnostics| !diagnostics.has_expected_size()) {
+        return false;
+    }
+
+    let mut source_strings_safe: Vec<SymbolStringMut> = source_strings_safe.iter()
+        .map(|source_string| source_string.to_symbol_str())
+        .collect();
+
+    let mut stats = DiffusionStats::new();
+    let forest_report = perform_in_place_forest_diffusion_internal(
+        &mut source_strings_safe,
+        forest_edges_safe,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        diffusion_steps,
+        diffusion_global_multiplier,
+        &options,
+        diagnostics_data_safe.is_some().then_some(&mut stats),
+    );
+    if let Some(diagnostics_data_safe) = diagnostics_data_safe {
+        diagnostics_data_safe.write_from(&stats);
+        diagnostics_data_safe.write_forest_report(&forest_report);
+    }
+    true
+}
+
+pub fn perform_in_place_forest_diffusion_internal(
+    source_strings: &mut [SymbolStringMut],
+    forest_edges: &[ForestEdge],
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    diffusion_steps: i32,
+    diffusion_global_multiplier: f32,
+    options: &DiffusionOptions,
+    mut stats: Option<&mut DiffusionStats>,
+) -> ForestReport {
+
+    let mut forest_report = ForestReport::default();
+    let graphs = source_strings.iter_mut()
+        .enumerate()
+        .map(|(string_index, source_string)| {
+            let graph = extract_edges_and_nodes_in_place(
+                source_string,
+                diffusion_node_symbol,
+                diffusion_amount_symbol,
+                branch_open_symbol,
+                branch_close_symbol,
+                options,
+                stats.as_deref_mut(),
+            );
+            if graph.is_err() {
+                forest_report.unbalanced_strings.push(string_index);
+            }
+            graph.ok()
+        })
+        .collect();
+    let Some(mut forest) = merge_graphs(graphs) else {
+        forest_report.unconnected_edges = (0..forest_edges.len()).collect();
+        return forest_report;
+    };
+    forest_report.unconnected_edges = forest.connect(forest_edges);
+
+    let DiffusionForest {
+        job: mut diffusion_config,
+        amounts: mut diffusion_amounts,
+        node_ranges,
+    } = forest;
+    diffusion_config.configure(diffusion_global_multiplier, options);
+    let diffuse_job_ref = diffusion_config.borrowed();
+    let mut_diffuse_amount_data = &mut diffusion_amounts.borrowed_mut();
+
+    diffuse_job_ref.diffuse_between(
+        mut_diffuse_amount_data,
+        diffusion_steps,
+        stats);
+
+    for (string_index, (source_string, node_range)) in source_strings.iter_mut().zip(node_ranges).enumerate() {
+        if forest_report.unbalanced_strings.contains(&string_index) {
+            continue;
+        }
+        apply_diffusion_results(
+            diffuse_job_ref.subset(node_range),
+            mut_diffuse_amount_data,
+            source_string,
+            diffusion_node_symbol,
+            diffusion_amount_symbol,
+            true);
+    }
+    forest_report
+}
+/// one plant of a parallel forest step: the string before the step, the string being written, and the match data between them
+#[repr(C)]
+pub struct ParallelDiffusionStringInterop {
+    pub source_data: SymbolStringInterop,
+    pub target_data: SymbolStringInteropMut,
+    pub match_singleton_data: NativeArrayInteropLSystemSingleSymbolMatchData,
+}
+
+impl<'a> ParallelDiffusionStringInterop {
+    pub fn to_parallel_string(&self) -> ParallelDiffusionString<'a> {
+        ParallelDiffusionString {
+            source_data: self.source_data.to_symbol_str(),
+            target_data: self.target_data.to_symbol_str(),
+            match_singleton_data: self.match_singleton_data.to_slice(),
+        }
+    }
+}
+
+pub struct ParallelDiffusionString<'a> {
+    pub source_data: SymbolString<'a>,
+    pub target_data: SymbolStringMut<'a>,
+    pub match_singleton_data: &'a [LSystemSingleSymbolMatchData],
+}
+
+native_array_interop!(ParallelDiffusionStringInterop, NativeArrayInteropParallelDiffusionStringInterop, NativeArrayInteropParallelDiffusionStringInteropMut);
+
+/// same as perform_in_place_forest_diffusion, but diffuses from each source string into its target string during a rewrite step,
+///     like perform_parallel_diffusion. forest edges index diffusion node symbols in the source strings.
+/// strings whose branch symbols do not match are still diffused into their targets, as perform_parallel_diffusion does,
+///     and forest edges which do not point at a diffusion node are skipped. both are reported in diagnostics_data.
+/// returns false without diffusing if options_data is invalid or diagnostics_data has an unexpected struct_size
+#[no_mangle]
+pub extern "C" fn perform_parallel_forest_diffusion(
+    strings: *mut NativeArrayInteropParallelDiffusionStringInteropMut,
+    forest_edges: *const NativeArrayInteropForestEdge,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    diffusion_steps: i32,
+    diffusion_global_multiplier: f32,
+    options_data: *const DiffusionOptionsInterop,
+    diagnostics_data: *mut DiffusionDiagnosticsInterop,
+) -> bool{
+
+    let (
+        strings_safe,
+        forest_edges_safe,
+        options,
+        diagnostics_data_safe) =
+        unsafe {(
+            strings.as_ref().unwrap().to_slice(),
+            forest_edges.as_ref().unwrap().to_slice(),
+            options_data.as_ref().unwrap().to_options(),
+            diagnostics_data.as_mut(),
+        )};
+
+    let Some(options) = options else {
+        return false;
+    };
+    if diagnostics_data_safe.as_ref().is_some_and(|diagnostics| !diagnostics.has_expected_size()) {
+        return false;
+    }
+
+    let mut strings_safe: Vec<ParallelDiffusionString> = strings_safe.iter()
+        .map(|string| string.to_parallel_string())
+        .collect();
+
+    let mut stats = DiffusionStats::new();
+    let forest_report = perform_parallel_forest_diffusion_internal(
+        &mut strings_safe,
+        forest_edges_safe,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        diffusion_steps,
+        diffusion_global_multiplier,
+        &options,
+        diagnostics_data_safe.is_some().then_some(&mut stats),
     );
-    diffusion_config.diffusion_global_multiplier = diffusion_global_multiplier;
+    if let Some(diagnostics_data_safe) = diagnostics_data_safe {
+        diagnostics_data_safe.write_from(&stats);
+        diagnostics_data_safe.write_forest_report(&forest_report);
+    }
+    true
+}
+
+pub fn perform_parallel_forest_diffusion_internal(
+    strings: &mut [ParallelDiffusionString],
+    forest_edges: &[ForestEdge],
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    diffusion_steps: i32,
+    diffusion_global_multiplier: f32,
+    options: &DiffusionOptions,
+    mut stats: Option<&mut DiffusionStats>,
+) -> ForestReport {
+
+    let mut forest_report = ForestReport::default();
+    let graphs = strings.iter_mut()
+        .enumerate()
+        .map(|(string_index, string)| {
+            let (diffusion_job, diffusion_amounts, branch_error) = extract_edges_and_nodes_in_parallel(
+                &string.source_data,
+                &mut string.target_data,
+                string.match_singleton_data,
+                diffusion_node_symbol,
+                diffusion_amount_symbol,
+                branch_open_symbol,
+                branch_close_symbol,
+                options,
+                stats.as_deref_mut(),
+            );
+            if branch_error.is_some() {
+                forest_report.unbalanced_strings.push(string_index);
+            }
+            Some((diffusion_job, diffusion_amounts))
+        })
+        .collect();
+    let Some(mut forest) = merge_graphs(graphs) else {
+        forest_report.unconnected_edges = (0..forest_edges.len()).collect();
+        return forest_report;
+    };
+    forest_report.unconnected_edges = forest.connect(forest_edges);
+
+    let DiffusionForest {
+        job: mut diffusion_config,
+        amounts: mut diffusion_amounts,
+        node_ranges,
+    } = forest;
+    diffusion_config.configure(diffusion_global_multiplier, options);
     let diffuse_job_ref = diffusion_config.borrowed();
     let mut_diffuse_amount_data = &mut diffusion_amounts.borrowed_mut();
 
     diffuse_job_ref.diffuse_between(
         mut_diffuse_amount_data,
-        diffusion_steps);
+        diffusion_steps,
+        stats);
+
+    for (string, node_range) in strings.iter_mut().zip(node_ranges) {
+        apply_diffusion_results(
+            diffuse_job_ref.subset(node_range),
+            mut_diffuse_amount_data,
+            &mut string.target_data,
+            diffusion_node_symbol,
+            diffusion_amount_symbol,
+            false);
+    }
+    forest_report
+}
+native_array_interop!(Vec3, NativeArrayInteropVec3, NativeArrayInteropVec3Mut);
+native_array_interop!(VoxelExchangeChannel, NativeArrayInteropVoxelExchangeChannel, NativeArrayInteropVoxelExchangeChannelMut);
+
+/// exchange resources between the diffusion nodes of the string and the voxels which contain them, in place.
+/// node_positions is indexed by symbol index, only the positions of diffusion node symbols are read.
+/// returns false if any channel points at a voxel layer which does not exist, those channels are skipped.
+///     also returns false without exchanging if options_data is invalid or diagnostics_data has an unexpected struct_size
+///     or if the branch symbols in the string do not match
+#[no_mangle]
+pub extern "C" fn perform_in_place_voxel_exchange(
+    source_data: *mut SymbolStringInteropMut,
+    node_positions: *const NativeArrayInteropVec3,
+    layer_data: *mut VoxelWorldVolumetricLayerDataInterop,
+    exchange_channels: *const NativeArrayInteropVoxelExchangeChannel,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    options_data: *const DiffusionOptionsInterop,
+    diagnostics_data: *mut DiffusionDiagnosticsInterop,
+) -> bool{
+
+    let (
+        mut source_data_safe,
+        node_positions_safe,
+        mut layer_data_safe,
+        exchange_channels_safe,
+        options,
+        diagnostics_data_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            node_positions.as_ref().unwrap().to_slice(),
+            layer_data.as_ref().unwrap().to_layer_data(),
+            exchange_channels.as_ref().unwrap().to_slice(),
+            options_data.as_ref().unwrap().to_options(),
+            diagnostics_data.as_mut(),
+        )};
+
+    let Some(options) = options else {
+        return false;
+    };
+    if diagnostics_data_safe.as_ref().is_some_and(|diagnostics| !diagnostics.has_expected_size()) {
+        return false;
+    }
+
+    let mut stats = DiffusionStats::new();
+    let result = perform_in_place_voxel_exchange_internal(
+        &mut source_data_safe,
+        node_positions_safe,
+        &mut layer_data_safe,
+        exchange_channels_safe,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        &options,
+        diagnostics_data_safe.is_some().then_some(&mut stats),
+    );
+    if let Some(diagnostics_data_safe) = diagnostics_data_safe {
+        diagnostics_data_safe.write_from(&stats);
+    }
+    result
+}
+
+pub fn perform_in_place_voxel_exchange_internal(
+    source_data: &mut SymbolStringMut,
+    node_positions: &[Vec3],
+    layer_data: &mut VoxelWorldVolumetricLayerData,
+    exchange_channels: &[VoxelExchangeChannel],
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    options: &DiffusionOptions,
+    mut stats: Option<&mut DiffusionStats>,
+) -> bool {
+
+    let Ok((diffusion_config, mut diffusion_amounts)) = extract_edges_and_nodes_in_place(
+        source_data,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        options,
+        stats.as_deref_mut(),
+    ) else {
+        return false;
+    };
+    let diffuse_job_ref = diffusion_config.borrowed();
+    let mut_diffuse_amount_data = &mut diffusion_amounts.borrowed_mut();
+
+    let all_channels_valid = exchange_with_voxels(
+        diffuse_job_ref,
+        mut_diffuse_amount_data.get_latest_data_mut(),
+        node_positions,
+        layer_data,
+        exchange_channels,
+        stats);
 
     apply_diffusion_results(
         diffuse_job_ref,
@@ -258,5 +993,141 @@ pub fn perform_in_place_diffusion_internal(
         diffusion_node_symbol,
         diffusion_amount_symbol,
         true);
+    all_channels_valid
+}
+
+/// write the diffusion graph extracted from the string as utf-8 text into the caller allocated output buffer, for debugging.
+///     the string is not modified.
+/// written_length is always set to the length of the full text. returns false without writing anything if the buffer is too small.
+///     if options_data is invalid, or the branch symbols in the string do not match,
+///     written_length is set to 0 and false is returned
+#[no_mangle]
+pub extern "C" fn export_diffusion_graph_debug(
+    source_data: *const SymbolStringInterop,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    options_data: *const DiffusionOptionsInterop,
+    format: DiffusionGraphFormat,
+    output: *mut NativeArrayInteropu8Mut,
+    written_length: *mut i32,
+) -> bool{
+
+    let (
+        source_data_safe,
+        options,
+        output_safe,
+        written_length_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            options_data.as_ref().unwrap().to_options(),
+            output.as_ref().unwrap().to_slice(),
+            written_length.as_mut().unwrap(),
+        )};
+
+    let Some(options) = options else {
+        *written_length_safe = 0;
+        return false;
+    };
+    let exported = export_diffusion_graph_internal(
+        &source_data_safe,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        &options,
+        format,
+    );
+    let Ok(exported) = exported else {
+        *written_length_safe = 0;
+        return false;
+    };
+    *written_length_safe = exported.len() as i32;
+    if exported.len() > output_safe.len() {
+        return false;
+    }
+    output_safe[..exported.len()].copy_from_slice(exported.as_bytes());
     true
-}
\ No newline at end of file
+}
+
+pub fn export_diffusion_graph_internal(
+    source_data: &SymbolString,
+    diffusion_node_symbol: i32,
+    diffusion_amount_symbol: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    options: &DiffusionOptions,
+    format: DiffusionGraphFormat,
+) -> Result<String, BranchTreeError> {
+
+    let (diffusion_config, mut diffusion_amounts) = extract_edges_and_nodes_read_only(
+        source_data,
+        diffusion_node_symbol,
+        diffusion_amount_symbol,
+        branch_open_symbol,
+        branch_close_symbol,
+        options,
+        None,
+    )?;
+    Ok(export_diffusion_graph(
+        &diffusion_config,
+        diffusion_amounts.borrowed_mut().get_latest_data(),
+        format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::extract_graph::extract_edges_and_nodes_read_only;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+
+    #[test]
+    fn diagnostics_clear_entries_past_the_observed_counts() {
+        let symbol_string = parse_symbol_string("1(0.5,0,10,4,10) 1(0.5,0,10) 1(0.5,0,10,0,10)", None).unwrap();
+        let mut stats = DiffusionStats::new();
+        extract_edges_and_nodes_read_only(&symbol_string.borrow(), 1, 2, -10, -11, &DiffusionOptions::default(), Some(&mut stats)).unwrap();
+        assert_eq!(stats.mismatched_edges.len(), 2);
+
+        // left over from an earlier call with more edges and resources
+        let stale_edge = MismatchedResourceEdge {
+            node_symbol_index: 9,
+            parent_symbol_index: 8,
+            node_resource_types: 7,
+            parent_resource_types: 6,
+        };
+        let stale_resource = DiffusionResourceStats {
+            total_before_extraction: 5.0,
+            ..Default::default()
+        };
+        let mut mismatched_edges = vec![stale_edge; 4];
+        let mut resources = vec![stale_resource; 3];
+        let mut unbalanced_strings = vec![3; 2];
+        let mut unconnected_edges = vec![4; 2];
+        let mut diagnostics = DiffusionDiagnosticsInterop {
+            struct_size: size_of::<DiffusionDiagnosticsInterop>() as i32,
+            stats: DiffusionStatsInterop {
+                resources: NativeArrayInteropDiffusionResourceStatsMut { data: resources.as_mut_ptr(), len: resources.len() as i32 },
+                observed_resource_types: 0,
+            },
+            mismatched_edges: NativeArrayInteropMismatchedResourceEdgeMut { data: mismatched_edges.as_mut_ptr(), len: mismatched_edges.len() as i32 },
+            observed_mismatched_edges: 0,
+            unbalanced_strings: NativeArrayInteropi32Mut { data: unbalanced_strings.as_mut_ptr(), len: unbalanced_strings.len() as i32 },
+            observed_unbalanced_strings: 0,
+            unconnected_edges: NativeArrayInteropi32Mut { data: unconnected_edges.as_mut_ptr(), len: unconnected_edges.len() as i32 },
+            observed_unconnected_edges: 0,
+        };
+        assert!(diagnostics.has_expected_size());
+        diagnostics.write_from(&stats);
+
+        assert_eq!(diagnostics.observed_mismatched_edges, 2);
+        assert_eq!(diagnostics.stats.observed_resource_types, 2);
+        assert_eq!(diagnostics.observed_unbalanced_strings, 0);
+        assert_eq!(diagnostics.observed_unconnected_edges, 0);
+        let edge_fields = |edge: &MismatchedResourceEdge| (edge.node_symbol_index, edge.parent_symbol_index, edge.node_resource_types, edge.parent_resource_types);
+        assert_eq!(mismatched_edges.iter().map(edge_fields).collect::<Vec<_>>(), vec![(1, 0, 1, 2), (2, 1, 2, 1), (0, 0, 0, 0), (0, 0, 0, 0)]);
+        assert_eq!(resources.iter().map(|resource| resource.total_before_extraction).collect::<Vec<_>>(), vec![0.0, 4.0, 0.0]);
+        assert_eq!(unbalanced_strings, vec![-1, -1]);
+        assert_eq!(unconnected_edges, vec![-1, -1]);
+    }
+}
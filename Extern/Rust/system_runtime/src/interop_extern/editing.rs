@@ -0,0 +1,188 @@
+use std::ops::Range;
+use crate::diffusion::extract_graph::{SymbolString, SymbolStringMut};
+use crate::diffusion::symbol_element_remap::SymbolStringOwned;
+use crate::diffusion::symbol_string_edit::{graft, replace_range, subtree_range, SymbolStringEditError};
+use crate::interop_extern::diffusion::{SymbolStringInterop, SymbolStringInteropMut};
+
+/// copy the edited string into the target, packing parameters from the start of the parameter array.
+///     the written counts are always set. returns false without writing anything if the target is too small
+fn write_to_target(
+    edited: &SymbolStringOwned,
+    target: &mut SymbolStringMut,
+    written_symbol_count: &mut i32,
+    written_parameter_count: &mut i32) -> bool {
+
+    *written_symbol_count = edited.symbols.len() as i32;
+    *written_parameter_count = edited.parameters.len() as i32;
+    if target.symbols.len() < edited.symbols.len() ||
+        target.param_indexing.len() < edited.param_indexing.len() ||
+        target.parameters.len() < edited.parameters.len() {
+        return false;
+    }
+
+    target.symbols[..edited.symbols.len()].copy_from_slice(&edited.symbols);
+    target.param_indexing[..edited.param_indexing.len()].copy_from_slice(&edited.param_indexing);
+    target.parameters[..edited.parameters.len()].copy_from_slice(&edited.parameters);
+    true
+}
+
+/// prune the subtree starting at the index of the source, writing the result into the target.
+///     the target must not overlap the source. written counts are always set, to the size of the edited string or to 0 if the edit fails
+#[no_mangle]
+pub extern "C" fn remove_symbol_subtree(
+    source_data: *const SymbolStringInterop,
+    index: i32,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    target_data: *mut SymbolStringInteropMut,
+    written_symbol_count: *mut i32,
+    written_parameter_count: *mut i32,
+) -> SymbolStringEditError{
+
+    let (
+        source_data_safe,
+        mut target_data_safe,
+        written_symbol_count_safe,
+        written_parameter_count_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            target_data.as_ref().unwrap().to_symbol_str(),
+            written_symbol_count.as_mut().unwrap(),
+            written_parameter_count.as_mut().unwrap(),
+        )};
+    *written_symbol_count_safe = 0;
+    *written_parameter_count_safe = 0;
+
+    let Ok(index) = usize::try_from(index) else {
+        return SymbolStringEditError::IndexOutOfRange;
+    };
+    let empty = SymbolString {
+        symbols: &[],
+        param_indexing: &[],
+        parameters: &[],
+    };
+    let edited = match subtree_range(&source_data_safe, index, branch_open_symbol, branch_close_symbol)
+        .and_then(|removed| replace_range(&source_data_safe, removed, &empty, branch_open_symbol, branch_close_symbol)) {
+        Ok(edited) => edited,
+        Err(error) => return error,
+    };
+    if !write_to_target(&edited, &mut target_data_safe, written_symbol_count_safe, written_parameter_count_safe) {
+        return SymbolStringEditError::TargetTooSmall;
+    }
+    SymbolStringEditError::None
+}
+
+/// insert every symbol of the inserted string before the index of the source, writing the result into the target.
+///     the target must not overlap the source. written counts are always set, to the size of the edited string or to 0 if the edit fails
+#[no_mangle]
+pub extern "C" fn insert_symbol_string(
+    source_data: *const SymbolStringInterop,
+    index: i32,
+    inserted_data: *const SymbolStringInterop,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    target_data: *mut SymbolStringInteropMut,
+    written_symbol_count: *mut i32,
+    written_parameter_count: *mut i32,
+) -> SymbolStringEditError{
+
+    replace_symbol_range(
+        source_data,
+        index,
+        index,
+        inserted_data,
+        branch_open_symbol,
+        branch_close_symbol,
+        target_data,
+        written_symbol_count,
+        written_parameter_count,
+    )
+}
+
+/// replace the symbols from start up to but not including end with the replacement, writing the result into the target.
+///     the target must not overlap the source. written counts are always set, to the size of the edited string or to 0 if the edit fails
+#[no_mangle]
+pub extern "C" fn replace_symbol_range(
+    source_data: *const SymbolStringInterop,
+    start: i32,
+    end: i32,
+    replacement_data: *const SymbolStringInterop,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    target_data: *mut SymbolStringInteropMut,
+    written_symbol_count: *mut i32,
+    written_parameter_count: *mut i32,
+) -> SymbolStringEditError{
+
+    let (
+        source_data_safe,
+        replacement_data_safe,
+        mut target_data_safe,
+        written_symbol_count_safe,
+        written_parameter_count_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            replacement_data.as_ref().unwrap().to_symbol_str(),
+            target_data.as_ref().unwrap().to_symbol_str(),
+            written_symbol_count.as_mut().unwrap(),
+            written_parameter_count.as_mut().unwrap(),
+        )};
+    *written_symbol_count_safe = 0;
+    *written_parameter_count_safe = 0;
+
+    let range: Range<usize> = match (usize::try_from(start), usize::try_from(end)) {
+        (Ok(start), Ok(end)) => start..end,
+        _ => return SymbolStringEditError::IndexOutOfRange,
+    };
+    let edited = match replace_range(&source_data_safe, range, &replacement_data_safe, branch_open_symbol, branch_close_symbol) {
+        Ok(edited) => edited,
+        Err(error) => return error,
+    };
+    if !write_to_target(&edited, &mut target_data_safe, written_symbol_count_safe, written_parameter_count_safe) {
+        return SymbolStringEditError::TargetTooSmall;
+    }
+    SymbolStringEditError::None
+}
+
+/// insert the scion as a new branch before the index of the source, wrapped in branch symbols, writing the result into the target.
+///     the target must not overlap the source. written counts are always set, to the size of the edited string or to 0 if the edit fails
+#[no_mangle]
+pub extern "C" fn graft_symbol_string(
+    source_data: *const SymbolStringInterop,
+    index: i32,
+    scion_data: *const SymbolStringInterop,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    target_data: *mut SymbolStringInteropMut,
+    written_symbol_count: *mut i32,
+    written_parameter_count: *mut i32,
+) -> SymbolStringEditError{
+
+    let (
+        source_data_safe,
+        scion_data_safe,
+        mut target_data_safe,
+        written_symbol_count_safe,
+        written_parameter_count_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            scion_data.as_ref().unwrap().to_symbol_str(),
+            target_data.as_ref().unwrap().to_symbol_str(),
+            written_symbol_count.as_mut().unwrap(),
+            written_parameter_count.as_mut().unwrap(),
+        )};
+    *written_symbol_count_safe = 0;
+    *written_parameter_count_safe = 0;
+
+    let Ok(index) = usize::try_from(index) else {
+        return SymbolStringEditError::IndexOutOfRange;
+    };
+    let edited = match graft(&source_data_safe, index, &scion_data_safe, branch_open_symbol, branch_close_symbol) {
+        Ok(edited) => edited,
+        Err(error) => return error,
+    };
+    if !write_to_target(&edited, &mut target_data_safe, written_symbol_count_safe, written_parameter_count_safe) {
+        return SymbolStringEditError::TargetTooSmall;
+    }
+    SymbolStringEditError::None
+}
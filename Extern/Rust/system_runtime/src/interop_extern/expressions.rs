@@ -35,6 +35,7 @@ pub enum OperatorType
 
 
 #[repr(C)]
+#[derive(Copy, Clone, Debug)]
 pub struct OperatorDefinition {
     pub operator_type: OperatorType,
     /// is set when the node has a constant value
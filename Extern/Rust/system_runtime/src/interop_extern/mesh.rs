@@ -0,0 +1,111 @@
+use crate::diffusion::branch_tree::{BranchTree, BranchTreeError};
+use crate::interop_extern::data::{native_array_interop, NativeArrayInteropi32};
+use crate::interop_extern::diffusion::SymbolStringInterop;
+use crate::interop_extern::turtle::{NativeArrayInteropTurtleOrganInstance, NativeArrayInteropTurtleStemInstance};
+use crate::math::Bounds;
+use crate::mesh::bounds::{branch_bounds, plant_bounds};
+use crate::mesh::builder::StemClass;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlantBoundsError {
+    None = 0,
+    /// a branch close symbol has no matching branch open symbol
+    UnmatchedBranchClose = 1,
+    /// a branch open symbol is never closed
+    UnclosedBranchOpen = 2,
+    /// the caller allocated buffer is too small to hold every branch
+    TargetTooSmall = 3,
+}
+
+native_array_interop!(Bounds, NativeArrayInteropBounds, NativeArrayInteropBoundsMut);
+
+/// stem classes which only carry what bounding needs. the material is not read
+fn stem_classes_from(stem_radial_resolutions: &[i32]) -> Vec<StemClass> {
+    stem_radial_resolutions.iter()
+        .map(|radial_resolution| StemClass {
+            material_index: 0,
+            radial_resolution: (*radial_resolution).clamp(0, u16::MAX as i32) as u16,
+        })
+        .collect()
+}
+
+/// compute the bounds of the mesh which would be built from the turtle output, writing them into bounds.
+///     template_bounds is indexed by organ template index. stems may be null, when stems are not meshed.
+///     stem_radial_resolutions is indexed by stem class index, stems are skipped when their class is missing or has a resolution of 0,
+///     the same as when meshing. it may be null when stems is null. empty bounds have min above max
+#[no_mangle]
+pub extern "C" fn compute_plant_bounds(
+    organs: *const NativeArrayInteropTurtleOrganInstance,
+    stems: *const NativeArrayInteropTurtleStemInstance,
+    stem_radial_resolutions: *const NativeArrayInteropi32,
+    template_bounds: *const NativeArrayInteropBounds,
+    bounds: *mut Bounds,
+) {
+    let (organs_safe, stems_safe, stem_radial_resolutions_safe, template_bounds_safe, bounds_safe) =
+        unsafe {(
+            organs.as_ref().unwrap().to_slice(),
+            stems.as_ref().map(|stems| stems.to_slice()).unwrap_or_default(),
+            stem_radial_resolutions.as_ref().map(|resolutions| resolutions.to_slice()).unwrap_or_default(),
+            template_bounds.as_ref().unwrap().to_slice(),
+            bounds.as_mut().unwrap(),
+        )};
+
+    let stem_classes = stem_classes_from(stem_radial_resolutions_safe);
+    *bounds_safe = plant_bounds(organs_safe, stems_safe, &stem_classes, template_bounds_safe);
+}
+
+/// compute the bounds of every branch of the string the turtle output was interpreted from, including the branches inside it.
+///     bounds are indexed by branch id in the order branch open symbols appear, with the whole plant at index 0.
+///     stems, stem_symbol_indexes and stem_radial_resolutions are as in compute_plant_bounds and interpret_turtle_to_buffer_with_stem_symbols,
+///     and may all be null when stems are not meshed.
+///     written_branch_count is always set when the string is valid. writes nothing if the target is too small
+#[no_mangle]
+pub extern "C" fn compute_plant_branch_bounds(
+    source_data: *const SymbolStringInterop,
+    branch_open_symbol: i32,
+    branch_close_symbol: i32,
+    organs: *const NativeArrayInteropTurtleOrganInstance,
+    stems: *const NativeArrayInteropTurtleStemInstance,
+    stem_symbol_indexes: *const NativeArrayInteropi32,
+    stem_radial_resolutions: *const NativeArrayInteropi32,
+    template_bounds: *const NativeArrayInteropBounds,
+    target: *mut NativeArrayInteropBoundsMut,
+    written_branch_count: *mut i32,
+) -> PlantBoundsError {
+    let (
+        source_data_safe,
+        organs_safe,
+        stems_safe,
+        stem_symbol_indexes_safe,
+        stem_radial_resolutions_safe,
+        template_bounds_safe,
+        target_safe,
+        written_branch_count_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            organs.as_ref().unwrap().to_slice(),
+            stems.as_ref().map(|stems| stems.to_slice()).unwrap_or_default(),
+            stem_symbol_indexes.as_ref().map(|indexes| indexes.to_slice()).unwrap_or_default(),
+            stem_radial_resolutions.as_ref().map(|resolutions| resolutions.to_slice()).unwrap_or_default(),
+            template_bounds.as_ref().unwrap().to_slice(),
+            target.as_ref().unwrap().to_slice(),
+            written_branch_count.as_mut().unwrap(),
+        )};
+
+    // previous nodes are not used, so any symbol will do for the node symbol
+    let branch_tree = match BranchTree::new(&source_data_safe, branch_open_symbol, branch_close_symbol, branch_open_symbol) {
+        Ok(branch_tree) => branch_tree,
+        Err(BranchTreeError::UnmatchedClose(_)) => return PlantBoundsError::UnmatchedBranchClose,
+        Err(BranchTreeError::UnclosedOpen(_)) => return PlantBoundsError::UnclosedBranchOpen,
+    };
+    let stem_classes = stem_classes_from(stem_radial_resolutions_safe);
+    let bounds = branch_bounds(organs_safe, stems_safe, stem_symbol_indexes_safe, &stem_classes, template_bounds_safe, &branch_tree);
+
+    *written_branch_count_safe = bounds.len() as i32;
+    if target_safe.len() < bounds.len() {
+        return PlantBoundsError::TargetTooSmall;
+    }
+    target_safe[..bounds.len()].copy_from_slice(&bounds);
+    PlantBoundsError::None
+}
@@ -0,0 +1,68 @@
+use crate::lsystem::rewrite_trace::{trace_rewrite, RewriteTraceEntry};
+use crate::interop_extern::data::{native_array_interop, NativeArrayInteropf32, NativeArrayInteropf32Mut};
+use crate::interop_extern::diffusion::{NativeArrayInteropLSystemSingleSymbolMatchData, SymbolStringInterop};
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RewriteTraceError {
+    None = 0,
+    /// the caller allocated buffers are too small to hold the trace
+    TargetTooSmall = 1,
+}
+
+native_array_interop!(RewriteTraceEntry, NativeArrayInteropRewriteTraceEntry, NativeArrayInteropRewriteTraceEntryMut);
+
+/// record how every symbol of the source is rewritten by the unity match data, one entry per source symbol, as lsystem::rewrite_trace::trace_rewrite.
+/// call with the same match data passed to the rewrite step, after matching. strings rewritten natively are traced by rewrite_traced instead.
+/// the match data does not keep the values of rule conditions, so has_condition_value is false on every entry.
+/// captured_parameter_memory is the temporary parameter memory used while matching. it may be null along with
+///     captured_parameters_output and written_captured_parameter_count, when captured parameters are not needed.
+/// written counts are always set. writes nothing if either output is too small
+#[no_mangle]
+pub extern "C" fn trace_rewrite_step(
+    source_data: *const SymbolStringInterop,
+    match_singleton_data: *const NativeArrayInteropLSystemSingleSymbolMatchData,
+    captured_parameter_memory: *const NativeArrayInteropf32,
+    entry_output: *mut NativeArrayInteropRewriteTraceEntryMut,
+    written_entry_count: *mut i32,
+    captured_parameters_output: *mut NativeArrayInteropf32Mut,
+    written_captured_parameter_count: *mut i32,
+) -> RewriteTraceError{
+
+    let (
+        source_data_safe,
+        match_singleton_data_safe,
+        captured_parameter_memory_safe,
+        entry_output_safe,
+        written_entry_count_safe,
+        captured_parameters_output_safe,
+        written_captured_parameter_count_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            match_singleton_data.as_ref().unwrap().to_slice(),
+            captured_parameter_memory.as_ref().map(|memory| memory.to_slice()),
+            entry_output.as_ref().unwrap().to_slice(),
+            written_entry_count.as_mut().unwrap(),
+            captured_parameters_output.as_ref().map(|output| output.to_slice()),
+            written_captured_parameter_count.as_mut(),
+        )};
+
+    // captured parameters are only traced when there is somewhere to write them
+    let captured_parameter_memory_safe = captured_parameter_memory_safe.filter(|_| captured_parameters_output_safe.is_some());
+    let trace = trace_rewrite(&source_data_safe, match_singleton_data_safe, captured_parameter_memory_safe);
+
+    *written_entry_count_safe = trace.entries.len() as i32;
+    if let Some(written_captured_parameter_count_safe) = written_captured_parameter_count_safe {
+        *written_captured_parameter_count_safe = trace.captured_parameters.len() as i32;
+    }
+    let captured_fits = captured_parameters_output_safe.as_ref().is_none_or(|output| output.len() >= trace.captured_parameters.len());
+    if entry_output_safe.len() < trace.entries.len() || !captured_fits {
+        return RewriteTraceError::TargetTooSmall;
+    }
+
+    entry_output_safe[..trace.entries.len()].copy_from_slice(&trace.entries);
+    if let Some(captured_parameters_output_safe) = captured_parameters_output_safe {
+        captured_parameters_output_safe[..trace.captured_parameters.len()].copy_from_slice(&trace.captured_parameters);
+    }
+    RewriteTraceError::None
+}
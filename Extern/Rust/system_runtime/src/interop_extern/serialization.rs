@@ -0,0 +1,240 @@
+use crate::diffusion::symbol_string_binary::{read_symbol_string, read_symbol_string_sizes, SerializedSymbolString, SerializedSymbolStringSizes, SymbolStringMetadata, SymbolStringReadError, SymbolStringWriteError, write_symbol_string};
+use crate::diffusion::symbol_string_text::SymbolNames;
+use crate::interop_extern::data::{IndexesIn, JaggedIndexing, NativeArrayInteropi32, NativeArrayInteropi32Mut, NativeArrayInteropJaggedIndexing, NativeArrayInteropJaggedIndexingMut, NativeArrayInteropu8, NativeArrayInteropu8Mut};
+use crate::interop_extern::diffusion::{SymbolStringInterop, SymbolStringInteropMut};
+
+/// symbol names packed as utf-8 into a single byte array
+#[repr(C)]
+pub struct SymbolTableInterop {
+    pub symbols: NativeArrayInteropi32,
+    /// parallel to symbols, indexing into name_bytes
+    pub name_indexing: NativeArrayInteropJaggedIndexing,
+    pub name_bytes: NativeArrayInteropu8,
+}
+
+impl SymbolTableInterop {
+    pub fn to_symbol_names(&self) -> Result<SymbolNames, SymbolStringWriteError> {
+        symbol_names_from_table(self.symbols.to_slice(), self.name_indexing.to_slice(), self.name_bytes.to_slice())
+    }
+}
+
+/// a negative name index is read as an empty name
+fn symbol_names_from_table(
+    symbols: &[i32],
+    name_indexing: &[JaggedIndexing],
+    name_bytes: &[u8]) -> Result<SymbolNames, SymbolStringWriteError> {
+    if symbols.len() != name_indexing.len() {
+        return Err(SymbolStringWriteError::InvalidSymbolTable);
+    }
+    let mut names = SymbolNames::new();
+    for (symbol, name_indexing) in symbols.iter().zip(name_indexing) {
+        if name_indexing.index >= 0 && name_indexing.index as usize + name_indexing.length as usize > name_bytes.len() {
+            return Err(SymbolStringWriteError::InvalidSymbolTable);
+        }
+        let name = std::str::from_utf8(name_indexing.to_slice_ref(name_bytes))
+            .map_err(|_| SymbolStringWriteError::InvalidSymbolTable)?;
+        names.add(*symbol, name);
+    }
+    Ok(names)
+}
+
+#[repr(C)]
+pub struct SymbolTableInteropMut {
+    pub symbols: NativeArrayInteropi32Mut,
+    pub name_indexing: NativeArrayInteropJaggedIndexingMut,
+    pub name_bytes: NativeArrayInteropu8Mut,
+}
+
+impl SymbolTableInteropMut {
+    /// returns false without writing anything if the arrays are too small
+    pub fn write_from(&mut self, names: &SymbolNames) -> bool {
+        let (symbols, name_indexing, name_bytes) = (
+            self.symbols.to_slice(),
+            self.name_indexing.to_slice(),
+            self.name_bytes.to_slice(),
+        );
+        let total_name_bytes: usize = names.iter().map(|(_, name)| name.len()).sum();
+        if symbols.len() < names.len() || name_indexing.len() < names.len() || name_bytes.len() < total_name_bytes {
+            return false;
+        }
+
+        let mut name_start = 0;
+        for (entry_index, (symbol, name)) in names.iter().enumerate() {
+            symbols[entry_index] = symbol;
+            name_indexing[entry_index] = JaggedIndexing {
+                index: name_start as i32,
+                length: name.len() as u16,
+            };
+            name_bytes[name_start..name_start + name.len()].copy_from_slice(name.as_bytes());
+            name_start += name.len();
+        }
+        true
+    }
+}
+
+/// save the string into the caller allocated output buffer. metadata and symbol_table may be null.
+/// written_length is set to the length of the saved data, or 0 if it cannot be saved. nothing is written if the buffer is too small.
+///     returns InvalidSymbolTable without writing anything if a symbol table name is out of range or not valid utf-8
+#[no_mangle]
+pub extern "C" fn write_symbol_string_to_buffer(
+    source_data: *const SymbolStringInterop,
+    metadata: *const SymbolStringMetadata,
+    symbol_table: *const SymbolTableInterop,
+    output: *mut NativeArrayInteropu8Mut,
+    written_length: *mut i32,
+) -> SymbolStringWriteError{
+
+    let (
+        source_data_safe,
+        metadata_safe,
+        symbol_table_safe,
+        output_safe,
+        written_length_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            metadata.as_ref(),
+            symbol_table.as_ref(),
+            output.as_ref().unwrap().to_slice(),
+            written_length.as_mut().unwrap(),
+        )};
+
+    let names = match symbol_table_safe.map(|symbol_table| symbol_table.to_symbol_names()).transpose() {
+        Ok(names) => names,
+        Err(error) => {
+            *written_length_safe = 0;
+            return error;
+        }
+    };
+    let bytes = match write_symbol_string(&source_data_safe, metadata_safe, names.as_ref()) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            *written_length_safe = 0;
+            return error;
+        }
+    };
+    *written_length_safe = bytes.len() as i32;
+    if bytes.len() > output_safe.len() {
+        return SymbolStringWriteError::TargetTooSmall;
+    }
+    output_safe[..bytes.len()].copy_from_slice(&bytes);
+    SymbolStringWriteError::None
+}
+
+/// read the sizes of a saved string, so the caller can allocate buffers to read it into
+#[no_mangle]
+pub extern "C" fn read_symbol_string_sizes_from_buffer(
+    input: *const NativeArrayInteropu8,
+    sizes: *mut SerializedSymbolStringSizes,
+) -> SymbolStringReadError{
+
+    let (
+        input_safe,
+        sizes_safe) =
+        unsafe {(
+            input.as_ref().unwrap().to_slice(),
+            sizes.as_mut().unwrap(),
+        )};
+
+    match read_symbol_string_sizes(input_safe) {
+        Ok(read_sizes) => {
+            *sizes_safe = read_sizes;
+            SymbolStringReadError::None
+        }
+        Err(error) => error,
+    }
+}
+
+/// read a saved string into caller allocated arrays, sized by read_symbol_string_sizes_from_buffer.
+///     parameters are packed in symbol order from the start of the parameter array.
+/// metadata and symbol_table may be null, they are left untouched when the saved string does not carry them
+#[no_mangle]
+pub extern "C" fn read_symbol_string_from_buffer(
+    input: *const NativeArrayInteropu8,
+    target_data: *mut SymbolStringInteropMut,
+    metadata: *mut SymbolStringMetadata,
+    symbol_table: *mut SymbolTableInteropMut,
+) -> SymbolStringReadError{
+
+    let (
+        input_safe,
+        target_data_safe,
+        metadata_safe,
+        symbol_table_safe) =
+        unsafe {(
+            input.as_ref().unwrap().to_slice(),
+            target_data.as_ref().unwrap().to_symbol_str(),
+            metadata.as_mut(),
+            symbol_table.as_mut(),
+        )};
+
+    let SerializedSymbolString { symbol_string, metadata: read_metadata, names } = match read_symbol_string(input_safe) {
+        Ok(serialized) => serialized,
+        Err(error) => return error,
+    };
+    if target_data_safe.symbols.len() < symbol_string.symbols.len() ||
+        target_data_safe.param_indexing.len() < symbol_string.param_indexing.len() ||
+        target_data_safe.parameters.len() < symbol_string.parameters.len() {
+        return SymbolStringReadError::TargetTooSmall;
+    }
+    if let (Some(symbol_table_safe), Some(names)) = (symbol_table_safe, names) {
+        if !symbol_table_safe.write_from(&names) {
+            return SymbolStringReadError::TargetTooSmall;
+        }
+    }
+
+    let symbol_count = symbol_string.symbols.len();
+    target_data_safe.symbols[..symbol_count].copy_from_slice(&symbol_string.symbols);
+    target_data_safe.param_indexing[..symbol_count].copy_from_slice(&symbol_string.param_indexing);
+    target_data_safe.parameters[..symbol_string.parameters.len()].copy_from_slice(&symbol_string.parameters);
+    if let (Some(metadata_safe), Some(read_metadata)) = (metadata_safe, read_metadata) {
+        *metadata_safe = read_metadata;
+    }
+    SymbolStringReadError::None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_names_from_the_table() {
+        let names = symbol_names_from_table(
+            &[1, 2, 3],
+            &[JaggedIndexing { index: 0, length: 4 }, JaggedIndexing { index: 4, length: 2 }, JaggedIndexing { index: -1, length: 0 }],
+            "stemab".as_bytes()).unwrap();
+
+        assert_eq!(names.name_of(1), Some("stem"));
+        assert_eq!(names.name_of(2), Some("ab"));
+        assert_eq!(names.name_of(3), Some(""));
+    }
+
+    #[test]
+    fn names_past_the_name_bytes_are_invalid() {
+        let names = symbol_names_from_table(
+            &[1],
+            &[JaggedIndexing { index: 2, length: 3 }],
+            "stem".as_bytes());
+
+        assert_eq!(names.err(), Some(SymbolStringWriteError::InvalidSymbolTable));
+    }
+
+    #[test]
+    fn names_must_be_utf8() {
+        let names = symbol_names_from_table(
+            &[1],
+            &[JaggedIndexing { index: 0, length: 2 }],
+            &[0xC3, 0x28]);
+
+        assert_eq!(names.err(), Some(SymbolStringWriteError::InvalidSymbolTable));
+    }
+
+    #[test]
+    fn every_symbol_needs_a_name() {
+        let names = symbol_names_from_table(
+            &[1, 2],
+            &[JaggedIndexing { index: 0, length: 4 }],
+            "stem".as_bytes());
+
+        assert_eq!(names.err(), Some(SymbolStringWriteError::InvalidSymbolTable));
+    }
+}
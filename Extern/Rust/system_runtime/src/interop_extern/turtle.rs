@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use crate::diffusion::branch_tree::BranchTreeError;
+use crate::diffusion::extract_graph::SymbolString;
+use crate::interop_extern::data::{JaggedIndexing, native_array_interop, NativeArrayInteropi32, NativeArrayInteropi32Mut};
+use crate::interop_extern::diffusion::SymbolStringInterop;
+use crate::math::{Mat4, Vec3};
+use crate::interop_extern::voxel::VoxelWorldVolumetricLayerDataInterop;
+use crate::turtle::interpreter::{interpret_turtle_in_environment, TurtleOperationTable, TurtleSymbols};
+use crate::turtle::operations::{OrganOperation, StemOperation, TurtleEnvironment, TurtleOperation, TurtleOrganTemplate, TurtleOutput};
+use crate::turtle::state::{TurtleOrganInstance, TurtleStemInstance, TurtleState};
+
+/// matches the numbering of the unity TurtleOperationType. entity and volumetric operations are not supported.
+///     tropisms are native only, numbered after the unity operations
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TurtleOperationType {
+    BendTowards = 0,
+    OrientTowards = 1,
+    AddOrgan = 2,
+    AddStem = 3,
+    Rotate = 5,
+    ScaleTransform = 6,
+    ScaleThickness = 7,
+    Gravitropism = 9,
+    Phototropism = 10,
+}
+
+impl TryFrom<u8> for TurtleOperationType {
+    /// the unsupported operation type
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TurtleOperationType::BendTowards),
+            1 => Ok(TurtleOperationType::OrientTowards),
+            2 => Ok(TurtleOperationType::AddOrgan),
+            3 => Ok(TurtleOperationType::AddStem),
+            5 => Ok(TurtleOperationType::Rotate),
+            6 => Ok(TurtleOperationType::ScaleTransform),
+            7 => Ok(TurtleOperationType::ScaleThickness),
+            9 => Ok(TurtleOperationType::Gravitropism),
+            10 => Ok(TurtleOperationType::Phototropism),
+            _ => Err(value),
+        }
+    }
+}
+
+/// every turtle operation flattened into one layout. fields not used by the operation type are ignored
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TurtleOperationInterop {
+    pub symbol: i32,
+    /// a TurtleOperationType. operations of any other type, such as the unsupported unity operations, are skipped
+    pub operation_type: u8,
+    /// the default direction of bend and orient, the axis of rotate, the non uniform scale of scale transform,
+    ///     the extra non uniform scale of add organ, or the gravity of gravitropism
+    pub vector: Vec3,
+    /// the default factor of bend, orient, scale transform, scale thickness and phototropism,
+    ///     the default degrees of rotate, or the default elasticity of gravitropism
+    pub default_value: f32,
+    /// add organ: scale by a parameter. add stem: scale the length and radius by the first parameter
+    pub do_scale: bool,
+    /// add organ and add stem: scale by the turtle thickness
+    pub apply_thickness: bool,
+    pub scale_is_additional: bool,
+    pub scale_power: f32,
+    /// add organ: the range of templates in the template list
+    pub organ_templates: JaggedIndexing,
+    /// add stem only
+    pub stem_class_index: i32,
+    pub will_move: bool,
+    pub length_radius_base: [f32; 2],
+    pub length_radius_scale: [f32; 2],
+}
+
+impl TurtleOperationInterop {
+    /// None if the operation type is not supported. voxel_layer is the layer read by phototropism,
+    ///     which is skipped when the layer is negative
+    pub fn to_operation(&self, voxel_layer: i32) -> Option<TurtleOperation> {
+        let Ok(operation_type) = TurtleOperationType::try_from(self.operation_type) else {
+            return None;
+        };
+        let operation = match operation_type {
+            TurtleOperationType::BendTowards => TurtleOperation::BendTowards {
+                default_direction: self.vector,
+                default_factor: self.default_value,
+            },
+            TurtleOperationType::OrientTowards => TurtleOperation::OrientTowards {
+                default_direction: self.vector,
+                default_factor: self.default_value,
+            },
+            TurtleOperationType::AddOrgan => {
+                let templates_start = self.organ_templates.index.max(0) as usize;
+                TurtleOperation::AddOrgan(OrganOperation {
+                    organ_templates: templates_start..templates_start + self.organ_templates.length as usize,
+                    do_scale: self.do_scale,
+                    scale_is_additional: self.scale_is_additional,
+                    scale_power: self.scale_power,
+                    extra_non_uniform_scale: self.vector,
+                    apply_thickness: self.apply_thickness,
+                })
+            }
+            TurtleOperationType::AddStem => TurtleOperation::AddStem(StemOperation {
+                stem_class_index: self.stem_class_index,
+                will_move: self.will_move,
+                scale_by_parameter: self.do_scale,
+                scale_by_thickness: self.apply_thickness,
+                length_radius_base: self.length_radius_base,
+                length_radius_scale: self.length_radius_scale,
+                scale_is_additional: self.scale_is_additional,
+                scale_power: self.scale_power,
+            }),
+            TurtleOperationType::Rotate => TurtleOperation::Rotate {
+                axis: self.vector.normalized(),
+                default_degrees: self.default_value,
+            },
+            TurtleOperationType::ScaleTransform => TurtleOperation::ScaleTransform {
+                non_uniform_scale: self.vector,
+                default_factor: self.default_value,
+            },
+            TurtleOperationType::ScaleThickness => TurtleOperation::ScaleThickness {
+                default_factor: self.default_value,
+            },
+            TurtleOperationType::Gravitropism => TurtleOperation::Gravitropism {
+                gravity: self.vector,
+                default_elasticity: self.default_value,
+            },
+            TurtleOperationType::Phototropism => TurtleOperation::Phototropism {
+                light_layer: usize::try_from(voxel_layer).ok()?,
+                default_factor: self.default_value,
+            },
+        };
+        Some(operation)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TurtleSymbolsInterop {
+    pub branch_open_symbol: i32,
+    pub branch_close_symbol: i32,
+    pub has_organ_identity_symbol: bool,
+    pub organ_identity_symbol: i32,
+    pub has_custom_data_symbol: bool,
+    pub custom_data_symbol: i32,
+}
+
+impl TurtleSymbolsInterop {
+    pub fn to_symbols(&self) -> TurtleSymbols {
+        TurtleSymbols {
+            branch_open_symbol: self.branch_open_symbol,
+            branch_close_symbol: self.branch_close_symbol,
+            organ_identity_symbol: self.has_organ_identity_symbol.then_some(self.organ_identity_symbol),
+            custom_data_symbol: self.has_custom_data_symbol.then_some(self.custom_data_symbol),
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TurtleInterpretError {
+    None = 0,
+    /// a branch close symbol has no matching branch open symbol
+    UnmatchedBranchClose = 1,
+    /// the caller allocated buffers are too small to hold every instance
+    TargetTooSmall = 2,
+    /// a branch open symbol is never closed
+    UnclosedBranchOpen = 3,
+}
+
+native_array_interop!(TurtleOperationInterop, NativeArrayInteropTurtleOperationInterop, NativeArrayInteropTurtleOperationInteropMut);
+native_array_interop!(TurtleOrganTemplate, NativeArrayInteropTurtleOrganTemplate, NativeArrayInteropTurtleOrganTemplateMut);
+native_array_interop!(TurtleOrganInstance, NativeArrayInteropTurtleOrganInstance, NativeArrayInteropTurtleOrganInstanceMut);
+native_array_interop!(TurtleStemInstance, NativeArrayInteropTurtleStemInstance, NativeArrayInteropTurtleStemInstanceMut);
+
+/// interpret the string with the turtle, writing every organ placed into the caller allocated organ output.
+/// stem_output may be null, when stems are not needed. phototropism operations are skipped, see interpret_turtle_to_buffer_in_environment.
+/// written counts are always set to the number of instances placed. writes nothing if either output is too small
+#[no_mangle]
+pub extern "C" fn interpret_turtle_to_buffer(
+    source_data: *const SymbolStringInterop,
+    operations: *const NativeArrayInteropTurtleOperationInterop,
+    organ_templates: *const NativeArrayInteropTurtleOrganTemplate,
+    symbols: *const TurtleSymbolsInterop,
+    default_state: *const TurtleState,
+    organ_output: *mut NativeArrayInteropTurtleOrganInstanceMut,
+    written_organ_count: *mut i32,
+    stem_output: *mut NativeArrayInteropTurtleStemInstanceMut,
+    written_stem_count: *mut i32,
+) -> TurtleInterpretError{
+
+    let (
+        source_data_safe,
+        operations_safe,
+        organ_templates_safe,
+        symbols_safe,
+        default_state_safe,
+        organ_output_safe,
+        written_organ_count_safe,
+        stem_output_safe,
+        written_stem_count_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            operations.as_ref().unwrap().to_slice(),
+            organ_templates.as_ref().unwrap().to_slice(),
+            symbols.as_ref().unwrap().to_symbols(),
+            default_state.as_ref().unwrap(),
+            organ_output.as_ref().unwrap().to_slice(),
+            written_organ_count.as_mut().unwrap(),
+            stem_output.as_ref().map(|stem_output| stem_output.to_slice()),
+            written_stem_count.as_mut(),
+        )};
+
+    let output = match interpret_turtle_from_interop(&source_data_safe, operations_safe, &[], organ_templates_safe, &symbols_safe, default_state_safe, &TurtleEnvironment::default()) {
+        Ok(output) => output,
+        Err(error) => return error,
+    };
+    write_turtle_output(&output, organ_output_safe, written_organ_count_safe, stem_output_safe, written_stem_count_safe, None)
+}
+
+/// interpret the string as interpret_turtle_to_buffer, also writing the index of the symbol which placed each stem into stem_symbol_output,
+///     parallel to stem_output. stem_symbol_output may be null, and is only written when stem_output is written.
+///     it must be at least as long as stem_output, or nothing is written
+#[no_mangle]
+pub extern "C" fn interpret_turtle_to_buffer_with_stem_symbols(
+    source_data: *const SymbolStringInterop,
+    operations: *const NativeArrayInteropTurtleOperationInterop,
+    organ_templates: *const NativeArrayInteropTurtleOrganTemplate,
+    symbols: *const TurtleSymbolsInterop,
+    default_state: *const TurtleState,
+    organ_output: *mut NativeArrayInteropTurtleOrganInstanceMut,
+    written_organ_count: *mut i32,
+    stem_output: *mut NativeArrayInteropTurtleStemInstanceMut,
+    written_stem_count: *mut i32,
+    stem_symbol_output: *mut NativeArrayInteropi32Mut,
+) -> TurtleInterpretError{
+
+    let (
+        source_data_safe,
+        operations_safe,
+        organ_templates_safe,
+        symbols_safe,
+        default_state_safe,
+        organ_output_safe,
+        written_organ_count_safe,
+        stem_output_safe,
+        written_stem_count_safe,
+        stem_symbol_output_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            operations.as_ref().unwrap().to_slice(),
+            organ_templates.as_ref().unwrap().to_slice(),
+            symbols.as_ref().unwrap().to_symbols(),
+            default_state.as_ref().unwrap(),
+            organ_output.as_ref().unwrap().to_slice(),
+            written_organ_count.as_mut().unwrap(),
+            stem_output.as_ref().map(|stem_output| stem_output.to_slice()),
+            written_stem_count.as_mut(),
+            stem_symbol_output.as_ref().map(|stem_symbol_output| stem_symbol_output.to_slice()),
+        )};
+
+    let output = match interpret_turtle_from_interop(&source_data_safe, operations_safe, &[], organ_templates_safe, &symbols_safe, default_state_safe, &TurtleEnvironment::default()) {
+        Ok(output) => output,
+        Err(error) => return error,
+    };
+    write_turtle_output(&output, organ_output_safe, written_organ_count_safe, stem_output_safe, written_stem_count_safe, stem_symbol_output_safe)
+}
+
+/// interpret the string as interpret_turtle_to_buffer_with_stem_symbols, in an environment read by phototropism.
+///     operation_voxel_layers is parallel to operations, the voxel layer holding light for each phototropism operation.
+///     phototropism operations without a layer, or with a negative layer, are skipped.
+///     local_to_world places the plant in the voxel world, it may be null when the plant is at the origin.
+///     operation_voxel_layers, voxel_layers and stem_symbol_output may be null
+#[no_mangle]
+pub extern "C" fn interpret_turtle_to_buffer_in_environment(
+    source_data: *const SymbolStringInterop,
+    operations: *const NativeArrayInteropTurtleOperationInterop,
+    operation_voxel_layers: *const NativeArrayInteropi32,
+    organ_templates: *const NativeArrayInteropTurtleOrganTemplate,
+    symbols: *const TurtleSymbolsInterop,
+    default_state: *const TurtleState,
+    local_to_world: *const Mat4,
+    voxel_layers: *mut VoxelWorldVolumetricLayerDataInterop,
+    organ_output: *mut NativeArrayInteropTurtleOrganInstanceMut,
+    written_organ_count: *mut i32,
+    stem_output: *mut NativeArrayInteropTurtleStemInstanceMut,
+    written_stem_count: *mut i32,
+    stem_symbol_output: *mut NativeArrayInteropi32Mut,
+) -> TurtleInterpretError{
+
+    let (
+        source_data_safe,
+        operations_safe,
+        operation_voxel_layers_safe,
+        organ_templates_safe,
+        symbols_safe,
+        default_state_safe,
+        local_to_world_safe,
+        voxel_layers_safe,
+        organ_output_safe,
+        written_organ_count_safe,
+        stem_output_safe,
+        written_stem_count_safe,
+        stem_symbol_output_safe) =
+        unsafe {(
+            source_data.as_ref().unwrap().to_symbol_str(),
+            operations.as_ref().unwrap().to_slice(),
+            operation_voxel_layers.as_ref().map(|voxel_layers| voxel_layers.to_slice()).unwrap_or_default(),
+            organ_templates.as_ref().unwrap().to_slice(),
+            symbols.as_ref().unwrap().to_symbols(),
+            default_state.as_ref().unwrap(),
+            local_to_world.as_ref().copied().unwrap_or_default(),
+            voxel_layers.as_ref().map(|voxel_layers| voxel_layers.to_layer_data()),
+            organ_output.as_ref().unwrap().to_slice(),
+            written_organ_count.as_mut().unwrap(),
+            stem_output.as_ref().map(|stem_output| stem_output.to_slice()),
+            written_stem_count.as_mut(),
+            stem_symbol_output.as_ref().map(|stem_symbol_output| stem_symbol_output.to_slice()),
+        )};
+
+    let environment = TurtleEnvironment {
+        voxel_layers: voxel_layers_safe.as_ref(),
+        local_to_world: local_to_world_safe,
+    };
+    let output = match interpret_turtle_from_interop(&source_data_safe, operations_safe, operation_voxel_layers_safe, organ_templates_safe, &symbols_safe, default_state_safe, &environment) {
+        Ok(output) => output,
+        Err(error) => return error,
+    };
+    write_turtle_output(&output, organ_output_safe, written_organ_count_safe, stem_output_safe, written_stem_count_safe, stem_symbol_output_safe)
+}
+
+/// operation_voxel_layers is parallel to operations, operations past its end have no voxel layer
+fn interpret_turtle_from_interop(
+    source_data: &SymbolString,
+    operations: &[TurtleOperationInterop],
+    operation_voxel_layers: &[i32],
+    organ_templates: &[TurtleOrganTemplate],
+    symbols: &TurtleSymbols,
+    default_state: &TurtleState,
+    environment: &TurtleEnvironment) -> Result<TurtleOutput, TurtleInterpretError> {
+
+    let operation_table = TurtleOperationTable {
+        operations: operations.iter()
+            .enumerate()
+            .filter_map(|(operation_index, operation)| {
+                let voxel_layer = operation_voxel_layers.get(operation_index).copied().unwrap_or(-1);
+                Some((operation.symbol, operation.to_operation(voxel_layer)?))
+            })
+            .collect::<HashMap<_, _>>(),
+        organ_templates: organ_templates.to_vec(),
+    };
+    interpret_turtle_in_environment(source_data, &operation_table, symbols, default_state, environment)
+        .map_err(|error| match error {
+            BranchTreeError::UnmatchedClose(_) => TurtleInterpretError::UnmatchedBranchClose,
+            BranchTreeError::UnclosedOpen(_) => TurtleInterpretError::UnclosedBranchOpen,
+        })
+}
+
+/// the written counts are always set. writes nothing if any output is too small
+fn write_turtle_output(
+    output: &TurtleOutput,
+    organ_output: &mut [TurtleOrganInstance],
+    written_organ_count: &mut i32,
+    stem_output: Option<&mut [TurtleStemInstance]>,
+    written_stem_count: Option<&mut i32>,
+    stem_symbol_output: Option<&mut [i32]>) -> TurtleInterpretError {
+
+    *written_organ_count = output.organs.len() as i32;
+    if let Some(written_stem_count) = written_stem_count {
+        *written_stem_count = output.stems.len() as i32;
+    }
+    let stems_fit = stem_output.as_ref().is_none_or(|stem_output| stem_output.len() >= output.stems.len());
+    let stem_symbols_fit = stem_symbol_output.as_ref().is_none_or(|stem_symbol_output| stem_symbol_output.len() >= output.stems.len());
+    if organ_output.len() < output.organs.len() || !stems_fit || !stem_symbols_fit {
+        return TurtleInterpretError::TargetTooSmall;
+    }
+
+    organ_output[..output.organs.len()].copy_from_slice(&output.organs);
+    if let Some(stem_output) = stem_output {
+        stem_output[..output.stems.len()].copy_from_slice(&output.stems);
+        if let Some(stem_symbol_output) = stem_symbol_output {
+            stem_symbol_output[..output.stems.len()].copy_from_slice(&output.stem_symbol_indexes);
+        }
+    }
+    TurtleInterpretError::None
+}
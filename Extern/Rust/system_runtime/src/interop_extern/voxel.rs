@@ -0,0 +1,92 @@
+use crate::interop_extern::data::{NativeArrayInteropf32, NativeArrayInteropf32Mut};
+use crate::voxel::diffusion::{diffuse_layer_by_adjacency, diffuse_layer_by_kernel, diffuse_layer_by_voxel_constant, VoxelBoundary};
+use crate::voxel::layout::{VolumetricWorldVoxelLayout, VoxelWorldVolumetricLayerData};
+
+/// matches the memory layout of the unity VoxelWorldVolumetricLayerData
+#[repr(C)]
+pub struct VoxelWorldVolumetricLayerDataInterop {
+    pub layout: VolumetricWorldVoxelLayout,
+    pub data: NativeArrayInteropf32Mut,
+}
+
+impl<'a> VoxelWorldVolumetricLayerDataInterop {
+    pub fn to_layer_data(&self) -> VoxelWorldVolumetricLayerData<'a> {
+        VoxelWorldVolumetricLayerData {
+            layout: self.layout,
+            data: self.data.to_slice(),
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn voxel_kernel_diffusion(
+    layer_data: *mut VoxelWorldVolumetricLayerDataInterop,
+    layer: i32,
+    kernel_size: i32,
+    delta_time: f32,
+    diffusion_constant: f32,
+    boundary: VoxelBoundary,
+) -> bool{
+    let mut layer_data_safe = unsafe {
+        layer_data.as_ref().unwrap().to_layer_data()
+    };
+    if kernel_size <= 0 {
+        return false;
+    }
+
+    diffuse_layer_by_kernel(
+        &mut layer_data_safe,
+        layer,
+        kernel_size as usize,
+        delta_time,
+        diffusion_constant,
+        &boundary)
+}
+
+#[no_mangle]
+pub extern "C" fn voxel_adjacency_diffusion(
+    layer_data: *mut VoxelWorldVolumetricLayerDataInterop,
+    layer: i32,
+    delta_time: f32,
+    diffusion_constant: f32,
+    boundary: VoxelBoundary,
+) -> bool{
+    let mut layer_data_safe = unsafe {
+        layer_data.as_ref().unwrap().to_layer_data()
+    };
+
+    diffuse_layer_by_adjacency(
+        &mut layer_data_safe,
+        layer,
+        delta_time,
+        diffusion_constant,
+        &boundary)
+}
+
+#[no_mangle]
+pub extern "C" fn voxel_adjacency_by_voxel_constant_diffusion(
+    layer_data: *mut VoxelWorldVolumetricLayerDataInterop,
+    layer: i32,
+    diffusion_constant_multipliers: *const NativeArrayInteropf32,
+    minimum_diffusion_constant_multiplier: f32,
+    delta_time: f32,
+    diffusion_constant: f32,
+    boundary: VoxelBoundary,
+) -> bool{
+    let (
+        mut layer_data_safe,
+        multipliers) =
+    unsafe {(
+        layer_data.as_ref().unwrap().to_layer_data(),
+        diffusion_constant_multipliers.as_ref().unwrap().to_slice()
+    )};
+
+    diffuse_layer_by_voxel_constant(
+        &mut layer_data_safe,
+        layer,
+        multipliers,
+        minimum_diffusion_constant_multiplier,
+        delta_time,
+        diffusion_constant,
+        &boundary)
+}
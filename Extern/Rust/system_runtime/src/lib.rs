@@ -1,3 +1,8 @@
 pub mod dynamic_expressions;
 pub mod diffusion;
-pub mod interop_extern;
\ No newline at end of file
+pub mod interop_extern;
+pub mod math;
+pub mod voxel;
+pub mod turtle;
+pub mod mesh;
+pub mod lsystem;
@@ -0,0 +1,5 @@
+pub mod context_match;
+pub mod expression_text;
+pub mod rule_file;
+pub mod rewrite;
+pub mod rewrite_trace;
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use crate::diffusion::branch_tree::BranchTree;
+use crate::diffusion::extract_graph::SymbolString;
+
+/// One symbol of a rule context, matching symbols with exactly this many parameters
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ContextSymbol {
+    pub symbol: i32,
+    pub parameter_count: usize,
+}
+
+/// The symbols which must follow a matched symbol, read as a tree in the same way as the unity suffix matcher:
+///     each symbol is the child of the symbol before it, and a branch makes its first symbol a sibling of what follows the branch.
+///     the branch symbols themselves are not matched
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SuffixContext {
+    symbols: Vec<ContextSymbol>,
+    /// the parent of each symbol. -1 when the parent is the matched symbol, -2 for branch symbols which are not matched
+    parents: Vec<i32>,
+    /// the position of each symbol in the children of its parent
+    index_in_parent: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    root_children: Vec<usize>,
+}
+
+impl SuffixContext {
+    /// build the tree of the suffix, which must have balanced branch symbols
+    pub fn new(symbols: Vec<ContextSymbol>, branch_open_symbol: i32, branch_close_symbol: i32) -> Self {
+        let mut parents = vec![-2; symbols.len()];
+        let mut child_counts = vec![0; symbols.len()];
+        let mut parent_stack = vec![-1];
+        for (index, symbol) in symbols.iter().enumerate() {
+            if symbol.symbol == branch_open_symbol {
+                let parent = *parent_stack.last().expect("unbalanced suffix context");
+                parents[index] = parent;
+                if parent >= 0 {
+                    child_counts[parent as usize] += 1;
+                }
+                parent_stack.push(index as i32);
+            } else if symbol.symbol == branch_close_symbol {
+                parent_stack.pop();
+            } else {
+                let parent = parent_stack.pop().expect("unbalanced suffix context");
+                parents[index] = parent;
+                if parent >= 0 {
+                    child_counts[parent as usize] += 1;
+                }
+                parent_stack.push(index as i32);
+            }
+        }
+
+        // move every child of a branch open symbol up to the parent of the branch
+        for index in 0..symbols.len() {
+            let Ok(parent) = usize::try_from(parents[index]) else {
+                continue;
+            };
+            if symbols[parent].symbol != branch_open_symbol {
+                continue;
+            }
+            let grandparent = parents[parent];
+            parents[index] = grandparent;
+            if grandparent >= 0 {
+                child_counts[grandparent as usize] += 1;
+            }
+            child_counts[parent] -= 1;
+            if child_counts[parent] <= 0 {
+                parents[parent] = -2;
+            }
+        }
+
+        let mut children = vec![Vec::new(); symbols.len()];
+        let mut root_children = Vec::new();
+        let mut index_in_parent = vec![0; symbols.len()];
+        for (index, parent) in parents.iter().enumerate() {
+            let siblings = match *parent {
+                -1 => &mut root_children,
+                -2 => continue,
+                parent => &mut children[parent as usize],
+            };
+            index_in_parent[index] = siblings.len();
+            siblings.push(index);
+        }
+
+        SuffixContext {
+            symbols,
+            parents,
+            index_in_parent,
+            children,
+            root_children,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn symbols(&self) -> &[ContextSymbol] {
+        &self.symbols
+    }
+
+    fn children_of(&self, node: i32) -> &[usize] {
+        match usize::try_from(node) {
+            Ok(node) => &self.children[node],
+            Err(_) => &self.root_children,
+        }
+    }
+
+    /// the node after this one in a depth first walk, starting from the matched symbol at -1
+    fn next(&self, node: i32) -> Option<i32> {
+        let mut node = node;
+        let mut index_in_children = 0;
+        while index_in_children >= self.children_of(node).len() && node >= 0 {
+            index_in_children = self.index_in_parent[node as usize] + 1;
+            node = self.parents[node as usize];
+        }
+        self.children_of(node).get(index_in_children).map(|child| *child as i32)
+    }
+
+    /// the node before this one in a depth first walk, which is -1 before the first node
+    fn previous(&self, node: i32) -> Option<i32> {
+        let node = usize::try_from(node).ok()?;
+        let mut previous = self.parents[node];
+        let mut child_index = self.index_in_parent[node] as i32 - 1;
+        while child_index >= 0 {
+            previous = self.children_of(previous)[child_index as usize] as i32;
+            child_index = self.children_of(previous).len() as i32 - 1;
+        }
+        Some(previous)
+    }
+
+    /// walk back from node to the nearest node whose parent is parent, including node itself
+    fn find_previous_with_parent(&self, node: i32, parent: i32) -> Option<i32> {
+        let mut node = node;
+        loop {
+            if self.parents[node as usize] == parent {
+                return Some(node);
+            }
+            node = self.previous(node).filter(|previous| *previous >= 0)?;
+        }
+    }
+}
+
+/// Matches the contexts of rules against one string, in the same way as the unity branching cache.
+///     only the symbols of the context symbol set are seen, all others are skipped over
+pub struct ContextMatcher<'a> {
+    pub symbol_string: &'a SymbolString<'a>,
+    /// a tolerant tree of the string, to jump over branches
+    pub branch_tree: &'a BranchTree,
+    pub branch_open_symbol: i32,
+    pub branch_close_symbol: i32,
+}
+
+impl ContextMatcher<'_> {
+    fn parameters_of(&self, index: usize) -> &[f32] {
+        let indexing = self.symbol_string.param_indexing[index];
+        &self.symbol_string.parameters[indexing.index as usize..indexing.index as usize + indexing.length as usize]
+    }
+
+    /// match the prefix against the symbols before index, along the path back to the root of the string.
+    ///     the parameters of the matched symbols are written to the end of captured_parameters, in order
+    pub fn matches_prefix(&self, index: usize, prefix: &[ContextSymbol], context_symbols: &[i32], captured_parameters: &mut Vec<f32>) -> bool {
+        let first_captured = captured_parameters.len();
+        let mut remaining = prefix.len();
+        let mut target_index = index;
+        while remaining > 0 && target_index > 0 {
+            target_index -= 1;
+            let symbol = self.symbol_string.symbols[target_index];
+            if context_symbols.binary_search(&symbol).is_err() || symbol == self.branch_open_symbol {
+                continue;
+            }
+            if symbol == self.branch_close_symbol {
+                // skip to just before the open symbol of the branch
+                target_index = self.branch_tree.matching_bracket(target_index).unwrap_or(target_index);
+                continue;
+            }
+            let expected = prefix[remaining - 1];
+            let parameters = self.parameters_of(target_index);
+            if expected.symbol != symbol || expected.parameter_count != parameters.len() {
+                captured_parameters.truncate(first_captured);
+                return false;
+            }
+            captured_parameters.extend(parameters.iter().rev());
+            remaining -= 1;
+        }
+        if remaining > 0 {
+            captured_parameters.truncate(first_captured);
+            return false;
+        }
+        captured_parameters[first_captured..].reverse();
+        true
+    }
+
+    /// match the suffix against the symbols after index, where a symbol in a branch may match a branch of the suffix.
+    ///     the parameters of the matched symbols are written to the end of captured_parameters, in the order they are matched
+    pub fn matches_suffix(&self, index: usize, suffix: &SuffixContext, context_symbols: &[i32], captured_parameters: &mut Vec<f32>) -> bool {
+        let first_captured = captured_parameters.len();
+        let matches = self.matches_suffix_from(index, suffix, context_symbols, captured_parameters);
+        if !matches {
+            captured_parameters.truncate(first_captured);
+        }
+        matches
+    }
+
+    fn matches_suffix_from(&self, origin_index: usize, suffix: &SuffixContext, context_symbols: &[i32], captured_parameters: &mut Vec<f32>) -> bool {
+        struct OpenBranch {
+            parent_index: usize,
+            open_index: usize,
+            captured_length: usize,
+        }
+        let symbols = self.symbol_string.symbols;
+        let mut open_branches: Vec<OpenBranch> = Vec::new();
+        let mut matched_nodes = HashMap::from([(origin_index, -1)]);
+        let mut parent_index = origin_index;
+        let Some(mut node) = suffix.next(-1) else {
+            return true;
+        };
+
+        let mut target_index = origin_index + 1;
+        while target_index < symbols.len() {
+            let symbol = symbols[target_index];
+            if context_symbols.binary_search(&symbol).is_err() {
+                target_index += 1;
+                continue;
+            }
+            if symbol == self.branch_open_symbol {
+                open_branches.push(OpenBranch {
+                    parent_index,
+                    open_index: target_index,
+                    captured_length: captured_parameters.len(),
+                });
+                target_index += 1;
+                continue;
+            }
+
+            let parent_node = matched_nodes[&parent_index];
+            let matched = symbol != self.branch_close_symbol && {
+                if let Some(previous) = suffix.find_previous_with_parent(node, parent_node) {
+                    node = previous;
+                }
+                let expected = suffix.symbols[node as usize];
+                let expected_parent = suffix.parents[node as usize];
+                expected.symbol == symbol
+                    && expected.parameter_count == self.parameters_of(target_index).len()
+                    && (expected_parent == -1 || expected_parent == parent_node)
+            };
+            if matched {
+                matched_nodes.insert(target_index, node);
+                captured_parameters.extend_from_slice(self.parameters_of(target_index));
+                parent_index = target_index;
+                match suffix.next(node) {
+                    Some(next) => node = next,
+                    None => return true,
+                }
+                target_index += 1;
+                continue;
+            }
+
+            // a close symbol ends its branch, a symbol which does not match skips the rest of its branch
+            let Some(branch) = open_branches.pop() else {
+                return false;
+            };
+            parent_index = branch.parent_index;
+            if symbol != self.branch_close_symbol {
+                match self.branch_tree.matching_bracket(branch.open_index) {
+                    Some(close_index) => target_index = close_index,
+                    None => return false,
+                }
+            }
+            if matched_nodes[&parent_index] != suffix.parents[node as usize] {
+                captured_parameters.truncate(branch.captured_length);
+            }
+            target_index += 1;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+
+    const OPEN: i32 = 100;
+    const CLOSE: i32 = 101;
+
+    fn context(symbols: &[i32]) -> Vec<ContextSymbol> {
+        symbols.iter().map(|symbol| ContextSymbol {
+            symbol: *symbol,
+            parameter_count: 0,
+        }).collect()
+    }
+
+    /// match the prefix and suffix at index of the text, with every symbol of the text in the context symbol set
+    fn matches(text: &str, index: usize, prefix: &[i32], suffix: &[i32]) -> bool {
+        let owned = parse_symbol_string(text, None).unwrap();
+        let symbol_string = owned.borrow();
+        let branch_tree = BranchTree::new_tolerant(&symbol_string, OPEN, CLOSE, OPEN);
+        let matcher = ContextMatcher {
+            symbol_string: &symbol_string,
+            branch_tree: &branch_tree,
+            branch_open_symbol: OPEN,
+            branch_close_symbol: CLOSE,
+        };
+        let mut context_symbols = owned.symbols.clone();
+        context_symbols.extend([OPEN, CLOSE]);
+        context_symbols.sort();
+        let mut captured = Vec::new();
+        matcher.matches_prefix(index, &context(prefix), &context_symbols, &mut captured)
+            && matcher.matches_suffix(index, &SuffixContext::new(context(suffix), OPEN, CLOSE), &context_symbols, &mut captured)
+    }
+
+    #[test]
+    fn suffix_branches_become_siblings() {
+        // B [ C ] D: C and D are both children of B
+        let suffix = SuffixContext::new(context(&[2, OPEN, 3, CLOSE, 4]), OPEN, CLOSE);
+        assert_eq!(suffix.parents, [-1, -2, 0, -2, 0]);
+        assert_eq!(suffix.children[0], [2, 4]);
+        assert_eq!(suffix.root_children, [0]);
+        let walk: Vec<i32> = std::iter::successors(suffix.next(-1), |node| suffix.next(*node)).collect();
+        assert_eq!(walk, [0, 2, 4]);
+        assert_eq!(suffix.previous(4), Some(2));
+        assert_eq!(suffix.previous(0), Some(-1));
+    }
+
+    #[test]
+    fn prefix_skips_branches_on_the_way_to_the_root() {
+        assert!(matches("1 100 2 101 3", 4, &[1], &[]));
+        assert!(!matches("1 100 2 101 3", 4, &[2], &[]));
+        assert!(matches("1 100 2 3", 3, &[1, 2], &[]));
+        assert!(!matches("3", 0, &[1], &[]));
+    }
+
+    #[test]
+    fn suffix_matches_into_branches() {
+        assert!(matches("1 100 2 101 3", 0, &[], &[100, 2, 101]));
+        assert!(matches("1 100 2 101 3", 0, &[], &[3]));
+        assert!(matches("1 100 2 101 3", 0, &[], &[100, 2, 101, 3]));
+        // as in unity, the order of the children does not matter, so a symbol in a branch is also a child
+        assert!(matches("1 100 2 101 3", 0, &[], &[2]));
+        assert!(!matches("1 100 2 101 3", 0, &[], &[100, 2, 101, 2]));
+        assert!(!matches("1 100 2 101 3", 0, &[], &[4]));
+        assert!(!matches("1 100 2 101", 0, &[], &[100, 2, 101, 3]));
+    }
+
+    #[test]
+    fn context_captures_parameters_in_order() {
+        let owned = parse_symbol_string("1(1, 2) 2(3) 100 3(4) 101", None).unwrap();
+        let symbol_string = owned.borrow();
+        let branch_tree = BranchTree::new_tolerant(&symbol_string, OPEN, CLOSE, OPEN);
+        let matcher = ContextMatcher {
+            symbol_string: &symbol_string,
+            branch_tree: &branch_tree,
+            branch_open_symbol: OPEN,
+            branch_close_symbol: CLOSE,
+        };
+        let context_symbols = [1, 2, 3, OPEN, CLOSE];
+        let mut captured = vec![0.0];
+        let prefix = [ContextSymbol { symbol: 1, parameter_count: 2 }];
+        assert!(matcher.matches_prefix(1, &prefix, &context_symbols, &mut captured));
+        let suffix = SuffixContext::new(vec![
+            ContextSymbol { symbol: OPEN, parameter_count: 0 },
+            ContextSymbol { symbol: 3, parameter_count: 1 },
+            ContextSymbol { symbol: CLOSE, parameter_count: 0 },
+        ], OPEN, CLOSE);
+        assert!(matcher.matches_suffix(1, &suffix, &context_symbols, &mut captured));
+        assert_eq!(captured, [0.0, 1.0, 2.0, 4.0]);
+
+        // a failed match leaves the captured parameters as they were
+        let wrong_count = [ContextSymbol { symbol: 1, parameter_count: 1 }];
+        assert!(!matcher.matches_prefix(1, &wrong_count, &context_symbols, &mut captured));
+        assert_eq!(captured.len(), 4);
+    }
+
+    #[test]
+    fn symbols_outside_the_context_set_are_skipped() {
+        let owned = parse_symbol_string("1 5 2", None).unwrap();
+        let symbol_string = owned.borrow();
+        let branch_tree = BranchTree::new_tolerant(&symbol_string, OPEN, CLOSE, OPEN);
+        let matcher = ContextMatcher {
+            symbol_string: &symbol_string,
+            branch_tree: &branch_tree,
+            branch_open_symbol: OPEN,
+            branch_close_symbol: CLOSE,
+        };
+        let mut captured = Vec::new();
+        assert!(matcher.matches_prefix(2, &context(&[1]), &[1, 2, OPEN, CLOSE], &mut captured));
+        assert!(!matcher.matches_prefix(2, &context(&[1]), &[1, 2, 5, OPEN, CLOSE], &mut captured));
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt;
+use crate::interop_extern::expressions::{OperatorDefinition, OperatorType};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpressionParseError {
+    /// byte offset into the expression text where parsing failed
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExpressionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ExpressionParseError {}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(usize, usize),
+    Operator(&'static str),
+}
+
+/// longer operators first, so ">=" is not read as ">"
+const OPERATORS: [&str; 17] = ["&&", "||", ">=", "<=", "==", "!=", "*", "/", "%", "^", "+", "-", ">", "<", "!", "(", ")"];
+
+/// binary operators from the loosest to the tightest binding, matching the precedence of the unity expression compiler.
+///     note that unity binds multiplication tighter than exponents
+const BINARY_LEVELS: [&[(&str, OperatorType)]; 7] = [
+    &[("||", OperatorType::BooleanOr)],
+    &[("&&", OperatorType::BooleanAnd)],
+    &[("==", OperatorType::Equal), ("!=", OperatorType::NotEqual)],
+    &[(">", OperatorType::GreaterThan), ("<", OperatorType::LessThan), (">=", OperatorType::GreaterThanOrEq), ("<=", OperatorType::LessThanOrEq)],
+    &[("+", OperatorType::Add), ("-", OperatorType::Subtract)],
+    &[("^", OperatorType::Exponent)],
+    &[("*", OperatorType::Multiply), ("/", OperatorType::Divide), ("%", OperatorType::Remainder)],
+];
+
+enum Expression {
+    Constant(f32),
+    Parameter(i32),
+    Unary(OperatorType, Box<Expression>),
+    Binary(OperatorType, Box<Expression>, Box<Expression>),
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, ExpressionParseError> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < text.len() {
+        let remaining = &text[position..];
+        let trimmed = remaining.trim_start();
+        position += remaining.len() - trimmed.len();
+        if trimmed.is_empty() {
+            break;
+        }
+
+        let first = trimmed.chars().next().unwrap();
+        if first.is_ascii_digit() || first == '.' {
+            let length = trimmed.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(trimmed.len());
+            let value = trimmed[..length].parse::<f32>()
+                .map_err(|_| ExpressionParseError { position, message: format!("Invalid number {}", &trimmed[..length]) })?;
+            tokens.push((position, Token::Number(value)));
+            position += length;
+        } else if first.is_alphabetic() || first == '_' {
+            let length = trimmed.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(trimmed.len());
+            tokens.push((position, Token::Name(position, position + length)));
+            position += length;
+        } else if let Some(operator) = OPERATORS.iter().find(|operator| trimmed.starts_with(**operator)) {
+            tokens.push((position, Token::Operator(operator)));
+            position += operator.len();
+        } else {
+            return Err(ExpressionParseError { position, message: format!("Unexpected character {}", first) });
+        }
+    }
+    Ok(tokens)
+}
+
+struct ExpressionParser<'a> {
+    text: &'a str,
+    tokens: Vec<(usize, Token)>,
+    next_token: usize,
+    parameter_names: &'a [&'a str],
+}
+
+impl ExpressionParser<'_> {
+    fn error<T>(&self, message: &str) -> Result<T, ExpressionParseError> {
+        let position = self.tokens.get(self.next_token).map_or(self.text.len(), |(position, _)| *position);
+        Err(ExpressionParseError { position, message: message.to_string() })
+    }
+
+    fn take_operator(&mut self, operator: &str) -> bool {
+        let matches = matches!(self.tokens.get(self.next_token), Some((_, Token::Operator(next))) if *next == operator);
+        self.next_token += matches as usize;
+        matches
+    }
+
+    fn parse_level(&mut self, level: usize) -> Result<Expression, ExpressionParseError> {
+        let Some(operators) = BINARY_LEVELS.get(level) else {
+            return self.parse_unary();
+        };
+        let mut lhs = self.parse_level(level + 1)?;
+        'operands: loop {
+            for (operator, operator_type) in operators.iter() {
+                if self.take_operator(operator) {
+                    let rhs = self.parse_level(level + 1)?;
+                    lhs = Expression::Binary(*operator_type, Box::new(lhs), Box::new(rhs));
+                    continue 'operands;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, ExpressionParseError> {
+        if self.take_operator("-") {
+            return Ok(Expression::Unary(OperatorType::NegateUnary, Box::new(self.parse_unary()?)));
+        }
+        if self.take_operator("!") {
+            return Ok(Expression::Unary(OperatorType::BooleanNot, Box::new(self.parse_unary()?)));
+        }
+        if self.take_operator("(") {
+            let inner = self.parse_level(0)?;
+            if !self.take_operator(")") {
+                return self.error("Expected )");
+            }
+            return Ok(inner);
+        }
+        match self.tokens.get(self.next_token).copied() {
+            Some((_, Token::Number(value))) => {
+                self.next_token += 1;
+                Ok(Expression::Constant(value))
+            }
+            Some((_, Token::Name(start, end))) => {
+                let name = &self.text[start..end];
+                let Some(parameter_index) = self.parameter_names.iter().position(|parameter| *parameter == name) else {
+                    return self.error(&format!("Unknown parameter {}", name));
+                };
+                self.next_token += 1;
+                Ok(Expression::Parameter(parameter_index as i32))
+            }
+            _ => self.error("Expected a number, parameter or ("),
+        }
+    }
+}
+
+/// write the expression and everything under it, with the expression at the first index it takes
+fn flatten(expression: &Expression, operators: &mut Vec<OperatorDefinition>) -> Result<u16, ExpressionParseError> {
+    let Ok(index) = u16::try_from(operators.len()) else {
+        return Err(ExpressionParseError { position: 0, message: "Expression is too long".to_string() });
+    };
+    operators.push(OperatorDefinition {
+        operator_type: OperatorType::ConstantValue,
+        node_value: 0.0,
+        parameter_index: 0,
+        rhs: 0,
+        lhs: 0,
+    });
+    let operator = match expression {
+        Expression::Constant(value) => OperatorDefinition {
+            operator_type: OperatorType::ConstantValue,
+            node_value: *value,
+            parameter_index: 0,
+            rhs: 0,
+            lhs: 0,
+        },
+        Expression::Parameter(parameter_index) => OperatorDefinition {
+            operator_type: OperatorType::ParameterValue,
+            node_value: 0.0,
+            parameter_index: *parameter_index,
+            rhs: 0,
+            lhs: 0,
+        },
+        Expression::Unary(operator_type, rhs) => OperatorDefinition {
+            operator_type: *operator_type,
+            node_value: 0.0,
+            parameter_index: 0,
+            rhs: flatten(rhs, operators)?,
+            lhs: 0,
+        },
+        Expression::Binary(operator_type, lhs, rhs) => OperatorDefinition {
+            operator_type: *operator_type,
+            node_value: 0.0,
+            parameter_index: 0,
+            lhs: flatten(lhs, operators)?,
+            rhs: flatten(rhs, operators)?,
+        },
+    };
+    operators[index as usize] = operator;
+    Ok(index)
+}
+
+/// Parse an expression such as `x / 2 + 1`, in the syntax of unity L-system files, into the layout read by
+///     dynamic_expressions::evaluate_expression with the root operator first.
+///     names are read as the parameter at the same index in parameter_names
+pub fn parse_expression(text: &str, parameter_names: &[&str]) -> Result<Vec<OperatorDefinition>, ExpressionParseError> {
+    let mut parser = ExpressionParser {
+        text,
+        tokens: tokenize(text)?,
+        next_token: 0,
+        parameter_names,
+    };
+    if parser.tokens.is_empty() {
+        return parser.error("Expected an expression");
+    }
+    let expression = parser.parse_level(0)?;
+    if parser.next_token < parser.tokens.len() {
+        return parser.error("Unexpected text after the expression");
+    }
+    let mut operators = Vec::new();
+    flatten(&expression, &mut operators)?;
+    Ok(operators)
+}
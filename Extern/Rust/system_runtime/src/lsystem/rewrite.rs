@@ -0,0 +1,381 @@
+use crate::diffusion::branch_tree::BranchTree;
+use crate::diffusion::extract_graph::SymbolString;
+use crate::lsystem::rewrite_trace::{trace_rewrite, RewriteTrace};
+use crate::diffusion::symbol_element_remap::SymbolStringOwned;
+use crate::dynamic_expressions::evaluate_expression;
+use crate::interop_extern::data::JaggedIndexing;
+use crate::interop_extern::diffusion::{LSystemMatchErrorCode, LSystemSingleSymbolMatchData};
+use crate::lsystem::context_match::ContextMatcher;
+use crate::lsystem::rule_file::{Rule, RuleSet};
+
+/// the number of symbols matched by each job of the unity matcher, each drawing from its own generator
+pub const MATCH_BATCH_SIZE: usize = 100;
+
+/// A xorshift generator which gives the same sequence as Unity.Mathematics.Random for the same seed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RewriteRandom {
+    state: u32,
+}
+
+impl RewriteRandom {
+    /// a seed of 0 is read as 1, since the generator can never leave a zero state
+    pub fn new(seed: u32) -> Self {
+        let mut random = RewriteRandom {
+            state: seed.max(1),
+        };
+        random.next_state();
+        random
+    }
+
+    /// the generator which picks the outcomes for the batch of symbols starting at start_index
+    pub fn for_batch(start_index: usize, step_seed: u32) -> Self {
+        let mut random = Self::new((start_index as u32).wrapping_add(1));
+        random.next_u32();
+        random.state ^= step_seed;
+        random
+    }
+
+    fn next_state(&mut self) -> u32 {
+        let state = self.state;
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        state
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.next_state().wrapping_sub(1)
+    }
+
+    /// in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        let bits = ((self.next_state() as u64) << 20) ^ self.next_state() as u64;
+        f64::from_bits(0x3ff0000000000000 | bits) - 1.0
+    }
+}
+
+/// the index of the first rule whose parameter count, context and condition match, with the value of its condition.
+///     the parameters captured by the matched rule are left at the end of captured_parameters.
+///     context is only None when no rule has a context
+fn find_matching_rule(
+    rules: &RuleSet,
+    possible_rules: &[Rule],
+    index: usize,
+    parameters: &[f32],
+    context: Option<&ContextMatcher>,
+    captured_parameters: &mut Vec<f32>) -> Option<(usize, Option<f32>)> {
+    let first_captured = captured_parameters.len();
+    for (rule_index, rule) in possible_rules.iter().enumerate() {
+        captured_parameters.truncate(first_captured);
+        let context = context.filter(|_| rule.has_context());
+        let context_symbols = rules.context_symbols(rule);
+        // the prefix is matched before the parameter count, as in unity
+        if context.is_some_and(|context| !context.matches_prefix(index, &rule.prefix, context_symbols, captured_parameters)) {
+            continue;
+        }
+        if rule.parameter_count != parameters.len() {
+            continue;
+        }
+        captured_parameters.extend_from_slice(parameters);
+        if context.is_some_and(|context| !context.matches_suffix(index, &rule.suffix, context_symbols, captured_parameters)) {
+            continue;
+        }
+        let condition_value = rule.condition.as_ref()
+            .map(|condition| evaluate_expression(condition, &rules.global_parameters, &captured_parameters[first_captured..]));
+        if condition_value.is_none_or(|value| value > 0.0) {
+            return Some((rule_index, condition_value));
+        }
+    }
+    captured_parameters.truncate(first_captured);
+    None
+}
+
+/// the index of the outcome whose share of the probability holds the sample
+fn select_outcome(rule: &Rule, random: &mut RewriteRandom) -> usize {
+    if rule.outcomes.len() <= 1 {
+        return 0;
+    }
+    let sample = random.next_f64();
+    let mut partition = 0.0;
+    rule.outcomes.iter()
+        .position(|outcome| {
+            partition += outcome.probability;
+            sample <= partition
+        })
+        .unwrap_or(rule.outcomes.len() - 1)
+}
+
+/// The match data of every symbol, with the parameters captured by the rule each symbol matched
+pub struct RuleMatches {
+    pub match_data: Vec<LSystemSingleSymbolMatchData>,
+    /// the memory indexed by tmp_parameter_memory_space
+    pub captured_parameters: Vec<f32>,
+    /// the value of the condition of the rule each symbol matched. None where the symbol is trivial or its rule has no condition
+    pub condition_values: Vec<Option<f32>>,
+}
+
+/// Match every symbol of the source against the rules, filling the match data the same way as the unity matcher.
+///     the first rule for a symbol whose context, parameter count and condition match is used. symbols without a matching rule are trivial.
+///     stochastic outcomes are drawn in order from one generator per MATCH_BATCH_SIZE symbols, as unity does.
+///     a matched symbol captures the parameters of its prefix, itself and its suffix, trivial symbols capture nothing
+pub fn match_rules(source: &SymbolString, rules: &RuleSet, step_seed: u32) -> RuleMatches {
+    let mut matches = RuleMatches {
+        match_data: Vec::with_capacity(source.symbols.len()),
+        captured_parameters: Vec::new(),
+        condition_values: Vec::with_capacity(source.symbols.len()),
+    };
+    // the branch tree is only needed to jump over branches while matching contexts
+    let branch_tree = rules.has_context_rules()
+        .then(|| BranchTree::new_tolerant(source, rules.branch_open_symbol, rules.branch_close_symbol, rules.branch_open_symbol));
+    let context = branch_tree.as_ref().map(|branch_tree| ContextMatcher {
+        symbol_string: source,
+        branch_tree,
+        branch_open_symbol: rules.branch_open_symbol,
+        branch_close_symbol: rules.branch_close_symbol,
+    });
+    let mut symbol_count = 0;
+    let mut parameter_count = 0;
+    let mut random = RewriteRandom::for_batch(0, step_seed);
+    for (index, symbol, parameters) in source.iter() {
+        if index % MATCH_BATCH_SIZE == 0 {
+            random = RewriteRandom::for_batch(index, step_seed);
+        }
+        let possible_rules = rules.rules_for(symbol);
+        let first_captured = matches.captured_parameters.len();
+        let matched_rule = find_matching_rule(rules, possible_rules, index, parameters, context.as_ref(), &mut matches.captured_parameters);
+        matches.condition_values.push(matched_rule.and_then(|(_, condition_value)| condition_value));
+        let (is_trivial, matched_rule_index, selected_outcome, replacement_length, replacement_parameter_length) = match matched_rule {
+            Some((rule_index, _)) => {
+                let rule = &possible_rules[rule_index];
+                let outcome_index = select_outcome(rule, &mut random);
+                let replacement = &rule.outcomes[outcome_index].replacement;
+                let replacement_parameter_length = replacement.iter().map(|symbol| symbol.parameters.len()).sum();
+                (false, rule_index, outcome_index, replacement.len(), replacement_parameter_length)
+            }
+            None => (true, 0, 0, 1, parameters.len()),
+        };
+
+        matches.match_data.push(LSystemSingleSymbolMatchData {
+            tmp_parameter_memory_space: JaggedIndexing {
+                index: first_captured as i32,
+                length: (matches.captured_parameters.len() - first_captured) as u16,
+            },
+            is_trivial,
+            matched_rule_index_in_possible: matched_rule_index as u8,
+            selected_replacement_pattern: selected_outcome as u8,
+            replacement_symbol_indexing: JaggedIndexing {
+                index: symbol_count as i32,
+                length: replacement_length as u16,
+            },
+            replacement_parameter_indexing: JaggedIndexing {
+                index: parameter_count as i32,
+                length: replacement_parameter_length as u16,
+            },
+            error_code: LSystemMatchErrorCode::None,
+        });
+        symbol_count += replacement_length;
+        parameter_count += replacement_parameter_length;
+    }
+    matches
+}
+
+/// Write the replacement of every symbol picked by match_rules into a new string.
+///     trivial symbols are copied with their parameters
+pub fn write_replacements(source: &SymbolString, rules: &RuleSet, matches: &RuleMatches) -> SymbolStringOwned {
+    let (symbol_count, parameter_count) = matches.match_data.last().map_or((0, 0), |last| (
+        last.replacement_symbol_indexing.index as usize + last.replacement_symbol_indexing.length as usize,
+        last.replacement_parameter_indexing.index as usize + last.replacement_parameter_indexing.length as usize,
+    ));
+    let mut target = SymbolStringOwned {
+        symbols: Vec::with_capacity(symbol_count),
+        param_indexing: Vec::with_capacity(symbol_count),
+        parameters: Vec::with_capacity(parameter_count),
+    };
+    for ((_, symbol, parameters), symbol_match) in source.iter().zip(matches.match_data.iter()) {
+        if symbol_match.is_trivial {
+            target.param_indexing.push(JaggedIndexing {
+                index: target.parameters.len() as i32,
+                length: parameters.len() as u16,
+            });
+            target.symbols.push(symbol);
+            target.parameters.extend_from_slice(parameters);
+            continue;
+        }
+        let rule = &rules.rules_for(symbol)[symbol_match.matched_rule_index_in_possible as usize];
+        let outcome = &rule.outcomes[symbol_match.selected_replacement_pattern as usize];
+        let memory_space = symbol_match.tmp_parameter_memory_space;
+        let captured = &matches.captured_parameters[memory_space.index as usize..memory_space.index as usize + memory_space.length as usize];
+        for replacement in outcome.replacement.iter() {
+            target.param_indexing.push(JaggedIndexing {
+                index: target.parameters.len() as i32,
+                length: replacement.parameters.len() as u16,
+            });
+            target.symbols.push(replacement.symbol);
+            target.parameters.extend(replacement.parameters.iter()
+                .map(|expression| evaluate_expression(expression, &rules.global_parameters, captured)));
+        }
+    }
+    target
+}
+
+/// Rewrite every symbol of the source once. step_seed picks the stochastic outcomes,
+///     and is drawn from a RewriteRandom seeded with the L-system seed once per step
+pub fn rewrite(source: &SymbolString, rules: &RuleSet, step_seed: u32) -> SymbolStringOwned {
+    let matches = match_rules(source, rules, step_seed);
+    write_replacements(source, rules, &matches)
+}
+
+/// rewrite, also tracing which rule rewrote each symbol, the value of its condition, the stochastic pick and the captured parameters
+pub fn rewrite_traced(source: &SymbolString, rules: &RuleSet, step_seed: u32) -> (SymbolStringOwned, RewriteTrace) {
+    let matches = match_rules(source, rules, step_seed);
+    let mut trace = trace_rewrite(source, &matches.match_data, Some(&matches.captured_parameters));
+    for (entry, condition_value) in trace.entries.iter_mut().zip(matches.condition_values.iter()) {
+        if let Some(condition_value) = condition_value {
+            entry.has_condition_value = true;
+            entry.condition_value = *condition_value;
+        }
+    }
+    (write_replacements(source, rules, &matches), trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::symbol_string_text::SymbolNames;
+    use crate::lsystem::rule_file::{link_lsystem_files, parse_lsystem_file, RuleFileParseError};
+
+    fn step(text: &str, steps: usize, seed: u32) -> String {
+        let mut names = SymbolNames::new();
+        let lsystem = parse_lsystem_file(text, &mut names).unwrap();
+        let mut symbol_string = lsystem.axiom.unwrap();
+        let mut step_random = RewriteRandom::new(seed);
+        for _ in 0..steps {
+            symbol_string = rewrite(&symbol_string.borrow(), &lsystem.rules, step_random.next_u32());
+        }
+        symbol_string.borrow().display_with(&names).to_string()
+    }
+
+    #[test]
+    fn random_matches_unity_sequence() {
+        // Unity.Mathematics.Random(1): state 1 advances to 270369 on construction
+        let mut random = RewriteRandom::new(1);
+        assert_eq!(random.next_u32(), 270368);
+        assert_eq!(RewriteRandom::new(0), RewriteRandom::new(1));
+        let sample = RewriteRandom::new(12345).next_f64();
+        assert!((0.0..1.0).contains(&sample));
+    }
+
+    #[test]
+    fn deterministic_rules_rewrite_every_symbol() {
+        let algae = "#axiom A\nA -> AB\nB -> A";
+        assert_eq!(step(algae, 4, 1), "A B A A B A B A");
+    }
+
+    #[test]
+    fn conditions_and_parameters_pick_rules() {
+        let text = "\
+#axiom A(0)
+#runtime growth 2
+#define STEP 1
+A(x) : x < 2 -> F(x * growth + 1)A(x + STEP)
+A(x) : x >= 2 || x < 0 -> B
+";
+        assert_eq!(step(text, 3, 1), "F(1)F(3)B");
+    }
+
+    #[test]
+    fn traces_conditions_and_finds_the_rewritten_symbol() {
+        let mut names = SymbolNames::new();
+        let lsystem = parse_lsystem_file("#axiom A(1)BA(5)\nA(x) : x - 2 -> F(x)F\nA(x) -> C\nB ->", &mut names).unwrap();
+        let axiom = lsystem.axiom.unwrap();
+        let (target, trace) = rewrite_traced(&axiom.borrow(), &lsystem.rules, 1);
+        assert_eq!(target.borrow().display_with(&names).to_string(), "C F(5)F");
+
+        let conditions: Vec<_> = trace.entries.iter().map(|entry| (entry.has_condition_value, entry.condition_value)).collect();
+        assert_eq!(conditions, [(false, 0.0), (false, 0.0), (true, 3.0)]);
+        assert_eq!(trace.entries[0].matched_rule_index, 1);
+        assert_eq!(trace.captured_parameters_of(&trace.entries[2]), &[5.0]);
+
+        let sources: Vec<_> = (0..4).map(|target_index| trace.entry_for_target(target_index).map(|entry| entry.source_index)).collect();
+        assert_eq!(sources, [Some(0), Some(2), Some(2), None]);
+    }
+
+    #[test]
+    fn contexts_match_through_branches_and_capture_parameters() {
+        let text = "#axiom A(1)B(2)[C(3)]D\n#matches ABC\nA(x) < B(y) > [C(z)] -> E(x + y + z)\nB(y) -> G";
+        assert_eq!(step(text, 1, 1), "A(1)E(6)[C(3)]D");
+
+        let mut names = SymbolNames::new();
+        let lsystem = parse_lsystem_file(text, &mut names).unwrap();
+        let (_, trace) = rewrite_traced(&lsystem.axiom.unwrap().borrow(), &lsystem.rules, 1);
+        assert_eq!(trace.captured_parameters_of(&trace.entries[1]), &[1.0, 2.0, 3.0]);
+        assert_eq!(trace.captured_parameters_of(&trace.entries[0]), &[] as &[f32]);
+    }
+
+    #[test]
+    fn contexts_only_see_the_symbols_the_file_matches() {
+        assert_eq!(step("#axiom AXB\n#matches AB\nA < B -> C", 1, 1), "A X C");
+        assert_eq!(step("#axiom AB\nA < B -> C", 1, 1), "A B");
+        // the longer context is tried first, whatever the order of the rules
+        assert_eq!(step("#axiom AB\n#matches AB\nB -> D\nA < B -> C", 1, 1), "A C");
+    }
+
+    #[test]
+    fn includes_link_library_symbols_relative_to_the_including_file() {
+        let files = [
+            ("plant/root.lsystem", "#axiom Sr(1)a\n#include ../lib/grow.lsyslib (Stem->S)\n#include diffusion (Node->r) (Amount->a)"),
+            ("lib/grow.lsyslib", "#export Stem s\n#global F\n#runtime rate 2\ns -> Fsc(rate)\nc(x) -> F"),
+        ];
+        let read_file = |path: &str| files.iter().find(|(name, _)| *name == path).map(|(_, text)| text.to_string());
+        let mut names = SymbolNames::new();
+        let lsystem = link_lsystem_files("plant/root.lsystem", read_file, &mut names).unwrap();
+        let mut symbol_string = lsystem.axiom.unwrap();
+        for _ in 0..2 {
+            symbol_string = rewrite(&symbol_string.borrow(), &lsystem.rules, 1);
+        }
+        // the builtin diffusion library removes amount symbols without parameters, as in unity
+        assert_eq!(symbol_string.borrow().display_with(&names).to_string(), "F F S c:grow(2)F r(1)");
+
+        let node = names.iter().find(|(_, name)| *name == "r").unwrap().0;
+        let amount = names.iter().find(|(_, name)| *name == "a").unwrap().0;
+        assert_eq!(lsystem.diffusion_symbols, Some((node, amount)));
+    }
+
+    #[test]
+    fn invalid_includes_are_rejected() {
+        let link_error = |files: &[(&str, &str)]| -> RuleFileParseError {
+            let read_file = |path: &str| files.iter().find(|(name, _)| *name == path).map(|(_, text)| text.to_string());
+            link_lsystem_files(files[0].0, read_file, &mut SymbolNames::new()).err().unwrap()
+        };
+        let missing = link_error(&[("a.lsystem", "#axiom A\n#include missing.lsyslib")]);
+        assert_eq!((missing.file.as_str(), missing.line), ("a.lsystem", 2));
+        let unexported = link_error(&[("a.lsystem", "#include b.lsyslib (Stem->S)"), ("b.lsyslib", "#export Leaf l")]);
+        assert_eq!((unexported.file.as_str(), unexported.line), ("a.lsystem", 1));
+        let cycle = link_error(&[("a.lsystem", "#include b.lsyslib"), ("b.lsyslib", "#include c.lsyslib"), ("c.lsyslib", "#include b.lsyslib")]);
+        assert!(cycle.message.contains("cycle"));
+        let library_axiom = link_error(&[("a.lsystem", "#include b.lsyslib"), ("b.lsyslib", "\n#axiom A")]);
+        assert_eq!((library_axiom.file.as_str(), library_axiom.line), ("b.lsyslib", 2));
+        let shared_define = link_error(&[("a.lsystem", "#define x 1\n#include b.lsyslib"), ("b.lsyslib", "#define x 2")]);
+        assert_eq!(shared_define.file, "b.lsyslib");
+    }
+
+    #[test]
+    fn stochastic_rules_follow_the_seed() {
+        let text = "#axiom AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\nP(0.5) | A -> B\nP(0.5) | A -> C";
+        let first = step(text, 1, 5);
+        assert_eq!(first, step(text, 1, 5));
+        assert!(first.contains('B') && first.contains('C'));
+        assert_ne!(first, step(text, 1, 6));
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let parse_error = |text: &str| parse_lsystem_file(text, &mut SymbolNames::new()).err().unwrap();
+        assert_eq!(parse_error("#axiom A\nP(0.5) | A -> B\nP(0.4) | A -> C").line, 2);
+        assert_eq!(parse_error("#axiom A\nA -> B\nA -> C").line, 3);
+        assert_eq!(parse_error("#axiom A\nB A -> C").line, 2);
+        assert_eq!(parse_error("#axiom A\nA > [B -> C").line, 2);
+        assert_eq!(parse_error("#axiom A\nA(x) -> B(y)").line, 2);
+        assert_eq!(parse_error("#include other.lsystem").line, 1);
+    }
+}
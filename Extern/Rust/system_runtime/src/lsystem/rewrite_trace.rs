@@ -0,0 +1,204 @@
+use crate::diffusion::extract_graph::SymbolString;
+use crate::interop_extern::data::JaggedIndexing;
+use crate::interop_extern::diffusion::{LSystemMatchErrorCode, LSystemSingleSymbolMatchData};
+
+/// How a single source symbol was rewritten, read from its match data
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct RewriteTraceEntry {
+    pub source_index: i32,
+    pub source_symbol: i32,
+    /// no rule applies to the symbol, so it is copied into the target unchanged
+    pub is_trivial: bool,
+    /// the index of the matched rule among the rules for the symbol
+    pub matched_rule_index: u8,
+    /// the replacement pattern picked by the stochastic selection
+    pub selected_replacement_pattern: u8,
+    /// condition_value holds the condition of the matched rule. false when the symbol is trivial, the rule has no condition,
+    ///     or the entry was traced from match data which does not keep conditions
+    pub has_condition_value: bool,
+    pub condition_value: f32,
+    /// the parameters captured by the match, as a range of RewriteTrace::captured_parameters.
+    ///     empty when no captured parameter memory was traced
+    pub captured_parameters: JaggedIndexing,
+    /// the range of symbols written into the target
+    pub replacement_symbols: JaggedIndexing,
+    /// the range of parameters written into the target
+    pub replacement_parameters: JaggedIndexing,
+    pub error_code: LSystemMatchErrorCode,
+}
+
+/// A record of every symbol rewritten in one step, for finding where a plant went wrong.
+///     traced by rewrite_traced when matching natively, with the condition values of the matched rules.
+///     trace_rewrite reads the match data left by the unity matcher instead, which does not keep the condition values
+#[derive(Clone, Debug, Default)]
+pub struct RewriteTrace {
+    /// indexed by source symbol index
+    pub entries: Vec<RewriteTraceEntry>,
+    pub captured_parameters: Vec<f32>,
+}
+
+impl RewriteTrace {
+    /// the parameters captured when matching the entry
+    pub fn captured_parameters_of(&self, entry: &RewriteTraceEntry) -> &[f32] {
+        let start = entry.captured_parameters.index as usize;
+        &self.captured_parameters[start..start + entry.captured_parameters.length as usize]
+    }
+
+    /// every entry whose match reported an error
+    pub fn errors(&self) -> impl Iterator<Item = &RewriteTraceEntry> {
+        self.entries.iter().filter(|entry| entry.error_code != LSystemMatchErrorCode::None)
+    }
+
+    /// the entry whose replacement wrote the target symbol. entries write the target in order,
+    ///     so this is a binary search over the replacement ranges
+    pub fn entry_for_target(&self, target_index: usize) -> Option<&RewriteTraceEntry> {
+        let target_index = target_index as i32;
+        let after_target = self.entries.partition_point(|entry| entry.replacement_symbols.index <= target_index);
+        // entries which write nothing share their index with the next entry, so step back to the one which wrote the symbol
+        self.entries[..after_target].iter().rev()
+            .find(|entry| entry.replacement_symbols.length > 0)
+            .filter(|entry| target_index < entry.replacement_symbols.index + entry.replacement_symbols.length as i32)
+    }
+}
+
+/// Record how every symbol of the source is rewritten by the match data, in the same order as the source.
+///     captured_parameter_memory is the temporary parameter memory the matcher captured parameters into, when available.
+///     captures which lie outside of that memory are traced as empty. match data has no condition values, so none are traced
+pub fn trace_rewrite(
+    source_data: &SymbolString,
+    match_singleton_data: &[LSystemSingleSymbolMatchData],
+    captured_parameter_memory: Option<&[f32]>) -> RewriteTrace {
+
+    let mut trace = RewriteTrace {
+        entries: Vec::with_capacity(match_singleton_data.len()),
+        captured_parameters: Vec::new(),
+    };
+    for (source_index, (symbol, match_data)) in source_data.symbols.iter().zip(match_singleton_data).enumerate() {
+        let captured_start = trace.captured_parameters.len();
+        let memory_space = match_data.tmp_parameter_memory_space;
+        let captured = captured_parameter_memory
+            .filter(|_| memory_space.index >= 0)
+            .and_then(|memory| memory.get(memory_space.index as usize..memory_space.index as usize + memory_space.length as usize));
+        if let Some(captured) = captured {
+            trace.captured_parameters.extend_from_slice(captured);
+        }
+
+        trace.entries.push(RewriteTraceEntry {
+            source_index: source_index as i32,
+            source_symbol: *symbol,
+            is_trivial: match_data.is_trivial,
+            matched_rule_index: match_data.matched_rule_index_in_possible,
+            selected_replacement_pattern: match_data.selected_replacement_pattern,
+            has_condition_value: false,
+            condition_value: 0.0,
+            captured_parameters: JaggedIndexing {
+                index: captured_start as i32,
+                length: (trace.captured_parameters.len() - captured_start) as u16,
+            },
+            replacement_symbols: match_data.replacement_symbol_indexing,
+            replacement_parameters: match_data.replacement_parameter_indexing,
+            error_code: match_data.error_code,
+        });
+    }
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+
+    fn match_data(
+        tmp_parameter_memory_space: (i32, u16),
+        is_trivial: bool,
+        replacement_symbols: (i32, u16),
+        replacement_parameters: (i32, u16),
+        error_code: LSystemMatchErrorCode) -> LSystemSingleSymbolMatchData {
+        let indexing = |(index, length): (i32, u16)| JaggedIndexing { index, length };
+        LSystemSingleSymbolMatchData {
+            tmp_parameter_memory_space: indexing(tmp_parameter_memory_space),
+            is_trivial,
+            matched_rule_index_in_possible: if is_trivial { 0 } else { 1 },
+            selected_replacement_pattern: if is_trivial { 0 } else { 2 },
+            replacement_symbol_indexing: indexing(replacement_symbols),
+            replacement_parameter_indexing: indexing(replacement_parameters),
+            error_code,
+        }
+    }
+
+    /// match data as the unity matcher leaves it for 1(1,2) 2 3(5) 4, where 1 is rewritten into two symbols,
+    ///     2 is trivial, 3 has too many parameters to capture, and 4 captures outside of the memory
+    fn unity_match_data() -> Vec<LSystemSingleSymbolMatchData> {
+        vec![
+            match_data((0, 2), false, (0, 2), (0, 3), LSystemMatchErrorCode::None),
+            match_data((2, 0), true, (2, 1), (3, 0), LSystemMatchErrorCode::None),
+            match_data((-1, 0), false, (3, 0), (3, 0), LSystemMatchErrorCode::TooManyParameters),
+            match_data((3, 2), false, (3, 1), (3, 1), LSystemMatchErrorCode::None),
+        ]
+    }
+
+    const CAPTURED_MEMORY: [f32; 4] = [1.0, 2.0, 7.0, 8.0];
+
+    #[test]
+    fn traces_unity_match_data() {
+        let source = parse_symbol_string("1(1,2) 2 3(5) 4", None).unwrap();
+        let trace = trace_rewrite(&source.borrow(), &unity_match_data(), Some(&CAPTURED_MEMORY));
+
+        assert_eq!(trace.entries.len(), 4);
+        let symbols: Vec<(i32, i32, bool)> = trace.entries.iter()
+            .map(|entry| (entry.source_index, entry.source_symbol, entry.is_trivial))
+            .collect();
+        assert_eq!(symbols, vec![(0, 1, false), (1, 2, true), (2, 3, false), (3, 4, false)]);
+        assert_eq!(trace.entries[0].matched_rule_index, 1);
+        assert_eq!(trace.entries[0].selected_replacement_pattern, 2);
+        assert_eq!(trace.entries[0].replacement_parameters.length, 3);
+        assert!(trace.entries.iter().all(|entry| !entry.has_condition_value));
+    }
+
+    #[test]
+    fn captures_outside_the_memory_are_empty() {
+        let source = parse_symbol_string("1(1,2) 2 3(5) 4", None).unwrap();
+        let trace = trace_rewrite(&source.borrow(), &unity_match_data(), Some(&CAPTURED_MEMORY));
+
+        assert_eq!(trace.captured_parameters, vec![1.0, 2.0]);
+        assert_eq!(trace.captured_parameters_of(&trace.entries[0]), &[1.0, 2.0]);
+        // a negative memory index and a range past the end of the memory are both traced as empty
+        assert!(trace.captured_parameters_of(&trace.entries[2]).is_empty());
+        assert!(trace.captured_parameters_of(&trace.entries[3]).is_empty());
+
+        let untraced = trace_rewrite(&source.borrow(), &unity_match_data(), None);
+        assert!(untraced.captured_parameters.is_empty());
+        assert!(untraced.entries.iter().all(|entry| entry.captured_parameters.length == 0));
+    }
+
+    #[test]
+    fn errors_report_the_failed_matches() {
+        let source = parse_symbol_string("1(1,2) 2 3(5) 4", None).unwrap();
+        let mut match_data = unity_match_data();
+        match_data[1].error_code = LSystemMatchErrorCode::TrivialSymbolNotIndicatedAtReplacementTime;
+        let trace = trace_rewrite(&source.borrow(), &match_data, Some(&CAPTURED_MEMORY));
+
+        let errors: Vec<(i32, LSystemMatchErrorCode)> = trace.errors()
+            .map(|entry| (entry.source_index, entry.error_code))
+            .collect();
+        assert_eq!(errors, vec![
+            (1, LSystemMatchErrorCode::TrivialSymbolNotIndicatedAtReplacementTime),
+            (2, LSystemMatchErrorCode::TooManyParameters),
+        ]);
+    }
+
+    #[test]
+    fn target_symbols_map_back_to_their_entry() {
+        let source = parse_symbol_string("1(1,2) 2 3(5) 4", None).unwrap();
+        let trace = trace_rewrite(&source.borrow(), &unity_match_data(), Some(&CAPTURED_MEMORY));
+
+        let source_of = |target_index: usize| trace.entry_for_target(target_index).map(|entry| entry.source_index);
+        assert_eq!(source_of(0), Some(0));
+        assert_eq!(source_of(1), Some(0));
+        assert_eq!(source_of(2), Some(1));
+        // 3 writes nothing, so the symbol after 2's replacement belongs to 4
+        assert_eq!(source_of(3), Some(3));
+        assert_eq!(source_of(4), None);
+    }
+}
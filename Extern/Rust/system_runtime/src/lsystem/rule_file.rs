@@ -0,0 +1,865 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::diffusion::symbol_element_remap::SymbolStringOwned;
+use crate::diffusion::symbol_string_text::SymbolNames;
+use crate::dynamic_expressions::evaluate_expression;
+use crate::interop_extern::data::JaggedIndexing;
+use crate::interop_extern::expressions::OperatorDefinition;
+use crate::lsystem::context_match::{ContextSymbol, SuffixContext};
+use crate::lsystem::expression_text::parse_expression;
+
+/// A symbol written by a rule, with one expression per parameter
+#[derive(Clone, Debug)]
+pub struct ReplacementSymbol {
+    pub symbol: i32,
+    pub parameters: Vec<Vec<OperatorDefinition>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RuleOutcome {
+    pub probability: f64,
+    pub replacement: Vec<ReplacementSymbol>,
+}
+
+/// Rewrites one symbol. expressions read the global parameters, followed by the parameters captured by the prefix,
+///     the matched symbol and the suffix, in that order
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub symbol: i32,
+    /// the rule only matches symbols with exactly this many parameters
+    pub parameter_count: usize,
+    /// symbols which must come before the matched symbol, along the path back to the root of the string
+    pub prefix: Vec<ContextSymbol>,
+    /// symbols which must follow the matched symbol, possibly inside branches
+    pub suffix: SuffixContext,
+    /// the file the rule is written in, which decides the symbols its context sees
+    pub file_index: usize,
+    /// the rule only matches when the condition is greater than 0
+    pub condition: Option<Vec<OperatorDefinition>>,
+    /// one outcome for a deterministic rule. otherwise picked at random, by probability
+    pub outcomes: Vec<RuleOutcome>,
+}
+
+impl Rule {
+    pub fn has_context(&self) -> bool {
+        !self.prefix.is_empty() || !self.suffix.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RuleSet {
+    /// the default values of the runtime parameters
+    pub global_parameters: Vec<f32>,
+    pub branch_open_symbol: i32,
+    pub branch_close_symbol: i32,
+    rules_by_symbol: HashMap<i32, Vec<Rule>>,
+    /// the sorted symbols seen by the contexts of rules in each file: the branch symbols and those listed by #matches
+    context_symbols_by_file: Vec<Vec<i32>>,
+}
+
+impl RuleSet {
+    /// the rules which may rewrite the symbol, in the order they are tried
+    pub fn rules_for(&self, symbol: i32) -> &[Rule] {
+        self.rules_by_symbol.get(&symbol).map_or(&[], |rules| rules.as_slice())
+    }
+
+    /// the symbols seen when matching the context of the rule, sorted. every other symbol is skipped over
+    pub fn context_symbols(&self, rule: &Rule) -> &[i32] {
+        self.context_symbols_by_file.get(rule.file_index).map_or(&[], |symbols| symbols.as_slice())
+    }
+
+    pub fn has_context_rules(&self) -> bool {
+        self.rules_by_symbol.values().flatten().any(Rule::has_context)
+    }
+}
+
+pub struct LSystemFile {
+    /// None when the file has no #axiom, such as a file of rules to apply to a string loaded elsewhere
+    pub axiom: Option<SymbolStringOwned>,
+    /// the number of steps the file asks for with #iterations
+    pub iterations: Option<u32>,
+    pub rules: RuleSet,
+    /// the node and amount symbols of the builtin diffusion library, when it is included
+    pub diffusion_symbols: Option<(i32, i32)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleFileParseError {
+    /// the path of the file where parsing failed, as it was included. empty for a file parsed from text
+    pub file: String,
+    /// 1-based line of the file where parsing failed. 0 when the error is not on one line
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RuleFileParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on line {}", self.message, self.line)?;
+        if !self.file.is_empty() {
+            write!(f, " of {}", self.file)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuleFileParseError {}
+
+/// The libraries built into the unity linker, which are included by name rather than by path.
+///     only their symbols and rules are linked, the custom steps unity runs for them are not.
+///     diffusion can be run by the caller with LSystemFile::diffusion_symbols
+const BUILTIN_LIBRARIES: [(&str, &str); 5] = [
+    ("diffusion", "#export Node n\n#export Amount a\na ->"),
+    ("autophagy", "#export Necrose a\nz ->"),
+    ("organIdentity", "#export Identifier i"),
+    ("sunlight", "#export LightAmount a"),
+    ("extraVertexData", "#export VertexData v"),
+];
+
+/// the first id given to the symbols of library files, past every character code
+const LIBRARY_SYMBOL_START: i32 = 0x110000;
+
+/// a rule as written, before rules sharing a symbol, context and condition are grouped into one stochastic rule
+struct ParsedRule {
+    line: usize,
+    file_index: usize,
+    symbol: i32,
+    parameter_count: usize,
+    prefix: Vec<ContextSymbol>,
+    suffix: SuffixContext,
+    condition_text: Option<String>,
+    condition: Option<Vec<OperatorDefinition>>,
+    probability: Option<f64>,
+    replacement: Vec<ReplacementSymbol>,
+}
+
+/// the id of a single character symbol. characters are matched against the names first,
+///     otherwise the character code is used as the id and named by the character
+fn symbol_for(character: char, names: &mut SymbolNames) -> Result<i32, String> {
+    let name = character.to_string();
+    if let Some((symbol, _)) = names.iter().find(|(_, existing)| *existing == name) {
+        return Ok(symbol);
+    }
+    let symbol = character as i32;
+    if let Some(existing) = names.name_of(symbol) {
+        return Err(format!("Symbol {} has no id, its character code {} is already named {}", character, symbol, existing));
+    }
+    names.add(symbol, name);
+    Ok(symbol)
+}
+
+/// The symbols of one file, by the character which writes them. as in unity, every file has symbols of its own,
+///     except for the branch symbols, characters marked #global and characters imported from another file
+struct FileSymbols {
+    by_character: HashMap<char, i32>,
+    /// the file being loaded, whose characters are symbols by their character as in a single file
+    is_root: bool,
+    global_characters: Vec<char>,
+    /// added to the names of the symbols of a library, such as "n:diffusion"
+    label: String,
+}
+
+impl FileSymbols {
+    fn symbol(&mut self, character: char, names: &mut SymbolNames) -> Result<i32, String> {
+        if let Some(symbol) = self.by_character.get(&character) {
+            return Ok(*symbol);
+        }
+        let symbol = if self.is_root || matches!(character, '[' | ']') || self.global_characters.contains(&character) {
+            symbol_for(character, names)?
+        } else {
+            // a library symbol keeps its id when the names were saved with a string from an earlier run
+            let name = format!("{}:{}", character, self.label);
+            let named = names.iter().find(|(_, existing)| *existing == name).map(|(symbol, _)| symbol);
+            match named {
+                Some(symbol) => symbol,
+                None => {
+                    let symbol = (LIBRARY_SYMBOL_START..).find(|symbol| names.name_of(*symbol).is_none())
+                        .ok_or_else(|| "Too many library symbols".to_string())?;
+                    names.add(symbol, name);
+                    symbol
+                }
+            }
+        };
+        self.by_character.insert(character, symbol);
+        Ok(symbol)
+    }
+
+    /// write a symbol shared with a file linked by an include with the character
+    fn share(&mut self, character: char, symbol: i32) -> Result<(), String> {
+        match self.by_character.get(&character) {
+            Some(existing) if *existing == symbol => return Ok(()),
+            Some(_) => return Err(format!("{} is imported as two different symbols", character)),
+            None => {}
+        }
+        if let Some((other, _)) = self.by_character.iter().find(|(_, existing)| **existing == symbol) {
+            return Err(format!("{} and {} are imported as the same symbol", other, character));
+        }
+        self.by_character.insert(character, symbol);
+        Ok(())
+    }
+}
+
+/// the text inside the parentheses which open at the start of text, and the length up to and including the closing parenthesis
+fn parenthesized(text: &str) -> Option<(&str, usize)> {
+    let mut depth = 0;
+    for (offset, character) in text.char_indices() {
+        match character {
+            '(' => depth += 1,
+            ')' if depth == 1 => return Some((&text[1..offset], offset + 1)),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// split on the commas which are not inside nested parentheses
+fn split_arguments(text: &str) -> Vec<&str> {
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (offset, character) in text.char_indices() {
+        match character {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push(&text[start..offset]);
+                start = offset + 1;
+            }
+            _ => {}
+        }
+    }
+    arguments.push(&text[start..]);
+    arguments
+}
+
+/// symbols such as `F(x + 1, 2)[+A]`. whitespace between symbols is ignored
+fn parse_replacement(text: &str, parameter_names: &[&str], symbols: &mut FileSymbols, names: &mut SymbolNames) -> Result<Vec<ReplacementSymbol>, String> {
+    let mut replacement = Vec::new();
+    let mut remaining = text.trim_start();
+    while let Some(character) = remaining.chars().next() {
+        let symbol = symbols.symbol(character, names)?;
+        remaining = remaining[character.len_utf8()..].trim_start();
+        let mut parameters = Vec::new();
+        if remaining.starts_with('(') {
+            let Some((parameter_text, length)) = parenthesized(remaining) else {
+                return Err(format!("Unclosed parameter list after {}", character));
+            };
+            if !parameter_text.trim().is_empty() {
+                for argument in split_arguments(parameter_text) {
+                    let expression = parse_expression(argument, parameter_names)
+                        .map_err(|error| format!("Invalid parameter of {}: {}", character, error))?;
+                    parameters.push(expression);
+                }
+            }
+            remaining = remaining[length..].trim_start();
+        }
+        replacement.push(ReplacementSymbol {
+            symbol,
+            parameters,
+        });
+    }
+    if replacement.len() > u16::MAX as usize {
+        return Err("Too many replacement symbols".to_string());
+    }
+    if replacement.iter().map(|symbol| symbol.parameters.len()).sum::<usize>() > u16::MAX as usize {
+        return Err("Too many replacement parameters".to_string());
+    }
+    Ok(replacement)
+}
+
+
+/// a series of symbols to match such as `A(x, y)B[C]`, with the parameter names of each symbol
+fn parse_input_symbols<'a>(text: &'a str, symbols: &mut FileSymbols, names: &mut SymbolNames) -> Result<Vec<(i32, Vec<&'a str>)>, String> {
+    let mut input_symbols = Vec::new();
+    let mut remaining = text.trim_start();
+    while let Some(character) = remaining.chars().next() {
+        let symbol = symbols.symbol(character, names)?;
+        remaining = remaining[character.len_utf8()..].trim_start();
+        let mut parameter_names = Vec::new();
+        if remaining.starts_with('(') {
+            let Some((parameter_text, length)) = parenthesized(remaining) else {
+                return Err(format!("Unclosed parameter list after {}", character));
+            };
+            parameter_names = parameter_text.split(',').map(str::trim).collect();
+            if parameter_names.iter().any(|name| name.is_empty()) {
+                return Err(format!("Empty parameter name in {}", text.trim()));
+            }
+            remaining = remaining[length..].trim_start();
+        }
+        input_symbols.push((symbol, parameter_names));
+    }
+    Ok(input_symbols)
+}
+
+/// the matched part of a rule such as `A(x) < B(y) > [C]D`
+struct Predecessor<'a> {
+    prefix: Vec<ContextSymbol>,
+    symbol: i32,
+    parameter_count: usize,
+    suffix: Vec<ContextSymbol>,
+    /// the names of the prefix parameters, then the parameters of the matched symbol, then the suffix parameters
+    parameter_names: Vec<&'a str>,
+}
+
+fn parse_predecessor<'a>(text: &'a str, symbols: &mut FileSymbols, names: &mut SymbolNames, branch_symbols: (i32, i32)) -> Result<Predecessor<'a>, String> {
+    let (prefix_text, rest) = match text.split_once('<') {
+        Some((prefix_text, rest)) => (Some(prefix_text), rest),
+        None => (None, text),
+    };
+    let (target_text, suffix_text) = match rest.split_once('>') {
+        Some((target_text, suffix_text)) => (target_text, Some(suffix_text)),
+        None => (rest, None),
+    };
+    if target_text.contains('<') || suffix_text.is_some_and(|suffix_text| suffix_text.contains(['<', '>'])) {
+        return Err(format!("Expected at most one < and one > in {}", text.trim()));
+    }
+
+    let target = parse_input_symbols(target_text, symbols, names)?;
+    let [(symbol, own_names)] = target.as_slice() else {
+        if target.is_empty() {
+            return Err("Expected a symbol to match".to_string());
+        }
+        return Err(format!("Expected a single symbol to match, found {}", target_text.trim()));
+    };
+    let mut context = |context_text: Option<&'a str>, side: &str| -> Result<Vec<(i32, Vec<&'a str>)>, String> {
+        let Some(context_text) = context_text else {
+            return Ok(Vec::new());
+        };
+        let context = parse_input_symbols(context_text, symbols, names)?;
+        if context.is_empty() {
+            return Err(format!("Expected symbols {} the matched symbol", side));
+        }
+        Ok(context)
+    };
+    let prefix = context(prefix_text, "before")?;
+    let suffix = context(suffix_text, "after")?;
+
+    let mut depth = 0;
+    for (suffix_symbol, _) in suffix.iter() {
+        if *suffix_symbol == branch_symbols.0 {
+            depth += 1;
+        } else if *suffix_symbol == branch_symbols.1 {
+            depth -= 1;
+            if depth < 0 {
+                break;
+            }
+        }
+    }
+    if depth != 0 {
+        return Err(format!("Unbalanced branch symbols after the matched symbol in {}", text.trim()));
+    }
+
+    let parameter_names: Vec<&str> = prefix.iter().chain(target.iter()).chain(suffix.iter())
+        .flat_map(|(_, parameter_names)| parameter_names.iter().copied())
+        .collect();
+    if parameter_names.len() > u16::MAX as usize {
+        return Err("Too many captured parameters".to_string());
+    }
+    let context_symbols = |context: &[(i32, Vec<&str>)]| context.iter()
+        .map(|(symbol, parameter_names)| ContextSymbol {
+            symbol: *symbol,
+            parameter_count: parameter_names.len(),
+        })
+        .collect();
+    Ok(Predecessor {
+        prefix: context_symbols(&prefix),
+        symbol: *symbol,
+        parameter_count: own_names.len(),
+        suffix: context_symbols(&suffix),
+        parameter_names,
+    })
+}
+
+fn parse_rule(
+    line: usize,
+    text: &str,
+    global_names: &[&str],
+    file_index: usize,
+    symbols: &mut FileSymbols,
+    names: &mut SymbolNames,
+    branch_symbols: (i32, i32)) -> Result<ParsedRule, String> {
+    let Some(arrow) = text.rfind("->") else {
+        return Err("Expected a rule such as A -> AB".to_string());
+    };
+    let mut matcher = text[..arrow].trim();
+
+    let mut probability = None;
+    // a probability is only read before the condition, so || in the condition is left alone
+    let probability_split = matcher.split_once('|').filter(|(probability_text, _)| !probability_text.contains(':'));
+    if let Some((probability_text, rest)) = probability_split {
+        let Some(probability_text) = probability_text.trim().strip_prefix('P') else {
+            return Err(format!("Expected a probability such as P(0.5), found {}", probability_text.trim()));
+        };
+        let expression = parse_expression(probability_text, &[])
+            .map_err(|error| format!("Invalid probability: {}", error))?;
+        probability = Some(evaluate_expression(&expression, &[], &[]) as f64);
+        matcher = rest;
+    }
+
+    let (predecessor, condition_text) = match matcher.split_once(':') {
+        Some((predecessor, condition)) => (predecessor, Some(condition.trim())),
+        None => (matcher, None),
+    };
+    let predecessor = parse_predecessor(predecessor, symbols, names, branch_symbols)?;
+    let parameter_names: Vec<&str> = global_names.iter().copied().chain(predecessor.parameter_names.iter().copied()).collect();
+    let condition = match condition_text {
+        Some(condition_text) => Some(parse_expression(condition_text, &parameter_names)
+            .map_err(|error| format!("Invalid condition: {}", error))?),
+        None => None,
+    };
+    let replacement = parse_replacement(&text[arrow + 2..], &parameter_names, symbols, names)?;
+
+    Ok(ParsedRule {
+        line,
+        file_index,
+        symbol: predecessor.symbol,
+        parameter_count: predecessor.parameter_count,
+        prefix: predecessor.prefix,
+        suffix: SuffixContext::new(predecessor.suffix, branch_symbols.0, branch_symbols.1),
+        condition_text: condition_text.map(str::to_string),
+        condition,
+        probability,
+        replacement,
+    })
+}
+
+/// Order the rules for each symbol the way the unity compiler does: deterministic rules in file order,
+///     then one stochastic rule for each group of outcomes sharing a symbol, parameter count, context and condition.
+///     the rules of each symbol are then stably sorted so those with the longest context are tried first
+fn group_rules(parsed_rules: Vec<ParsedRule>, files: &[SourceFile]) -> Result<HashMap<i32, Vec<Rule>>, RuleFileParseError> {
+    let mut rules_by_symbol: HashMap<i32, Vec<Rule>> = HashMap::new();
+    let key = |rule: &ParsedRule| (rule.symbol, rule.parameter_count, rule.prefix.clone(), rule.suffix.clone(), rule.condition_text.clone());
+    let error = |rule: &ParsedRule, message: String| RuleFileParseError {
+        file: files[rule.file_index].identifier.clone(),
+        line: rule.line,
+        message,
+    };
+
+    let mut deterministic_keys = Vec::new();
+    let mut stochastic_groups: Vec<Vec<ParsedRule>> = Vec::new();
+    for rule in parsed_rules {
+        if rule.probability.is_none() {
+            if deterministic_keys.contains(&key(&rule)) {
+                return Err(error(&rule, "Another rule already matches the same symbol and condition".to_string()));
+            }
+            deterministic_keys.push(key(&rule));
+            rules_by_symbol.entry(rule.symbol).or_default().push(Rule {
+                symbol: rule.symbol,
+                parameter_count: rule.parameter_count,
+                prefix: rule.prefix,
+                suffix: rule.suffix,
+                file_index: rule.file_index,
+                condition: rule.condition,
+                outcomes: vec![RuleOutcome {
+                    probability: 1.0,
+                    replacement: rule.replacement,
+                }],
+            });
+            continue;
+        }
+        match stochastic_groups.iter_mut().find(|group| key(&group[0]) == key(&rule)) {
+            Some(group) => group.push(rule),
+            None => stochastic_groups.push(vec![rule]),
+        }
+    }
+
+    for group in stochastic_groups {
+        let total_probability: f64 = group.iter().filter_map(|rule| rule.probability).sum();
+        if (total_probability - 1.0).abs() > 1e-5 {
+            return Err(error(&group[0], format!("Probabilities of the rule group add up to {}, not 1", total_probability)));
+        }
+        if group.len() > u8::MAX as usize + 1 {
+            return Err(error(&group[0], "Too many outcomes in the rule group".to_string()));
+        }
+        let mut group = group.into_iter();
+        let first = group.next().unwrap();
+        let mut outcomes = vec![RuleOutcome {
+            probability: first.probability.unwrap(),
+            replacement: first.replacement,
+        }];
+        outcomes.extend(group.map(|rule| RuleOutcome {
+            probability: rule.probability.unwrap(),
+            replacement: rule.replacement,
+        }));
+        rules_by_symbol.entry(first.symbol).or_default().push(Rule {
+            symbol: first.symbol,
+            parameter_count: first.parameter_count,
+            prefix: first.prefix,
+            suffix: first.suffix,
+            file_index: first.file_index,
+            condition: first.condition,
+            outcomes,
+        });
+    }
+
+    for rules in rules_by_symbol.values_mut() {
+        rules.sort_by_key(|rule| Reverse(rule.prefix.len() + rule.suffix.symbols().len()));
+    }
+    if let Some(rules) = rules_by_symbol.values().find(|rules| rules.len() > u8::MAX as usize + 1) {
+        return Err(RuleFileParseError {
+            file: String::new(),
+            line: 0,
+            message: format!("Too many rules for symbol {}", rules[0].symbol),
+        });
+    }
+    Ok(rules_by_symbol)
+}
+
+/// a directive including another file, which writes the symbols it exports by name with characters of the including file
+struct Include {
+    line: usize,
+    identifier: String,
+    imports: Vec<(String, char)>,
+}
+
+/// one file of an L-system, read but not yet linked
+struct SourceFile {
+    /// the path of the file, or the name of a builtin library
+    identifier: String,
+    axiom: Option<(usize, String)>,
+    iterations: Option<u32>,
+    runtime_parameters: Vec<(usize, String, f32)>,
+    defines: Vec<(usize, String, String)>,
+    rule_lines: Vec<(usize, String)>,
+    includes: Vec<Include>,
+    exports: Vec<(usize, String, char)>,
+    global_characters: Vec<char>,
+    /// the branch symbols, and the characters listed by #matches
+    context_characters: Vec<char>,
+}
+
+/// the path of a file included from the including file. builtin libraries are included by name.
+///     . and .. are resolved, so a file included by two paths is linked once
+fn include_identifier(including: &str, path: &str) -> String {
+    if BUILTIN_LIBRARIES.iter().any(|(name, _)| *name == path) {
+        return path.to_string();
+    }
+    let joined = Path::new(including).parent().unwrap_or(Path::new("")).join(path);
+    let mut normalized = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized.to_string_lossy().into_owned()
+}
+
+/// read the directives and rule lines of one file. rules are parsed once the files are linked
+fn read_source_file(identifier: &str, text: &str, is_library: bool) -> Result<SourceFile, RuleFileParseError> {
+    let mut file = SourceFile {
+        identifier: identifier.to_string(),
+        axiom: None,
+        iterations: None,
+        runtime_parameters: Vec::new(),
+        defines: Vec::new(),
+        rule_lines: Vec::new(),
+        includes: Vec::new(),
+        exports: Vec::new(),
+        global_characters: Vec::new(),
+        context_characters: vec!['[', ']'],
+    };
+    let add_characters = |characters: &mut Vec<char>, text: &str| {
+        for character in text.chars().filter(|character| !character.is_whitespace()) {
+            if !characters.contains(&character) {
+                characters.push(character);
+            }
+        }
+    };
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let error = |message: String| RuleFileParseError {
+            file: identifier.to_string(),
+            line: line_number,
+            message,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("##") {
+            continue;
+        }
+        let Some(directive) = line.strip_prefix('#') else {
+            file.rule_lines.push((line_number, line.to_string()));
+            continue;
+        };
+        let Some((directive, parameter)) = directive.split_once(char::is_whitespace) else {
+            return Err(error(format!("Directive #{} expects a value", directive)));
+        };
+        let parameter = parameter.trim();
+        match directive {
+            "axiom" | "iterations" if is_library => return Err(error(format!("#{} cannot be used in a library", directive))),
+            "axiom" => file.axiom = Some((line_number, parameter.to_string())),
+            "iterations" => {
+                let count = parameter.parse::<u32>()
+                    .map_err(|_| error(format!("#iterations expects a whole number, found {}", parameter)))?;
+                file.iterations = Some(count);
+            }
+            "runtime" => {
+                let (name, value) = parameter.split_once(char::is_whitespace)
+                    .ok_or_else(|| error("#runtime expects a name and a default value".to_string()))?;
+                let value = value.trim().parse::<f32>()
+                    .map_err(|_| error(format!("#runtime expects a number, found {}", value.trim())))?;
+                file.runtime_parameters.push((line_number, name.to_string(), value));
+            }
+            "define" => {
+                let (name, replacement) = parameter.split_once(char::is_whitespace)
+                    .ok_or_else(|| error("#define expects a name and a replacement".to_string()))?;
+                file.defines.push((line_number, name.to_string(), replacement.trim().to_string()));
+            }
+            "include" => {
+                let (path, remapping) = parameter.split_once(char::is_whitespace).unwrap_or((parameter, ""));
+                let mut imports = Vec::new();
+                for remap in remapping.split_whitespace() {
+                    let import = remap.strip_prefix('(').and_then(|remap| remap.strip_suffix(')'))
+                        .and_then(|remap| remap.split_once("->"))
+                        .filter(|(_, character)| character.chars().count() == 1);
+                    let Some((name, character)) = import else {
+                        return Err(error(format!("Expected an import such as (Name->c), found {}", remap)));
+                    };
+                    imports.push((name.to_string(), character.chars().next().unwrap()));
+                }
+                file.includes.push(Include {
+                    line: line_number,
+                    identifier: include_identifier(identifier, path),
+                    imports,
+                });
+            }
+            "export" if !is_library => return Err(error("#export can only be used in a library".to_string())),
+            "export" => {
+                let export = parameter.split_once(char::is_whitespace)
+                    .map(|(name, character)| (name, character.trim()))
+                    .filter(|(_, character)| character.chars().count() == 1);
+                let Some((name, character)) = export else {
+                    return Err(error("#export expects a name and a symbol".to_string()));
+                };
+                file.exports.push((line_number, name.to_string(), character.chars().next().unwrap()));
+            }
+            "matches" => add_characters(&mut file.context_characters, parameter),
+            "global" => add_characters(&mut file.global_characters, parameter),
+            // only used by the unity compiler to check symbols and to mark immature organs
+            "symbols" | "immature" => {}
+            _ => return Err(error(format!("Unknown directive #{}", directive))),
+        }
+    }
+    Ok(file)
+}
+
+/// the files in the order each is included after the files it includes, failing on an include cycle
+fn leaf_first_order(files: &[SourceFile], file_indexes: &HashMap<String, usize>) -> Result<Vec<usize>, RuleFileParseError> {
+    fn visit(
+        file_index: usize,
+        files: &[SourceFile],
+        file_indexes: &HashMap<String, usize>,
+        visiting: &mut Vec<usize>,
+        order: &mut Vec<usize>) -> Result<(), RuleFileParseError> {
+        if order.contains(&file_index) {
+            return Ok(());
+        }
+        visiting.push(file_index);
+        for include in files[file_index].includes.iter() {
+            let included = file_indexes[&include.identifier];
+            if visiting.contains(&included) {
+                return Err(RuleFileParseError {
+                    file: files[file_index].identifier.clone(),
+                    line: include.line,
+                    message: format!("{} is included in a cycle", include.identifier),
+                });
+            }
+            visit(included, files, file_indexes, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(file_index);
+        Ok(())
+    }
+
+    let mut order = Vec::with_capacity(files.len());
+    visit(0, files, file_indexes, &mut Vec::new(), &mut order)?;
+    Ok(order)
+}
+
+/// Load an .lsystem file in the format read by the unity compiler, along with every file it includes.
+///     read_file gives the text of a file by its path, or None when it cannot be read.
+///     includes such as `#include ../sizing.lsyslib (Export->c)` are resolved relative to the including file, except for
+///     the builtin libraries such as `#include diffusion (Node->n) (Amount->a)` which are included by name.
+///     as in unity, each library has symbols of its own, shared only through #export, #global and the branch symbols.
+///     the loaded file names its symbols by their character, library symbols are named by their character and the library,
+///     such as "c:sizing". defines and runtime parameters are shared by every file
+pub fn link_lsystem_files(
+    path: &str,
+    mut read_file: impl FnMut(&str) -> Option<String>,
+    names: &mut SymbolNames) -> Result<LSystemFile, RuleFileParseError> {
+    let link_error = |file: &str, line: usize, message: String| RuleFileParseError {
+        file: file.to_string(),
+        line,
+        message,
+    };
+    let root_identifier = include_identifier("", path);
+    if root_identifier.ends_with(".lsyslib") {
+        return Err(link_error(&root_identifier, 0, "A library cannot be loaded on its own".to_string()));
+    }
+
+    // files are read in the same order as unity, which decides the order of rules, defines and runtime parameters
+    let mut files: Vec<SourceFile> = Vec::new();
+    let mut file_indexes: HashMap<String, usize> = HashMap::new();
+    let mut pending = vec![(root_identifier, None)];
+    while let Some((identifier, included_from)) = pending.pop() {
+        if file_indexes.contains_key(&identifier) {
+            continue;
+        }
+        let builtin = BUILTIN_LIBRARIES.iter().find(|(name, _)| *name == identifier);
+        let file = match builtin {
+            Some((_, text)) => read_source_file(&identifier, text, true)?,
+            None => {
+                let Some(text) = read_file(&identifier) else {
+                    let (including, line) = included_from.unwrap_or((String::new(), 0));
+                    return Err(link_error(&including, line, format!("Could not read {}", identifier)));
+                };
+                read_source_file(&identifier, &text, identifier.ends_with(".lsyslib"))?
+            }
+        };
+        for include in file.includes.iter() {
+            pending.push((include.identifier.clone(), Some((identifier.clone(), include.line))));
+        }
+        file_indexes.insert(identifier, files.len());
+        files.push(file);
+    }
+
+    let branch_symbols = (
+        symbol_for('[', names).map_err(|message| link_error(&files[0].identifier, 0, message))?,
+        symbol_for(']', names).map_err(|message| link_error(&files[0].identifier, 0, message))?,
+    );
+    let mut file_symbols: Vec<FileSymbols> = files.iter().enumerate().map(|(file_index, file)| FileSymbols {
+        by_character: HashMap::new(),
+        is_root: file_index == 0,
+        global_characters: file.global_characters.clone(),
+        label: Path::new(&file.identifier).file_stem()
+            .map_or(file.identifier.clone(), |stem| stem.to_string_lossy().into_owned())
+            .replace(char::is_whitespace, "_"),
+    }).collect();
+    // an exported symbol takes the symbol of the first file to import it, so the loaded file names imports by its own characters.
+    //     every file is visited before the files it includes
+    for file_index in leaf_first_order(&files, &file_indexes)?.into_iter().rev() {
+        for include in files[file_index].includes.iter() {
+            let error = |message: String| link_error(&files[file_index].identifier, include.line, message);
+            let included = file_indexes[&include.identifier];
+            for (name, character) in include.imports.iter() {
+                let Some((_, _, exported)) = files[included].exports.iter().find(|(_, exported_name, _)| exported_name == name) else {
+                    return Err(error(format!("{} does not export {}", include.identifier, name)));
+                };
+                match file_symbols[included].by_character.get(exported).copied() {
+                    Some(symbol) => file_symbols[file_index].share(*character, symbol),
+                    None => {
+                        let symbol = file_symbols[file_index].symbol(*character, names).map_err(error)?;
+                        file_symbols[included].share(*exported, symbol)
+                    }
+                }.map_err(error)?;
+            }
+        }
+    }
+
+    let mut global_names: Vec<&str> = Vec::new();
+    let mut global_parameters = Vec::new();
+    let mut defines: Vec<(&str, &str)> = Vec::new();
+    for file in files.iter() {
+        for (line, name, value) in file.runtime_parameters.iter() {
+            if global_names.contains(&name.as_str()) {
+                return Err(link_error(&file.identifier, *line, format!("Runtime parameter {} is declared twice", name)));
+            }
+            global_names.push(name);
+            global_parameters.push(*value);
+        }
+        for (line, name, replacement) in file.defines.iter() {
+            if defines.iter().any(|(defined, _)| *defined == name.as_str()) {
+                return Err(link_error(&file.identifier, *line, format!("{} is defined twice", name)));
+            }
+            defines.push((name.as_str(), replacement.as_str()));
+        }
+    }
+
+    let mut parsed_rules = Vec::new();
+    for (file_index, file) in files.iter().enumerate() {
+        for (line_number, line) in file.rule_lines.iter() {
+            // later defines are applied first, as in unity
+            let mut line = line.to_string();
+            for (name, replacement) in defines.iter().rev() {
+                line = line.replace(name, replacement);
+            }
+            let rule = parse_rule(*line_number, &line, &global_names, file_index, &mut file_symbols[file_index], names, branch_symbols)
+                .map_err(|message| link_error(&file.identifier, *line_number, message))?;
+            parsed_rules.push(rule);
+        }
+    }
+    let mut context_symbols_by_file = Vec::with_capacity(files.len());
+    for (file, symbols) in files.iter().zip(file_symbols.iter_mut()) {
+        let mut context_symbols = file.context_characters.iter()
+            .map(|character| symbols.symbol(*character, names))
+            .collect::<Result<Vec<i32>, String>>()
+            .map_err(|message| link_error(&file.identifier, 0, message))?;
+        context_symbols.sort_unstable();
+        context_symbols.dedup();
+        context_symbols_by_file.push(context_symbols);
+    }
+
+    let root = &files[0];
+    let mut axiom = None;
+    if let Some((axiom_line, axiom_text)) = &root.axiom {
+        let axiom_symbols = parse_replacement(axiom_text, &global_names, &mut file_symbols[0], names)
+            .map_err(|message| link_error(&root.identifier, *axiom_line, format!("Invalid axiom: {}", message)))?;
+        let mut axiom_string = SymbolStringOwned {
+            symbols: Vec::with_capacity(axiom_symbols.len()),
+            param_indexing: Vec::with_capacity(axiom_symbols.len()),
+            parameters: Vec::new(),
+        };
+        for symbol in axiom_symbols {
+            axiom_string.symbols.push(symbol.symbol);
+            axiom_string.param_indexing.push(JaggedIndexing {
+                index: axiom_string.parameters.len() as i32,
+                length: symbol.parameters.len() as u16,
+            });
+            axiom_string.parameters.extend(symbol.parameters.iter().map(|expression| evaluate_expression(expression, &global_parameters, &[])));
+        }
+        axiom = Some(axiom_string);
+    }
+
+    let diffusion_symbols = match file_indexes.get("diffusion") {
+        Some(diffusion) => {
+            let symbols = &mut file_symbols[*diffusion];
+            let node = symbols.symbol('n', names);
+            let amount = symbols.symbol('a', names);
+            node.and_then(|node| Ok((node, amount?))).ok()
+        }
+        None => None,
+    };
+    Ok(LSystemFile {
+        axiom,
+        iterations: root.iterations,
+        rules: RuleSet {
+            global_parameters,
+            branch_open_symbol: branch_symbols.0,
+            branch_close_symbol: branch_symbols.1,
+            rules_by_symbol: group_rules(parsed_rules, &files)?,
+            context_symbols_by_file,
+        },
+        diffusion_symbols,
+    })
+}
+
+/// Load an .lsystem file from disk, along with every file it includes. see link_lsystem_files
+pub fn load_lsystem_file(path: &str, names: &mut SymbolNames) -> Result<LSystemFile, RuleFileParseError> {
+    link_lsystem_files(path, |path| fs::read_to_string(path).ok(), names)
+}
+
+/// Parse a single .lsystem file in the format read by the unity compiler, such as
+///     `#axiom A`, `#iterations 5`, `#runtime growth 0.5`, `#define step 2` and rules such as `P(0.5) | A(x) : x > 1 -> B(x - 1)[A]`.
+///     rules may match a context, such as `A < B(x) > [C]D -> E(x)`, seeing only the branch symbols and those listed by #matches.
+///     symbols are single characters, matched against the names or else given their character code as an id.
+///     the branch symbols [ and ] are always named. only builtin libraries can be included, use load_lsystem_file for others
+pub fn parse_lsystem_file(text: &str, names: &mut SymbolNames) -> Result<LSystemFile, RuleFileParseError> {
+    link_lsystem_files("", |path| path.is_empty().then(|| text.to_string()), names)
+}
@@ -0,0 +1,289 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Matches the memory layout of a unity Vector3
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    pub const ONE: Vec3 = Vec3::new(1.0, 1.0, 1.0);
+    pub const RIGHT: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Vec3 { x, y, z }
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// returns the zero vector when this vector has no length
+    pub fn normalized(self) -> Vec3 {
+        let length = self.length();
+        if length <= f32::EPSILON {
+            Vec3::ZERO
+        } else {
+            self * (1.0 / length)
+        }
+    }
+
+    pub fn scale(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+
+    pub fn min(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z))
+    }
+
+    pub fn max(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+    fn mul(self, scale: f32) -> Vec3 {
+        Vec3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// Matches the memory layout of a unity Vector3Int
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vec3Int {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Vec3Int {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Vec3Int { x, y, z }
+    }
+}
+
+impl Add for Vec3Int {
+    type Output = Vec3Int;
+    fn add(self, other: Vec3Int) -> Vec3Int {
+        Vec3Int::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+/// Matches the memory layout of a unity Matrix4x4, which is stored column major
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    pub columns: [[f32; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        columns: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        let mut translation = Mat4::IDENTITY;
+        translation.columns[3] = [offset.x, offset.y, offset.z, 1.0];
+        translation
+    }
+
+    pub fn scale(scale: Vec3) -> Mat4 {
+        let mut scaled = Mat4::IDENTITY;
+        scaled.columns[0][0] = scale.x;
+        scaled.columns[1][1] = scale.y;
+        scaled.columns[2][2] = scale.z;
+        scaled
+    }
+
+    /// rotation by the angle in degrees about the normalized axis, as unity Quaternion.AngleAxis
+    pub fn rotation(axis: Vec3, degrees: f32) -> Mat4 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let one_minus_cos = 1.0 - cos;
+        let Vec3 { x, y, z } = axis;
+        Mat4 {
+            columns: [
+                [cos + x * x * one_minus_cos, y * x * one_minus_cos + z * sin, z * x * one_minus_cos - y * sin, 0.0],
+                [x * y * one_minus_cos - z * sin, cos + y * y * one_minus_cos, z * y * one_minus_cos + x * sin, 0.0],
+                [x * z * one_minus_cos + y * sin, y * z * one_minus_cos - x * sin, cos + z * z * one_minus_cos, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn multiply_point(&self, point: Vec3) -> Vec3 {
+        self.multiply_vector(point) + Vec3::new(self.columns[3][0], self.columns[3][1], self.columns[3][2])
+    }
+
+    /// transform a direction, ignoring translation
+    pub fn multiply_vector(&self, vector: Vec3) -> Vec3 {
+        let [x_axis, y_axis, z_axis, _] = self.columns;
+        Vec3::new(
+            x_axis[0] * vector.x + y_axis[0] * vector.y + z_axis[0] * vector.z,
+            x_axis[1] * vector.x + y_axis[1] * vector.y + z_axis[1] * vector.z,
+            x_axis[2] * vector.x + y_axis[2] * vector.y + z_axis[2] * vector.z,
+        )
+    }
+
+    /// the inverse of a matrix made of translations, rotations and scales. None when a scale is zero
+    pub fn inverse_affine(&self) -> Option<Mat4> {
+        let [x_axis, y_axis, z_axis, translation] = self.columns;
+        let x_axis = Vec3::new(x_axis[0], x_axis[1], x_axis[2]);
+        let y_axis = Vec3::new(y_axis[0], y_axis[1], y_axis[2]);
+        let z_axis = Vec3::new(z_axis[0], z_axis[1], z_axis[2]);
+        let determinant = x_axis.dot(y_axis.cross(z_axis));
+        if determinant.abs() <= f32::EPSILON * f32::EPSILON {
+            return None;
+        }
+
+        // the rows of the inverse are the cross products of the columns, over the determinant
+        let inverse_rows = [
+            y_axis.cross(z_axis) * (1.0 / determinant),
+            z_axis.cross(x_axis) * (1.0 / determinant),
+            x_axis.cross(y_axis) * (1.0 / determinant),
+        ];
+        let mut inverse = Mat4::IDENTITY;
+        for (row, inverse_row) in inverse_rows.iter().enumerate() {
+            inverse.columns[0][row] = inverse_row.x;
+            inverse.columns[1][row] = inverse_row.y;
+            inverse.columns[2][row] = inverse_row.z;
+        }
+        let inverse_translation = -inverse.multiply_vector(Vec3::new(translation[0], translation[1], translation[2]));
+        inverse.columns[3] = [inverse_translation.x, inverse_translation.y, inverse_translation.z, 1.0];
+        Some(inverse)
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut product = [[0.0; 4]; 4];
+        for (column, product_column) in product.iter_mut().enumerate() {
+            for (row, product_value) in product_column.iter_mut().enumerate() {
+                *product_value = (0..4)
+                    .map(|i| self.columns[i][row] * other.columns[column][i])
+                    .sum();
+            }
+        }
+        Mat4 {
+            columns: product,
+        }
+    }
+}
+
+/// An axis aligned bounding box. the empty bounds has min above max, so encapsulating any point replaces it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Bounds::EMPTY
+    }
+}
+
+impl Bounds {
+    pub const EMPTY: Bounds = Bounds {
+        min: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        max: Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+    };
+
+    pub fn from_center_extents(center: Vec3, extents: Vec3) -> Bounds {
+        Bounds {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// half the size along each axis
+    pub fn extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn encapsulate_point(&mut self, point: Vec3) {
+        self.min = self.min.min(point);
+        self.max = self.max.max(point);
+    }
+
+    pub fn encapsulate(&mut self, other: &Bounds) {
+        if other.is_empty() {
+            return;
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// the smallest axis aligned bounds holding this box after the transform
+    pub fn transformed(&self, transform: &Mat4) -> Bounds {
+        if self.is_empty() {
+            return Bounds::EMPTY;
+        }
+        let extents = self.extents();
+        let [x_axis, y_axis, z_axis, _] = transform.columns;
+        let transformed_extents = Vec3::new(
+            (x_axis[0] * extents.x).abs() + (y_axis[0] * extents.y).abs() + (z_axis[0] * extents.z).abs(),
+            (x_axis[1] * extents.x).abs() + (y_axis[1] * extents.y).abs() + (z_axis[1] * extents.z).abs(),
+            (x_axis[2] * extents.x).abs() + (y_axis[2] * extents.y).abs() + (z_axis[2] * extents.z).abs(),
+        );
+        Bounds::from_center_extents(transform.multiply_point(self.center()), transformed_extents)
+    }
+}
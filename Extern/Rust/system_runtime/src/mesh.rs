@@ -0,0 +1,4 @@
+pub mod builder;
+pub mod obj;
+pub mod gltf;
+pub mod bounds;
@@ -0,0 +1,221 @@
+use crate::diffusion::branch_tree::BranchTree;
+use crate::math::{Bounds, Vec3};
+use crate::mesh::builder::{meshed_stem_class, StemClass, STEM_MIDPOINT};
+use crate::turtle::state::{TurtleOrganInstance, TurtleStemInstance};
+
+/// the bounds of the ring of vertices built for the stem. covers the whole circle the ring is cut from,
+///     so it holds for any radial resolution
+pub fn stem_bounds(stem: &TurtleStemInstance) -> Bounds {
+    // the ring is the unit circle about the midpoint in the stem's local y z plane
+    let [_, y_axis, z_axis, _] = stem.orientation.columns;
+    let extents = Vec3::new(
+        y_axis[0].hypot(z_axis[0]),
+        y_axis[1].hypot(z_axis[1]),
+        y_axis[2].hypot(z_axis[2]),
+    );
+    Bounds::from_center_extents(stem.orientation.multiply_point(STEM_MIDPOINT), extents)
+}
+
+/// the bounds of the organ's template after placement. None when the organ has no template bounds
+pub fn organ_bounds(organ: &TurtleOrganInstance, template_bounds: &[Bounds]) -> Option<Bounds> {
+    let template_bounds = usize::try_from(organ.organ_template_index).ok()
+        .and_then(|index| template_bounds.get(index))?;
+    Some(template_bounds.transformed(&organ.organ_transform))
+}
+
+/// the bounds of every organ with template bounds, paired with the index of the symbol which placed it
+fn organ_instance_bounds<'a>(
+    organs: &'a [TurtleOrganInstance],
+    template_bounds: &'a [Bounds]) -> impl Iterator<Item = (i32, Bounds)> + 'a {
+    organs.iter()
+        .filter_map(|organ| Some((organ.symbol_index, organ_bounds(organ, template_bounds)?)))
+}
+
+/// the bounds of every stem which would be meshed, paired with the index of the stem
+fn stem_instance_bounds<'a>(
+    stems: &'a [TurtleStemInstance],
+    stem_classes: &'a [StemClass]) -> impl Iterator<Item = (usize, Bounds)> + 'a {
+    stems.iter()
+        .enumerate()
+        .filter(|(_, stem)| meshed_stem_class(stem, stem_classes).is_some())
+        .map(|(stem_index, stem)| (stem_index, stem_bounds(stem)))
+}
+
+/// Compute the bounds of the mesh built from the turtle output, without building it.
+///     template_bounds is indexed by organ template index, usually from OrganTemplateMesh::bounds.
+///     stems are skipped when build_plant_mesh would skip them, by the same stem classes.
+///     stems are bounded by their full rings, so the result may be slightly larger than the built mesh
+pub fn plant_bounds(
+    organs: &[TurtleOrganInstance],
+    stems: &[TurtleStemInstance],
+    stem_classes: &[StemClass],
+    template_bounds: &[Bounds]) -> Bounds {
+    let mut bounds = Bounds::EMPTY;
+    for (_, instance_bounds) in organ_instance_bounds(organs, template_bounds) {
+        bounds.encapsulate(&instance_bounds);
+    }
+    for (_, instance_bounds) in stem_instance_bounds(stems, stem_classes) {
+        bounds.encapsulate(&instance_bounds);
+    }
+    bounds
+}
+
+/// Compute a bounding hierarchy over the branches of the string the turtle output was interpreted from.
+///     indexed by branch id, each bounds holds the organs and stems placed in that branch and every branch inside it,
+///     so the root bounds covers the whole plant. stem_symbol_indexes is parallel to stems, as in TurtleOutput.
+///     instances placed by symbols outside the tree are skipped, as are stems without a symbol index
+pub fn branch_bounds(
+    organs: &[TurtleOrganInstance],
+    stems: &[TurtleStemInstance],
+    stem_symbol_indexes: &[i32],
+    stem_classes: &[StemClass],
+    template_bounds: &[Bounds],
+    branch_tree: &BranchTree) -> Vec<Bounds> {
+    let mut bounds = vec![Bounds::EMPTY; branch_tree.branches().len()];
+    let stem_bounds = stem_instance_bounds(stems, stem_classes)
+        .filter_map(|(stem_index, stem_bounds)| Some((*stem_symbol_indexes.get(stem_index)?, stem_bounds)));
+    for (symbol_index, instance_bounds) in organ_instance_bounds(organs, template_bounds).chain(stem_bounds) {
+        let Some(symbol_index) = usize::try_from(symbol_index).ok().filter(|index| *index < branch_tree.len()) else {
+            continue;
+        };
+        bounds[branch_tree.branch_of(symbol_index)].encapsulate(&instance_bounds);
+    }
+
+    // child branches always come after their parent, so walking backwards folds each subtree in before its parent is read
+    for branch_id in (1..bounds.len()).rev() {
+        let parent_branch = branch_tree.branch(branch_id).parent_branch as usize;
+        let child_bounds = bounds[branch_id];
+        bounds[parent_branch].encapsulate(&child_bounds);
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::symbol_element_remap::SymbolStringOwned;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+    use crate::math::Mat4;
+    use crate::mesh::builder::{build_plant_mesh, OrganTemplateMesh, TemplateVertex};
+    use crate::turtle::interpreter::{interpret_turtle, TurtleOperationTable, TurtleSymbols};
+    use crate::turtle::operations::{OrganOperation, StemOperation, TurtleOperation, TurtleOrganTemplate, TurtleOutput};
+    use crate::turtle::state::TurtleState;
+
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+    const STEM: i32 = 1;
+    const TURN: i32 = 2;
+    const LEAF: i32 = 3;
+    const THIN: i32 = 4;
+
+    const STEM_CLASSES: [StemClass; 1] = [StemClass { material_index: 0, radial_resolution: 5 }];
+
+    fn leaf_mesh() -> OrganTemplateMesh {
+        let vertex = |x: f32, y: f32| TemplateVertex {
+            position: Vec3::new(x, y, 0.25),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            uv: [x, y],
+        };
+        OrganTemplateMesh {
+            vertices: vec![vertex(0.0, 0.0), vertex(2.0, 0.5), vertex(1.0, -0.5)],
+            triangles: vec![0, 1, 2],
+            material_index: 1,
+        }
+    }
+
+    fn interpret(text: &str) -> (SymbolStringOwned, TurtleOutput) {
+        let mut operations = TurtleOperationTable::new();
+        operations
+            .add(STEM, TurtleOperation::AddStem(StemOperation {
+                stem_class_index: 0,
+                will_move: true,
+                scale_by_parameter: false,
+                scale_by_thickness: true,
+                length_radius_base: [1.0, 0.2],
+                length_radius_scale: [1.0, 1.0],
+                scale_is_additional: false,
+                scale_power: 1.0,
+            }))
+            .add(TURN, TurtleOperation::Rotate { axis: Vec3::new(0.0, 0.0, 1.0), default_degrees: 35.0 })
+            .add(LEAF, TurtleOperation::AddOrgan(OrganOperation {
+                organ_templates: 0..1,
+                do_scale: false,
+                scale_is_additional: false,
+                scale_power: 1.0,
+                extra_non_uniform_scale: Vec3::ONE,
+                apply_thickness: false,
+            }))
+            .add(THIN, TurtleOperation::ScaleThickness { default_factor: 0.5 });
+        operations.organ_templates.push(TurtleOrganTemplate {
+            base_mesh_transform: Mat4::rotation(Vec3::RIGHT, 60.0),
+            translation: Vec3::ZERO,
+            also_move: false,
+        });
+        let symbols = TurtleSymbols {
+            branch_open_symbol: OPEN,
+            branch_close_symbol: CLOSE,
+            organ_identity_symbol: None,
+            custom_data_symbol: None,
+        };
+        let symbol_string = parse_symbol_string(text, None).unwrap();
+        let output = interpret_turtle(&symbol_string.borrow(), &operations, &symbols, &TurtleState::default()).unwrap();
+        (symbol_string, output)
+    }
+
+    fn contains(bounds: &Bounds, point: Vec3) -> bool {
+        let tolerance = 1e-5;
+        point.x >= bounds.min.x - tolerance && point.x <= bounds.max.x + tolerance &&
+            point.y >= bounds.min.y - tolerance && point.y <= bounds.max.y + tolerance &&
+            point.z >= bounds.min.z - tolerance && point.z <= bounds.max.z + tolerance
+    }
+
+    fn contains_bounds(outer: &Bounds, inner: &Bounds) -> bool {
+        contains(outer, inner.min) && contains(outer, inner.max)
+    }
+
+    const PLANT: &str = "1 2 1 -10 2(-80) 1 4 1 3 -11 -10 2(120) 4 1 -10 2 1 3 -11 1 3 -11 2 1 3";
+
+    #[test]
+    fn plant_bounds_hold_every_vertex_of_the_mesh() {
+        let (_, output) = interpret(PLANT);
+        let leaf = leaf_mesh();
+        let mesh = build_plant_mesh(&output, std::slice::from_ref(&leaf), &STEM_CLASSES);
+        let bounds = plant_bounds(&output.organs, &output.stems, &STEM_CLASSES, &[leaf.bounds()]);
+
+        assert_eq!(mesh.vertices.len(), 8 * 6 + 4 * 3);
+        for vertex in mesh.vertices.iter() {
+            assert!(contains(&bounds, vertex.position), "{:?} is outside {:?}", vertex.position, bounds);
+        }
+    }
+
+    #[test]
+    fn branch_bounds_fold_children_into_their_parents() {
+        let (symbol_string, output) = interpret(PLANT);
+        let leaf = leaf_mesh();
+        let branch_tree = BranchTree::new(&symbol_string.borrow(), OPEN, CLOSE, STEM).unwrap();
+        let bounds = branch_bounds(
+            &output.organs,
+            &output.stems,
+            &output.stem_symbol_indexes,
+            &STEM_CLASSES,
+            &[leaf.bounds()],
+            &branch_tree);
+
+        assert_eq!(bounds.len(), 4);
+        let whole_plant = plant_bounds(&output.organs, &output.stems, &STEM_CLASSES, &[leaf.bounds()]);
+        assert_eq!(bounds[0], whole_plant);
+        for branch_id in 1..bounds.len() {
+            assert!(!bounds[branch_id].is_empty());
+            let parent_branch = branch_tree.branch(branch_id).parent_branch as usize;
+            assert!(contains_bounds(&bounds[parent_branch], &bounds[branch_id]));
+        }
+
+        // the leaf of the nested branch is held by the branch around it, but not by the sibling branch
+        let nested = branch_tree.branch_of(16);
+        assert_eq!(branch_tree.branch(nested).parent_branch as usize, branch_tree.branch_of(11));
+        let nested_leaf = output.organs.iter().find(|organ| organ.symbol_index == 17).unwrap();
+        let nested_leaf_bounds = organ_bounds(nested_leaf, &[leaf.bounds()]).unwrap();
+        assert!(contains_bounds(&bounds[branch_tree.branch_of(11)], &nested_leaf_bounds));
+        assert!(!contains_bounds(&bounds[branch_tree.branch_of(3)], &nested_leaf_bounds));
+    }
+}
@@ -0,0 +1,331 @@
+use std::f32::consts::PI;
+use crate::math::{Bounds, Mat4, Vec3};
+use crate::turtle::operations::TurtleOutput;
+use crate::turtle::state::TurtleStemInstance;
+
+/// A vertex of an organ template mesh, in the template's local space
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TemplateVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: [f32; 2],
+}
+
+/// The mesh placed for each organ instance, indexed by organ template index
+#[derive(Clone, Debug, Default)]
+pub struct OrganTemplateMesh {
+    pub vertices: Vec<TemplateVertex>,
+    pub triangles: Vec<u32>,
+    pub material_index: usize,
+}
+
+impl OrganTemplateMesh {
+    pub fn bounds(&self) -> Bounds {
+        let mut bounds = Bounds::EMPTY;
+        for vertex in self.vertices.iter() {
+            bounds.encapsulate_point(vertex.position);
+        }
+        bounds
+    }
+}
+
+/// How stems of one class are meshed, indexed by stem class index
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StemClass {
+    pub material_index: usize,
+    /// the number of faces around each stem. stems with a resolution of 0 are not meshed
+    pub radial_resolution: u16,
+}
+
+/// the class the stem is meshed with. None when the stem has no class, or its class is not meshed
+pub fn meshed_stem_class<'a>(stem: &TurtleStemInstance, stem_classes: &'a [StemClass]) -> Option<&'a StemClass> {
+    usize::try_from(stem.stem_class_index).ok()
+        .and_then(|index| stem_classes.get(index))
+        .filter(|stem_class| stem_class.radial_resolution > 0)
+}
+
+/// Matches the memory layout of the unity MeshVertexLayout
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MeshVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: [f32; 2],
+    /// the bytes of the organ identity
+    pub color: [u8; 4],
+    /// the custom data of the turtle
+    pub extra_data: [u8; 4],
+}
+
+/// A plant mesh in the turtle's space, with one triangle list per material
+#[derive(Clone, Debug, Default)]
+pub struct PlantMesh {
+    pub vertices: Vec<MeshVertex>,
+    /// indexed by material index
+    pub submeshes: Vec<Vec<u32>>,
+}
+
+impl PlantMesh {
+    /// convert from unity's left handed space to the right handed space of obj and gltf,
+    ///     by mirroring along x and reversing the winding of every triangle to keep them facing outwards
+    pub fn to_right_handed(&self) -> PlantMesh {
+        let mirror = |vector: Vec3| Vec3::new(-vector.x, vector.y, vector.z);
+        PlantMesh {
+            vertices: self.vertices.iter()
+                .map(|vertex| MeshVertex {
+                    position: mirror(vertex.position),
+                    normal: mirror(vertex.normal),
+                    ..*vertex
+                })
+                .collect(),
+            submeshes: self.submeshes.iter()
+                .map(|triangles| triangles.chunks_exact(3)
+                    .flat_map(|triangle| [triangle[0], triangle[2], triangle[1]])
+                    .collect())
+                .collect(),
+        }
+    }
+
+    fn submesh(&mut self, material_index: usize) -> &mut Vec<u32> {
+        if self.submeshes.len() <= material_index {
+            self.submeshes.resize_with(material_index + 1, Vec::new);
+        }
+        &mut self.submeshes[material_index]
+    }
+}
+
+/// Where each stem's ring of vertices starts, and how far around and along the stem its texture runs
+struct StemRing {
+    first_vertex: u32,
+    uv_depth: f32,
+    normalized_angle_offset: f32,
+}
+
+/// the center of every stem ring, in the stem's local space
+pub(crate) const STEM_MIDPOINT: Vec3 = Vec3::new(0.5, 0.0, 0.0);
+
+/// the rotation about the parent's x axis which best lines the child's y axis up with the parent's,
+///     from -0.5 to 0.5 of a full turn
+fn normalized_circle_offset(parent: &Mat4, next: &Mat4) -> f32 {
+    let parent_plane_x = parent.multiply_vector(Vec3::new(0.0, 0.0, 1.0)).normalized();
+    let parent_plane_y = parent.multiply_vector(Vec3::new(0.0, 1.0, 0.0)).normalized();
+    let parent_plane_normal = parent.multiply_vector(Vec3::RIGHT);
+
+    let next_y = next.multiply_vector(Vec3::new(0.0, 1.0, 0.0));
+    let projected_y = parent_plane_x * parent_plane_x.dot(next_y) + parent_plane_y * parent_plane_y.dot(next_y);
+    let projected_y = projected_y.normalized();
+    if projected_y == Vec3::ZERO {
+        return 0.0;
+    }
+    let angle = parent_plane_y.dot(projected_y).clamp(-1.0, 1.0).acos();
+    let sign = if parent_plane_normal.dot(parent_plane_y.cross(projected_y)) < 0.0 { -1.0 } else { 1.0 };
+    sign * angle / (2.0 * PI)
+}
+
+/// Build the mesh of every organ and stem placed by the turtle, in the same way as the unity mesh building jobs.
+///     each stem is a ring of vertices around its midpoint, joined to the ring of its parent when both share a stem class,
+///     so a chain of stems forms a generalized cylinder. the radius of each ring comes from the turtle thickness.
+///     organs or stems without a template or class are skipped
+pub fn build_plant_mesh(
+    turtle_output: &TurtleOutput,
+    organ_meshes: &[OrganTemplateMesh],
+    stem_classes: &[StemClass]) -> PlantMesh {
+
+    let mut mesh = PlantMesh::default();
+    add_stems(&mut mesh, &turtle_output.stems, stem_classes);
+
+    for organ in turtle_output.organs.iter() {
+        let Some(template) = usize::try_from(organ.organ_template_index).ok().and_then(|index| organ_meshes.get(index)) else {
+            continue;
+        };
+        let first_vertex = mesh.vertices.len() as u32;
+        for vertex in template.vertices.iter() {
+            mesh.vertices.push(MeshVertex {
+                position: organ.organ_transform.multiply_point(vertex.position),
+                normal: organ.organ_transform.multiply_vector(vertex.normal).normalized(),
+                uv: vertex.uv,
+                color: organ.organ_identity.to_le_bytes(),
+                extra_data: organ.custom_data,
+            });
+        }
+        mesh.submesh(template.material_index)
+            .extend(template.triangles.iter().map(|vertex_index| vertex_index + first_vertex));
+    }
+    mesh
+}
+
+fn add_stems(mesh: &mut PlantMesh, stems: &[TurtleStemInstance], stem_classes: &[StemClass]) {
+    // parents are always placed before their children
+    let mut rings: Vec<Option<StemRing>> = Vec::with_capacity(stems.len());
+    for stem in stems.iter() {
+        let Some(stem_class) = meshed_stem_class(stem, stem_classes) else {
+            rings.push(None);
+            continue;
+        };
+        let parent = usize::try_from(stem.parent_index).ok()
+            .filter(|parent_index| *parent_index < rings.len())
+            .and_then(|parent_index| Some((&stems[parent_index], rings[parent_index].as_ref()?)));
+
+        let (uv_depth, normalized_angle_offset) = match parent {
+            Some((parent_stem, parent_ring)) => {
+                let circumference = stem.orientation.multiply_vector(Vec3::new(0.0, 1.0, 0.0)).length() * 2.0 * PI;
+                let distance_from_parent = (stem.orientation.multiply_point(STEM_MIDPOINT) - parent_stem.orientation.multiply_point(STEM_MIDPOINT)).length();
+                let uv_length = if circumference > 0.0 { distance_from_parent / circumference } else { 0.0 };
+                (
+                    parent_ring.uv_depth + uv_length,
+                    parent_ring.normalized_angle_offset + normalized_circle_offset(&parent_stem.orientation, &stem.orientation),
+                )
+            }
+            None => (0.0, 0.0),
+        };
+
+        let first_vertex = mesh.vertices.len() as u32;
+        let radial_resolution = stem_class.radial_resolution as u32;
+        for theta in 0..=radial_resolution {
+            let normalized = theta as f32 / radial_resolution as f32;
+            let (sin, cos) = ((normalized + normalized_angle_offset) * PI * 2.0).sin_cos();
+            let normal = Vec3::new(0.0, sin, cos);
+            mesh.vertices.push(MeshVertex {
+                position: stem.orientation.multiply_point(normal + STEM_MIDPOINT),
+                normal: stem.orientation.multiply_vector(normal).normalized(),
+                uv: [normalized, uv_depth],
+                color: stem.organ_identity.to_le_bytes(),
+                extra_data: stem.custom_data,
+            });
+        }
+
+        // only stems of the same class share a vertex count to join to
+        if let Some((parent_stem, parent_ring)) = parent {
+            if parent_stem.stem_class_index == stem.stem_class_index {
+                let triangles = mesh.submesh(stem_class.material_index);
+                for face in 0..radial_resolution {
+                    // the first and last vertex of each ring are at the same point, so no wrapping is needed
+                    let (parent_a, parent_b) = (parent_ring.first_vertex + face, parent_ring.first_vertex + face + 1);
+                    let (child_a, child_b) = (first_vertex + face, first_vertex + face + 1);
+                    triangles.extend_from_slice(&[parent_a, child_a, child_b, parent_a, child_b, parent_b]);
+                }
+            }
+        }
+
+        rings.push(Some(StemRing {
+            first_vertex,
+            uv_depth,
+            normalized_angle_offset,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::turtle::state::TurtleOrganInstance;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).length() < 1e-5, "{:?} is not close to {:?}", actual, expected);
+    }
+
+    fn stem(stem_class_index: i32, parent_index: i32, orientation: Mat4) -> TurtleStemInstance {
+        TurtleStemInstance {
+            stem_class_index,
+            orientation,
+            parent_index,
+            organ_identity: 7,
+            custom_data: [1, 2, 3, 4],
+        }
+    }
+
+    fn turtle_output(stems: Vec<TurtleStemInstance>, organs: Vec<TurtleOrganInstance>) -> TurtleOutput {
+        TurtleOutput {
+            stem_symbol_indexes: (0..stems.len() as i32).collect(),
+            organs,
+            stems,
+        }
+    }
+
+    const STEM_CLASSES: [StemClass; 2] = [
+        StemClass { material_index: 1, radial_resolution: 4 },
+        StemClass { material_index: 0, radial_resolution: 3 },
+    ];
+
+    #[test]
+    fn stem_ring_circles_the_midpoint() {
+        let output = turtle_output(vec![stem(0, -1, Mat4::IDENTITY)], vec![]);
+        let mesh = build_plant_mesh(&output, &[], &STEM_CLASSES);
+
+        // the first vertex is repeated at the end of the ring to close the texture seam
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_close(mesh.vertices[0].position, Vec3::new(0.5, 0.0, 1.0));
+        assert_close(mesh.vertices[1].position, Vec3::new(0.5, 1.0, 0.0));
+        assert_close(mesh.vertices[2].position, Vec3::new(0.5, 0.0, -1.0));
+        assert_close(mesh.vertices[4].position, mesh.vertices[0].position);
+        assert_eq!(mesh.vertices[0].color, 7u32.to_le_bytes());
+        assert_eq!(mesh.vertices[0].extra_data, [1, 2, 3, 4]);
+        // a stem without a parent has nothing to join to
+        assert!(mesh.submeshes.iter().all(|triangles| triangles.is_empty()));
+    }
+
+    #[test]
+    fn child_ring_is_joined_to_its_parent() {
+        let output = turtle_output(vec![
+            stem(1, -1, Mat4::IDENTITY),
+            stem(1, 0, Mat4::translation(Vec3::RIGHT)),
+        ], vec![]);
+        let mesh = build_plant_mesh(&output, &[], &STEM_CLASSES);
+
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_eq!(mesh.submeshes, vec![vec![
+            0, 4, 5, 0, 5, 1,
+            1, 5, 6, 1, 6, 2,
+            2, 6, 7, 2, 7, 3,
+        ]]);
+        assert_eq!(mesh.vertices[4].uv, [0.0, 1.0 / (2.0 * PI)]);
+    }
+
+    #[test]
+    fn stems_of_different_classes_are_not_joined() {
+        let output = turtle_output(vec![
+            stem(0, -1, Mat4::IDENTITY),
+            stem(1, 0, Mat4::translation(Vec3::RIGHT)),
+            stem(-1, 1, Mat4::IDENTITY),
+        ], vec![]);
+        let mesh = build_plant_mesh(&output, &[], &STEM_CLASSES);
+
+        assert_eq!(mesh.vertices.len(), 5 + 4);
+        assert!(mesh.submeshes.iter().all(|triangles| triangles.is_empty()));
+    }
+
+    #[test]
+    fn organ_vertices_are_transformed_and_carry_the_identity() {
+        let template = OrganTemplateMesh {
+            vertices: vec![
+                TemplateVertex { position: Vec3::ZERO, normal: Vec3::new(0.0, 0.0, 1.0), uv: [0.0, 0.0] },
+                TemplateVertex { position: Vec3::RIGHT, normal: Vec3::new(0.0, 0.0, 1.0), uv: [1.0, 0.0] },
+                TemplateVertex { position: Vec3::new(0.0, 1.0, 0.0), normal: Vec3::new(0.0, 0.0, 1.0), uv: [0.0, 1.0] },
+            ],
+            triangles: vec![0, 1, 2],
+            material_index: 2,
+        };
+        let organ = TurtleOrganInstance {
+            organ_template_index: 0,
+            organ_transform: Mat4::translation(Vec3::new(1.0, 2.0, 3.0)) * Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0) * Mat4::scale(Vec3::ONE * 2.0),
+            thickness: 1.0,
+            organ_identity: 0x04030201,
+            custom_data: [9, 8, 7, 6],
+            index_in_stem_tree: 0,
+            symbol_index: 1,
+        };
+        let skipped = TurtleOrganInstance { organ_template_index: 3, ..organ };
+        let output = turtle_output(vec![stem(0, -1, Mat4::IDENTITY)], vec![organ, skipped]);
+        let mesh = build_plant_mesh(&output, &[template], &STEM_CLASSES);
+
+        assert_eq!(mesh.vertices.len(), 5 + 3);
+        let organ_vertices = &mesh.vertices[5..];
+        assert_close(organ_vertices[0].position, Vec3::new(1.0, 2.0, 3.0));
+        assert_close(organ_vertices[1].position, Vec3::new(1.0, 4.0, 3.0));
+        assert_close(organ_vertices[2].position, Vec3::new(-1.0, 2.0, 3.0));
+        assert_close(organ_vertices[1].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(organ_vertices[2].uv, [0.0, 1.0]);
+        assert!(organ_vertices.iter().all(|vertex| vertex.color == [1, 2, 3, 4] && vertex.extra_data == [9, 8, 7, 6]));
+        assert_eq!(mesh.submeshes[2], vec![5, 6, 7]);
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Write;
+use crate::mesh::builder::PlantMesh;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const CHUNK_TYPE_JSON: &[u8; 4] = b"JSON";
+const CHUNK_TYPE_BIN: &[u8; 4] = b"BIN\0";
+
+const COMPONENT_UNSIGNED_BYTE: u32 = 5121;
+const COMPONENT_UNSIGNED_INT: u32 = 5125;
+const COMPONENT_FLOAT: u32 = 5126;
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+struct BufferView {
+    byte_offset: usize,
+    byte_length: usize,
+    target: u32,
+}
+
+/// Write the mesh as binary glTF 2.0, converted to right handed space.
+///     every submesh is a primitive of a single mesh, using a material named "material_{index}".
+///     organ identities are written as COLOR_0, and the extra vertex data as the custom attribute _EXTRA_DATA,
+///     both as normalized unsigned bytes
+pub fn plant_mesh_to_glb(mesh: &PlantMesh) -> Vec<u8> {
+    let mesh = mesh.to_right_handed();
+    let mut bin: Vec<u8> = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut push_view = |bin: &mut Vec<u8>, bytes: &mut dyn Iterator<Item = u8>, target: u32| {
+        let byte_offset = bin.len();
+        bin.extend(bytes);
+        buffer_views.push(BufferView {
+            byte_offset,
+            byte_length: bin.len() - byte_offset,
+            target,
+        });
+    };
+
+    let vertices = &mesh.vertices;
+    push_view(&mut bin, &mut vertices.iter()
+        .flat_map(|vertex| [vertex.position.x, vertex.position.y, vertex.position.z])
+        .flat_map(f32::to_le_bytes), TARGET_ARRAY_BUFFER);
+    push_view(&mut bin, &mut vertices.iter()
+        .flat_map(|vertex| [vertex.normal.x, vertex.normal.y, vertex.normal.z])
+        .flat_map(f32::to_le_bytes), TARGET_ARRAY_BUFFER);
+    // gltf uvs start from the top of the texture
+    push_view(&mut bin, &mut vertices.iter()
+        .flat_map(|vertex| [vertex.uv[0], 1.0 - vertex.uv[1]])
+        .flat_map(f32::to_le_bytes), TARGET_ARRAY_BUFFER);
+    push_view(&mut bin, &mut vertices.iter().flat_map(|vertex| vertex.color), TARGET_ARRAY_BUFFER);
+    push_view(&mut bin, &mut vertices.iter().flat_map(|vertex| vertex.extra_data), TARGET_ARRAY_BUFFER);
+    let primitive_materials: Vec<usize> = mesh.submeshes.iter()
+        .enumerate()
+        .filter(|(_, triangles)| !triangles.is_empty())
+        .map(|(material_index, _)| material_index)
+        .collect();
+    for material_index in primitive_materials.iter() {
+        push_view(&mut bin, &mut mesh.submeshes[*material_index].iter().flat_map(|index| index.to_le_bytes()), TARGET_ELEMENT_ARRAY_BUFFER);
+    }
+
+    let has_mesh = !vertices.is_empty() && !primitive_materials.is_empty();
+    let mut json = String::new();
+    json.push_str("{\"asset\":{\"version\":\"2.0\",\"generator\":\"system_runtime\"},\"scene\":0,");
+    if !has_mesh {
+        json.push_str("\"scenes\":[{\"nodes\":[]}]}");
+        return write_glb(json, &[]);
+    }
+    json.push_str("\"scenes\":[{\"nodes\":[0]}],\"nodes\":[{\"mesh\":0}],");
+
+    json.push_str("\"materials\":[");
+    for material_index in 0..mesh.submeshes.len() {
+        if material_index > 0 {
+            json.push(',');
+        }
+        write!(json, "{{\"name\":\"material_{}\"}}", material_index).unwrap();
+    }
+    json.push_str("],");
+
+    json.push_str("\"meshes\":[{\"primitives\":[");
+    for (primitive_index, material_index) in primitive_materials.iter().enumerate() {
+        if primitive_index > 0 {
+            json.push(',');
+        }
+        write!(json, "{{\"attributes\":{{\"POSITION\":0,\"NORMAL\":1,\"TEXCOORD_0\":2,\"COLOR_0\":3,\"_EXTRA_DATA\":4}},\"indices\":{},\"material\":{}}}",
+            5 + primitive_index, material_index).unwrap();
+    }
+    json.push_str("]}],");
+
+    let vertex_count = vertices.len();
+    let (min, max) = vertices.iter().fold(
+        ([f32::MAX; 3], [f32::MIN; 3]),
+        |(min, max), vertex| {
+            let position = [vertex.position.x, vertex.position.y, vertex.position.z];
+            (
+                [0, 1, 2].map(|axis| min[axis].min(position[axis])),
+                [0, 1, 2].map(|axis| max[axis].max(position[axis])),
+            )
+        });
+    json.push_str("\"accessors\":[");
+    write!(json, "{{\"bufferView\":0,\"componentType\":{},\"count\":{},\"type\":\"VEC3\",\"min\":[{},{},{}],\"max\":[{},{},{}]}},",
+        COMPONENT_FLOAT, vertex_count, min[0], min[1], min[2], max[0], max[1], max[2]).unwrap();
+    write!(json, "{{\"bufferView\":1,\"componentType\":{},\"count\":{},\"type\":\"VEC3\"}},", COMPONENT_FLOAT, vertex_count).unwrap();
+    write!(json, "{{\"bufferView\":2,\"componentType\":{},\"count\":{},\"type\":\"VEC2\"}},", COMPONENT_FLOAT, vertex_count).unwrap();
+    write!(json, "{{\"bufferView\":3,\"componentType\":{},\"normalized\":true,\"count\":{},\"type\":\"VEC4\"}},", COMPONENT_UNSIGNED_BYTE, vertex_count).unwrap();
+    write!(json, "{{\"bufferView\":4,\"componentType\":{},\"normalized\":true,\"count\":{},\"type\":\"VEC4\"}}", COMPONENT_UNSIGNED_BYTE, vertex_count).unwrap();
+    for (primitive_index, material_index) in primitive_materials.iter().enumerate() {
+        write!(json, ",{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}",
+            5 + primitive_index, COMPONENT_UNSIGNED_INT, mesh.submeshes[*material_index].len()).unwrap();
+    }
+    json.push_str("],");
+
+    json.push_str("\"bufferViews\":[");
+    for (view_index, view) in buffer_views.iter().enumerate() {
+        if view_index > 0 {
+            json.push(',');
+        }
+        write!(json, "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+            view.byte_offset, view.byte_length, view.target).unwrap();
+    }
+    write!(json, "],\"buffers\":[{{\"byteLength\":{}}}]}}", bin.len()).unwrap();
+
+    write_glb(json, &bin)
+}
+
+/// the glb container: a 12 byte header, then the json chunk padded with spaces and the binary chunk padded with zeros
+fn write_glb(mut json: String, bin: &[u8]) -> Vec<u8> {
+    while !json.len().is_multiple_of(4) {
+        json.push(' ');
+    }
+    let padded_bin_length = bin.len().div_ceil(4) * 4;
+    let mut total_length = 12 + 8 + json.len();
+    if !bin.is_empty() {
+        total_length += 8 + padded_bin_length;
+    }
+
+    let mut glb = Vec::with_capacity(total_length);
+    glb.extend_from_slice(GLB_MAGIC);
+    glb.extend_from_slice(&GLB_VERSION.to_le_bytes());
+    glb.extend_from_slice(&(total_length as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(CHUNK_TYPE_JSON);
+    glb.extend_from_slice(json.as_bytes());
+    if !bin.is_empty() {
+        glb.extend_from_slice(&(padded_bin_length as u32).to_le_bytes());
+        glb.extend_from_slice(CHUNK_TYPE_BIN);
+        glb.extend_from_slice(bin);
+        glb.resize(total_length, 0);
+    }
+    glb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec3;
+    use crate::mesh::builder::MeshVertex;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// every number written after the key in the json, in order
+    fn json_numbers(json: &str, key: &str) -> Vec<usize> {
+        json.match_indices(key)
+            .map(|(start, _)| {
+                let digits = &json[start + key.len()..];
+                let length = digits.find(|c: char| !c.is_ascii_digit()).unwrap();
+                digits[..length].parse().unwrap()
+            })
+            .collect()
+    }
+
+    fn triangle_mesh() -> PlantMesh {
+        let vertex = |x: f32| MeshVertex {
+            position: Vec3::new(x, 1.0, 0.0),
+            ..Default::default()
+        };
+        PlantMesh {
+            vertices: vec![vertex(0.0), vertex(1.0), vertex(2.0)],
+            submeshes: vec![vec![0, 1, 2], vec![], vec![0, 2, 1, 1, 2, 0]],
+        }
+    }
+
+    #[test]
+    fn glb_chunks_are_padded_and_sized() {
+        let glb = plant_mesh_to_glb(&triangle_mesh());
+
+        assert_eq!(&glb[0..4], GLB_MAGIC);
+        assert_eq!(read_u32(&glb, 4), GLB_VERSION);
+        assert_eq!(read_u32(&glb, 8) as usize, glb.len());
+
+        let json_length = read_u32(&glb, 12) as usize;
+        assert_eq!(json_length % 4, 0);
+        assert_eq!(&glb[16..20], CHUNK_TYPE_JSON);
+        let json = std::str::from_utf8(&glb[20..20 + json_length]).unwrap();
+        assert!(json.trim_end_matches(' ').ends_with('}'));
+
+        let bin_header = 20 + json_length;
+        let bin_length = read_u32(&glb, bin_header) as usize;
+        assert_eq!(bin_length % 4, 0);
+        assert_eq!(&glb[bin_header + 4..bin_header + 8], CHUNK_TYPE_BIN);
+        assert_eq!(bin_header + 8 + bin_length, glb.len());
+
+        let buffer_length = json_numbers(json, "\"buffers\":[{\"byteLength\":")[0];
+        assert_eq!(bin_length, buffer_length.div_ceil(4) * 4);
+    }
+
+    #[test]
+    fn glb_accessors_count_vertices_and_indexes() {
+        let glb = plant_mesh_to_glb(&triangle_mesh());
+        let json_length = read_u32(&glb, 12) as usize;
+        let json = std::str::from_utf8(&glb[20..20 + json_length]).unwrap();
+
+        // five vertex attributes, then one index list per non empty submesh
+        assert_eq!(json_numbers(json, "\"count\":"), vec![3, 3, 3, 3, 3, 3, 6]);
+        assert_eq!(json_numbers(json, "\"byteLength\":"), vec![36, 36, 24, 12, 12, 12, 24, 156]);
+        assert_eq!(json_numbers(json, "\"material\":"), vec![0, 2]);
+    }
+
+    #[test]
+    fn empty_glb_has_no_binary_chunk() {
+        let glb = plant_mesh_to_glb(&PlantMesh::default());
+
+        let json_length = read_u32(&glb, 12) as usize;
+        assert_eq!(json_length % 4, 0);
+        assert_eq!(read_u32(&glb, 8) as usize, 20 + json_length);
+        assert_eq!(glb.len(), 20 + json_length);
+    }
+}
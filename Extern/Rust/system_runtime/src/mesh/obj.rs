@@ -0,0 +1,35 @@
+use std::fmt::Write;
+use crate::mesh::builder::PlantMesh;
+
+/// Write the mesh as a Wavefront OBJ, converted to right handed space.
+///     organ identities are written as vertex colors after each position, and each submesh as a group using
+///     the material "material_{index}". OBJ has no place for the extra vertex data, export gltf to keep it
+pub fn plant_mesh_to_obj(mesh: &PlantMesh) -> String {
+    let mesh = mesh.to_right_handed();
+    let mut obj = String::new();
+    for vertex in mesh.vertices.iter() {
+        let [r, g, b, _] = vertex.color;
+        writeln!(obj, "v {} {} {} {} {} {}",
+            vertex.position.x, vertex.position.y, vertex.position.z,
+            r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0).unwrap();
+    }
+    for vertex in mesh.vertices.iter() {
+        writeln!(obj, "vt {} {}", vertex.uv[0], vertex.uv[1]).unwrap();
+    }
+    for vertex in mesh.vertices.iter() {
+        writeln!(obj, "vn {} {} {}", vertex.normal.x, vertex.normal.y, vertex.normal.z).unwrap();
+    }
+    for (material_index, triangles) in mesh.submeshes.iter().enumerate() {
+        if triangles.is_empty() {
+            continue;
+        }
+        writeln!(obj, "g submesh_{}", material_index).unwrap();
+        writeln!(obj, "usemtl material_{}", material_index).unwrap();
+        for triangle in triangles.chunks_exact(3) {
+            // obj indexes from 1, with the same index for the position, uv and normal
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}").unwrap();
+        }
+    }
+    obj
+}
@@ -0,0 +1,4 @@
+pub mod state;
+pub mod operations;
+pub mod interpreter;
+pub mod planar;
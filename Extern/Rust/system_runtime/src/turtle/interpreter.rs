@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use crate::diffusion::branch_tree::{BranchTree, BranchTreeError};
+use crate::diffusion::extract_graph::SymbolString;
+use crate::turtle::operations::{TurtleEnvironment, TurtleOperation, TurtleOrganTemplate, TurtleOutput};
+use crate::turtle::state::TurtleState;
+
+/// Symbols read by the turtle itself, rather than through the operation table
+#[derive(Copy, Clone, Debug)]
+pub struct TurtleSymbols {
+    pub branch_open_symbol: i32,
+    pub branch_close_symbol: i32,
+    /// sets the organ identity to the bits of the first parameter
+    pub organ_identity_symbol: Option<i32>,
+    /// sets up to 4 bytes of custom data, from parameters between 0 and 1
+    pub custom_data_symbol: Option<i32>,
+}
+
+/// Every turtle operation, keyed by the symbol which applies it
+#[derive(Clone, Debug, Default)]
+pub struct TurtleOperationTable {
+    pub operations: HashMap<i32, TurtleOperation>,
+    /// indexed by the template ranges of AddOrgan operations
+    pub organ_templates: Vec<TurtleOrganTemplate>,
+}
+
+impl TurtleOperationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// replaces any operation already applied by the symbol
+    pub fn add(&mut self, symbol: i32, operation: TurtleOperation) -> &mut Self {
+        self.operations.insert(symbol, operation);
+        self
+    }
+}
+
+/// walk the string applying every operation in the table, saving the turtle state when entering each branch.
+///     returns an error without interpreting anything if the branch symbols do not match
+pub fn interpret_turtle(
+    symbol_string: &SymbolString,
+    operations: &TurtleOperationTable,
+    symbols: &TurtleSymbols,
+    default_state: &TurtleState) -> Result<TurtleOutput, BranchTreeError> {
+    interpret_turtle_in_environment(symbol_string, operations, symbols, default_state, &TurtleEnvironment::default())
+}
+
+/// interpret the string as interpret_turtle, with an environment for operations which respond to the world such as phototropism
+pub fn interpret_turtle_in_environment(
+    symbol_string: &SymbolString,
+    operations: &TurtleOperationTable,
+    symbols: &TurtleSymbols,
+    default_state: &TurtleState,
+    environment: &TurtleEnvironment) -> Result<TurtleOutput, BranchTreeError> {
+
+    let mut output = TurtleOutput {
+        organs: Vec::new(),
+        stems: Vec::new(),
+        stem_symbol_indexes: Vec::new(),
+    };
+    let branch_tree = BranchTree::new(symbol_string, symbols.branch_open_symbol, symbols.branch_close_symbol, symbols.branch_open_symbol)?;
+    // indexed by branch, the state of the turtle as it entered the branch
+    let mut branch_states = vec![*default_state; branch_tree.branches().len()];
+    let mut current_state = *default_state;
+
+    for (symbol_index, symbol, params) in symbol_string.iter() {
+        if symbol == symbols.branch_open_symbol {
+            branch_states[branch_tree.branch_of(symbol_index)] = current_state;
+            continue;
+        }
+        if symbol == symbols.branch_close_symbol {
+            current_state = branch_states[branch_tree.branch_of(symbol_index)];
+            continue;
+        }
+        if Some(symbol) == symbols.organ_identity_symbol {
+            if let Some(identity) = params.first() {
+                current_state.organ_identity = identity.to_bits();
+            }
+            continue;
+        }
+        if Some(symbol) == symbols.custom_data_symbol {
+            for (custom_data, param) in current_state.custom_data.iter_mut().zip(params) {
+                *custom_data = (param * 255.0).clamp(0.0, 255.0) as u8;
+            }
+        }
+        if let Some(operation) = operations.operations.get(&symbol) {
+            operation.operate(&mut current_state, symbol_index, params, &operations.organ_templates, environment, &mut output);
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+    use crate::math::Vec3;
+    use crate::turtle::operations::StemOperation;
+    use crate::turtle::state::TurtleStemInstance;
+
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+    const STEM: i32 = 1;
+    const TURN: i32 = 2;
+    const THIN: i32 = 3;
+    const IDENTITY: i32 = 4;
+    const CUSTOM_DATA: i32 = 5;
+
+    fn interpret(text: &str) -> TurtleOutput {
+        let mut operations = TurtleOperationTable::new();
+        operations
+            .add(STEM, TurtleOperation::AddStem(StemOperation {
+                stem_class_index: 0,
+                will_move: true,
+                scale_by_parameter: false,
+                scale_by_thickness: true,
+                length_radius_base: [1.0, 1.0],
+                length_radius_scale: [1.0, 1.0],
+                scale_is_additional: false,
+                scale_power: 1.0,
+            }))
+            .add(TURN, TurtleOperation::Rotate { axis: Vec3::new(0.0, 0.0, 1.0), default_degrees: 90.0 })
+            .add(THIN, TurtleOperation::ScaleThickness { default_factor: 0.5 });
+        let symbols = TurtleSymbols {
+            branch_open_symbol: OPEN,
+            branch_close_symbol: CLOSE,
+            organ_identity_symbol: Some(IDENTITY),
+            custom_data_symbol: Some(CUSTOM_DATA),
+        };
+        let symbol_string = parse_symbol_string(text, None).unwrap();
+        interpret_turtle(&symbol_string.borrow(), &operations, &symbols, &TurtleState::default()).unwrap()
+    }
+
+    fn position(stem: &TurtleStemInstance) -> Vec3 {
+        stem.orientation.multiply_point(Vec3::ZERO)
+    }
+
+    #[test]
+    fn branches_restore_the_state_they_were_entered_with() {
+        let output = interpret("1 -10 2 3 4(2) 5(0.5,1) 1 1 -11 1");
+
+        assert_eq!(output.stems.len(), 4);
+        let (in_branch, after_branch) = (&output.stems[1], &output.stems[3]);
+        assert!((position(in_branch) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((in_branch.orientation.multiply_vector(Vec3::new(0.0, 1.0, 0.0)).length() - 0.5).abs() < 1e-5);
+        assert_eq!(in_branch.organ_identity, 2.0f32.to_bits());
+        assert_eq!(in_branch.custom_data, [127, 255, 0, 0]);
+
+        // position, heading, thickness, identity and custom data all return to how they were at the branch
+        assert!((position(after_branch) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((after_branch.orientation.multiply_vector(Vec3::RIGHT) - Vec3::RIGHT).length() < 1e-5);
+        assert!((after_branch.orientation.multiply_vector(Vec3::new(0.0, 1.0, 0.0)).length() - 1.0).abs() < 1e-5);
+        assert_eq!(after_branch.organ_identity, 0);
+        assert_eq!(after_branch.custom_data, [0; 4]);
+    }
+
+    #[test]
+    fn stems_after_a_branch_are_parented_to_the_stem_before_it() {
+        let output = interpret("1 -10 1 1 -11 -10 2 1 -10 1 -11 -11 1");
+
+        let parents: Vec<i32> = output.stems.iter().map(|stem| stem.parent_index).collect();
+        assert_eq!(parents, vec![-1, 0, 1, 0, 3, 0]);
+        assert_eq!(output.stem_symbol_indexes, vec![0, 2, 3, 7, 9, 12]);
+    }
+
+    #[test]
+    fn unmatched_branches_are_rejected() {
+        let symbol_string = parse_symbol_string("1 -10 1", None).unwrap();
+        let symbols = TurtleSymbols {
+            branch_open_symbol: OPEN,
+            branch_close_symbol: CLOSE,
+            organ_identity_symbol: None,
+            custom_data_symbol: None,
+        };
+        let output = interpret_turtle(&symbol_string.borrow(), &TurtleOperationTable::new(), &symbols, &TurtleState::default());
+        assert!(output.is_err());
+    }
+}
@@ -0,0 +1,571 @@
+use std::ops::Range;
+use crate::math::{Mat4, Vec3};
+use crate::turtle::state::{TurtleOrganInstance, TurtleStemInstance, TurtleState};
+use crate::voxel::layout::VoxelWorldVolumetricLayerData;
+
+/// A mesh the turtle can place, along with how far the turtle moves past it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TurtleOrganTemplate {
+    /// transforms the mesh into the turtle's local space
+    pub base_mesh_transform: Mat4,
+    pub translation: Vec3,
+    /// whether the turtle moves by the translation after placing the organ
+    pub also_move: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrganOperation {
+    /// the range of templates in the template list. the first parameter selects between them when there is more than one
+    pub organ_templates: Range<usize>,
+    /// scale the organ by the next parameter
+    pub do_scale: bool,
+    pub scale_is_additional: bool,
+    /// the scale parameter is raised to 1 / scale_power
+    pub scale_power: f32,
+    pub extra_non_uniform_scale: Vec3,
+    /// scale the organ along y and z by the turtle thickness
+    pub apply_thickness: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StemOperation {
+    pub stem_class_index: i32,
+    /// whether the turtle moves along the length of the stem after placing it
+    pub will_move: bool,
+    /// scale the length and radius by the first parameter
+    pub scale_by_parameter: bool,
+    /// scale the radius by the turtle thickness
+    pub scale_by_thickness: bool,
+    pub length_radius_base: [f32; 2],
+    pub length_radius_scale: [f32; 2],
+    pub scale_is_additional: bool,
+    /// the scale parameter is raised to 1 / scale_power
+    pub scale_power: f32,
+}
+
+/// The operations the turtle applies when it reads a symbol, matching the unity TurtleOperation.
+///     symbols with an unexpected number of parameters leave the turtle unchanged
+#[derive(Clone, Debug, PartialEq)]
+pub enum TurtleOperation {
+    /// params: (factor, x, y, z), (x, y, z), (factor), or none.
+    ///     bend the turtle's x axis towards the world space direction, by the factor scaled by how far the axis is from the direction
+    BendTowards {
+        default_direction: Vec3,
+        default_factor: f32,
+    },
+    /// params: (factor, x, y, z), (x, y, z), (factor), or none.
+    ///     roll the turtle about its x axis, turning its y axis towards the world space direction by the factor
+    OrientTowards {
+        default_direction: Vec3,
+        default_factor: f32,
+    },
+    AddOrgan(OrganOperation),
+    AddStem(StemOperation),
+    /// params: (degrees), or none
+    Rotate {
+        axis: Vec3,
+        default_degrees: f32,
+    },
+    /// params: (x, y, z), (factor), or none. a single factor scales the non uniform scale
+    ScaleTransform {
+        non_uniform_scale: Vec3,
+        default_factor: f32,
+    },
+    /// params: (factor), or none
+    ScaleThickness {
+        default_factor: f32,
+    },
+    /// params: (elasticity), or none.
+    ///     bend towards gravity as BendTowards, by the elasticity divided by the turtle thickness so thicker stems droop less
+    Gravitropism {
+        gravity: Vec3,
+        default_elasticity: f32,
+    },
+    /// params: (factor), or none.
+    ///     bend towards increasing light, along the gradient of the light layer at the turtle's position.
+    ///     the turtle is placed in the voxel world by the environment's local_to_world, and nothing happens outside of the voxel volume
+    ///     or when the environment has no voxel layers
+    Phototropism {
+        light_layer: usize,
+        default_factor: f32,
+    },
+}
+
+/// The world around the turtle, read by operations which respond to their surroundings
+#[derive(Copy, Clone, Default)]
+pub struct TurtleEnvironment<'a> {
+    pub voxel_layers: Option<&'a VoxelWorldVolumetricLayerData<'a>>,
+    /// from the space the turtle's transform is in, to the world space of the voxel layers
+    pub local_to_world: Mat4,
+}
+
+/// where the turtle writes the instances it places
+pub struct TurtleOutput {
+    pub organs: Vec<TurtleOrganInstance>,
+    pub stems: Vec<TurtleStemInstance>,
+    /// parallel to stems, the index of the symbol which placed each stem
+    pub stem_symbol_indexes: Vec<i32>,
+}
+
+/// bend the turtle's x axis towards the world space direction, by the factor scaled by how far the axis is from the direction
+fn bend_towards(state: &mut TurtleState, bend_direction: Vec3, bend_factor: f32) {
+    let Some(inverse) = state.transformation.inverse_affine() else {
+        return;
+    };
+    let local_bend_direction = inverse.multiply_vector(bend_direction);
+    let bend_axis = Vec3::RIGHT.cross(local_bend_direction);
+    // slerp from identity towards the rotation from x onto the direction
+    let slerp_amount = (bend_factor * local_bend_direction.cross(Vec3::RIGHT).length()).clamp(0.0, 1.0);
+    if bend_axis.length() <= f32::EPSILON {
+        return;
+    }
+    let angle = Vec3::RIGHT.dot(local_bend_direction.normalized()).clamp(-1.0, 1.0).acos().to_degrees();
+    state.transformation = state.transformation * Mat4::rotation(bend_axis.normalized(), angle * slerp_amount);
+}
+
+/// the optional leading factor and direction of the bend and orient parameters
+fn factor_and_direction(params: &[f32], default_factor: f32, default_direction: Vec3) -> (f32, Vec3) {
+    match *params {
+        [factor, x, y, z] => (factor, Vec3::new(x, y, z)),
+        [x, y, z] => (default_factor, Vec3::new(x, y, z)),
+        [factor] => (factor, default_direction),
+        _ => (default_factor, default_direction),
+    }
+}
+
+fn scale_parameter(scale: f32, scale_power: f32) -> f32 {
+    if scale_power != 1.0 {
+        scale.powf(1.0 / scale_power)
+    } else {
+        scale
+    }
+}
+
+impl TurtleOperation {
+    pub fn operate(
+        &self,
+        state: &mut TurtleState,
+        symbol_index: usize,
+        params: &[f32],
+        organ_templates: &[TurtleOrganTemplate],
+        environment: &TurtleEnvironment,
+        output: &mut TurtleOutput) {
+        match self {
+            TurtleOperation::BendTowards { default_direction, default_factor } => {
+                let (bend_factor, bend_direction) = factor_and_direction(params, *default_factor, *default_direction);
+                bend_towards(state, bend_direction, bend_factor);
+            }
+            TurtleOperation::OrientTowards { default_direction, default_factor } => {
+                let (orient_factor, orient_direction) = factor_and_direction(params, *default_factor, *default_direction);
+                let Some(inverse) = state.transformation.inverse_affine() else {
+                    return;
+                };
+                let local_orient_direction = inverse.multiply_vector(orient_direction);
+                if local_orient_direction.y.abs() <= f32::EPSILON && local_orient_direction.z.abs() <= f32::EPSILON {
+                    return;
+                }
+                // the roll which points y as close to the direction as possible
+                let angle = local_orient_direction.z.atan2(local_orient_direction.y).to_degrees();
+                state.transformation = state.transformation * Mat4::rotation(Vec3::RIGHT, angle * orient_factor.clamp(0.0, 1.0));
+            }
+            TurtleOperation::AddOrgan(organ_operation) => {
+                organ_operation.operate(state, symbol_index, params, organ_templates, output);
+            }
+            TurtleOperation::AddStem(stem_operation) => {
+                stem_operation.operate(state, symbol_index, params, output);
+            }
+            TurtleOperation::Rotate { axis, default_degrees } => {
+                let degrees = params.first().copied().unwrap_or(*default_degrees);
+                state.transformation = state.transformation * Mat4::rotation(*axis, degrees);
+            }
+            TurtleOperation::ScaleTransform { non_uniform_scale, default_factor } => {
+                let scale = match *params {
+                    [] => *non_uniform_scale * *default_factor,
+                    [factor] => *non_uniform_scale * factor,
+                    [x, y, z] => Vec3::new(x, y, z),
+                    _ => return,
+                };
+                state.transformation = state.transformation * Mat4::scale(scale);
+            }
+            TurtleOperation::ScaleThickness { default_factor } => {
+                match *params {
+                    [] => state.thickness *= default_factor,
+                    [factor] => state.thickness *= factor,
+                    _ => {}
+                }
+            }
+            TurtleOperation::Gravitropism { gravity, default_elasticity } => {
+                let elasticity = match *params {
+                    [] => *default_elasticity,
+                    [elasticity] => elasticity,
+                    _ => return,
+                };
+                bend_towards(state, *gravity, elasticity / state.thickness.max(f32::EPSILON));
+            }
+            TurtleOperation::Phototropism { light_layer, default_factor } => {
+                let factor = match *params {
+                    [] => *default_factor,
+                    [factor] => factor,
+                    _ => return,
+                };
+                let Some(voxel_layers) = environment.voxel_layers else {
+                    return;
+                };
+                if !voxel_layers.is_valid_layer(*light_layer as i32) {
+                    return;
+                }
+                let Some(world_to_local) = environment.local_to_world.inverse_affine() else {
+                    return;
+                };
+                let turtle_position = environment.local_to_world.multiply_point(state.transformation.multiply_point(Vec3::ZERO));
+                let Some(light_gradient) = voxel_layers.gradient(*light_layer, turtle_position) else {
+                    return;
+                };
+                bend_towards(state, world_to_local.multiply_vector(light_gradient).normalized(), factor);
+            }
+        }
+    }
+}
+
+impl OrganOperation {
+    fn operate(
+        &self,
+        state: &mut TurtleState,
+        symbol_index: usize,
+        params: &[f32],
+        organ_templates: &[TurtleOrganTemplate],
+        output: &mut TurtleOutput) {
+        let template_count = self.organ_templates.len();
+        let mut selected_template = 0;
+        if template_count > 1 && !params.is_empty() {
+            selected_template = (params[0] as i32).clamp(0, template_count as i32 - 1) as usize;
+        }
+        let organ_template_index = self.organ_templates.start + selected_template;
+        let Some(organ_template) = organ_templates.get(organ_template_index) else {
+            return;
+        };
+
+        let mut mesh_transform = state.transformation;
+        let mut turtle_translate = organ_template.translation;
+        let scale_index = if template_count <= 1 { 0 } else { 1 };
+        if self.do_scale && params.len() > scale_index {
+            let scale = scale_parameter(params[scale_index], self.scale_power);
+            let base_scale = if self.scale_is_additional { Vec3::ONE } else { Vec3::ZERO };
+            let scale_vector = base_scale + self.extra_non_uniform_scale * scale;
+            if organ_template.also_move {
+                turtle_translate = turtle_translate.scale(scale_vector);
+            }
+            mesh_transform = mesh_transform * Mat4::scale(scale_vector);
+        }
+        if self.apply_thickness {
+            mesh_transform = mesh_transform * Mat4::scale(Vec3::new(1.0, state.thickness, state.thickness));
+        }
+
+        output.organs.push(TurtleOrganInstance {
+            organ_template_index: organ_template_index as i32,
+            organ_transform: mesh_transform * organ_template.base_mesh_transform,
+            thickness: state.thickness,
+            organ_identity: state.organ_identity,
+            custom_data: state.custom_data,
+            index_in_stem_tree: state.index_in_stem_tree,
+            symbol_index: symbol_index as i32,
+        });
+
+        if organ_template.also_move {
+            state.transformation = state.transformation * Mat4::translation(turtle_translate);
+        }
+    }
+}
+
+impl StemOperation {
+    fn operate(
+        &self,
+        state: &mut TurtleState,
+        symbol_index: usize,
+        params: &[f32],
+        output: &mut TurtleOutput) {
+        let mut length_radius = self.length_radius_base;
+        if self.scale_by_parameter && !params.is_empty() {
+            let scale = scale_parameter(params[0], self.scale_power);
+            let base_scale = if self.scale_is_additional { 1.0 } else { 0.0 };
+            length_radius[0] *= base_scale + scale * self.length_radius_scale[0];
+            length_radius[1] *= base_scale + scale * self.length_radius_scale[1];
+        }
+        if self.scale_by_thickness {
+            length_radius[1] *= state.thickness;
+        }
+        let [length, radius] = length_radius;
+
+        output.stems.push(TurtleStemInstance {
+            stem_class_index: self.stem_class_index,
+            orientation: state.transformation * Mat4::scale(Vec3::new(length, radius, radius)),
+            parent_index: state.index_in_stem_tree,
+            organ_identity: state.organ_identity,
+            custom_data: state.custom_data,
+        });
+        output.stem_symbol_indexes.push(symbol_index as i32);
+        state.index_in_stem_tree = output.stems.len() as i32 - 1;
+
+        if self.will_move {
+            state.transformation = state.transformation * Mat4::translation(Vec3::new(length, 0.0, 0.0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec3Int;
+    use crate::voxel::layout::{VolumetricWorldVoxelLayout, VoxelVolume, VoxelWorldVolumetricLayerDataOwned};
+
+    fn assert_vec_close(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).length() < 1e-5, "{:?} is not close to {:?}", actual, expected);
+    }
+
+    fn assert_mat_close(actual: &Mat4, expected: &Mat4) {
+        for (actual_column, expected_column) in actual.columns.iter().zip(expected.columns.iter()) {
+            for (actual_value, expected_value) in actual_column.iter().zip(expected_column.iter()) {
+                assert!((actual_value - expected_value).abs() < 1e-5, "{:?} is not close to {:?}", actual, expected);
+            }
+        }
+    }
+
+    fn empty_output() -> TurtleOutput {
+        TurtleOutput {
+            organs: Vec::new(),
+            stems: Vec::new(),
+            stem_symbol_indexes: Vec::new(),
+        }
+    }
+
+    /// apply the operation to the state with the parameters, without organ templates or an environment
+    fn operate(operation: &TurtleOperation, state: &mut TurtleState, params: &[f32]) -> TurtleOutput {
+        let mut output = empty_output();
+        operation.operate(state, 0, params, &[], &TurtleEnvironment::default(), &mut output);
+        output
+    }
+
+    fn heading(state: &TurtleState) -> Vec3 {
+        state.transformation.multiply_vector(Vec3::RIGHT)
+    }
+
+    const UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    const FORWARD: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+
+    #[test]
+    fn rotate_by_default_and_parameter_degrees() {
+        let rotate = TurtleOperation::Rotate { axis: FORWARD, default_degrees: 90.0 };
+
+        let mut state = TurtleState::default();
+        operate(&rotate, &mut state, &[]);
+        assert_mat_close(&state.transformation, &Mat4 {
+            columns: [
+                [0.0, 1.0, 0.0, 0.0],
+                [-1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        });
+
+        operate(&rotate, &mut state, &[-45.0]);
+        assert_vec_close(heading(&state), Vec3::new(0.5f32.sqrt(), 0.5f32.sqrt(), 0.0));
+    }
+
+    #[test]
+    fn bend_towards_turns_heading_by_factor() {
+        let bend = TurtleOperation::BendTowards { default_direction: UP, default_factor: 1.0 };
+
+        let mut state = TurtleState::default();
+        operate(&bend, &mut state, &[]);
+        assert_mat_close(&state.transformation, &Mat4::rotation(FORWARD, 90.0));
+
+        let mut state = TurtleState::default();
+        operate(&bend, &mut state, &[0.5]);
+        assert_mat_close(&state.transformation, &Mat4::rotation(FORWARD, 45.0));
+
+        // a direction given by parameter bends about the axis between it and the heading
+        let mut state = TurtleState::default();
+        operate(&bend, &mut state, &[1.0, 0.0, 0.0, -1.0]);
+        assert_mat_close(&state.transformation, &Mat4::rotation(UP, 90.0));
+        assert_vec_close(heading(&state), Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn bend_towards_the_heading_does_nothing() {
+        let bend = TurtleOperation::BendTowards { default_direction: Vec3::RIGHT, default_factor: 1.0 };
+
+        let mut state = TurtleState::default();
+        operate(&bend, &mut state, &[]);
+        assert_eq!(state.transformation, Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn orient_towards_rolls_about_the_heading() {
+        let orient = TurtleOperation::OrientTowards { default_direction: FORWARD, default_factor: 1.0 };
+
+        let mut state = TurtleState::default();
+        operate(&orient, &mut state, &[]);
+        assert_mat_close(&state.transformation, &Mat4::rotation(Vec3::RIGHT, 90.0));
+        assert_vec_close(heading(&state), Vec3::RIGHT);
+
+        let mut state = TurtleState::default();
+        operate(&orient, &mut state, &[0.5]);
+        assert_mat_close(&state.transformation, &Mat4::rotation(Vec3::RIGHT, 45.0));
+    }
+
+    #[test]
+    fn stems_are_parented_to_the_last_stem() {
+        let add_stem = TurtleOperation::AddStem(StemOperation {
+            stem_class_index: 2,
+            will_move: true,
+            scale_by_parameter: true,
+            scale_by_thickness: true,
+            length_radius_base: [2.0, 0.5],
+            length_radius_scale: [1.0, 0.5],
+            scale_is_additional: false,
+            scale_power: 1.0,
+        });
+        let mut state = TurtleState {
+            thickness: 0.5,
+            ..Default::default()
+        };
+        let mut output = empty_output();
+        add_stem.operate(&mut state, 3, &[], &[], &TurtleEnvironment::default(), &mut output);
+        add_stem.operate(&mut state, 5, &[2.0], &[], &TurtleEnvironment::default(), &mut output);
+
+        assert_eq!(output.stems.iter().map(|stem| stem.parent_index).collect::<Vec<_>>(), vec![-1, 0]);
+        assert_eq!(output.stem_symbol_indexes, vec![3, 5]);
+        assert_eq!(state.index_in_stem_tree, 1);
+        assert_eq!(output.stems[0].stem_class_index, 2);
+        assert_mat_close(&output.stems[0].orientation, &Mat4::scale(Vec3::new(2.0, 0.25, 0.25)));
+        // the second stem is scaled by its parameter, and placed at the end of the first
+        assert_mat_close(&output.stems[1].orientation, &(Mat4::translation(Vec3::new(2.0, 0.0, 0.0)) * Mat4::scale(Vec3::new(4.0, 0.25, 0.25))));
+        assert_vec_close(state.transformation.multiply_point(Vec3::ZERO), Vec3::new(6.0, 0.0, 0.0));
+    }
+
+    fn organ_templates() -> Vec<TurtleOrganTemplate> {
+        (0..4)
+            .map(|template_index| TurtleOrganTemplate {
+                base_mesh_transform: Mat4::translation(Vec3::new(0.0, template_index as f32, 0.0)),
+                translation: Vec3::RIGHT,
+                also_move: template_index == 3,
+            })
+            .collect()
+    }
+
+    fn add_organ(organ_templates: Range<usize>, scale_is_additional: bool, scale_power: f32) -> TurtleOperation {
+        TurtleOperation::AddOrgan(OrganOperation {
+            organ_templates,
+            do_scale: true,
+            scale_is_additional,
+            scale_power,
+            extra_non_uniform_scale: Vec3::ONE,
+            apply_thickness: false,
+        })
+    }
+
+    fn place_organ(operation: &TurtleOperation, state: &mut TurtleState, params: &[f32]) -> TurtleOrganInstance {
+        let mut output = empty_output();
+        operation.operate(state, 7, params, &organ_templates(), &TurtleEnvironment::default(), &mut output);
+        assert_eq!(output.organs.len(), 1);
+        output.organs[0]
+    }
+
+    #[test]
+    fn organ_template_is_selected_by_the_first_parameter() {
+        let organ = add_organ(1..4, false, 1.0);
+
+        let mut state = TurtleState::default();
+        assert_eq!(place_organ(&organ, &mut state, &[1.0]).organ_template_index, 2);
+        assert_eq!(place_organ(&organ, &mut state, &[-3.0]).organ_template_index, 1);
+        // out of range selections are clamped to the last template, which moves the turtle
+        let placed = place_organ(&organ, &mut state, &[9.0, 2.0]);
+        assert_eq!(placed.organ_template_index, 3);
+        assert_eq!(placed.symbol_index, 7);
+        assert_mat_close(&placed.organ_transform, &(Mat4::scale(Vec3::ONE * 2.0) * Mat4::translation(Vec3::new(0.0, 3.0, 0.0))));
+        assert_vec_close(state.transformation.multiply_point(Vec3::ZERO), Vec3::new(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn organ_scale_is_raised_to_the_inverse_scale_power() {
+        // a single template reads the scale from the first parameter
+        let organ = add_organ(0..1, false, 2.0);
+        let mut state = TurtleState::default();
+        let placed = place_organ(&organ, &mut state, &[9.0]);
+        assert_mat_close(&placed.organ_transform, &Mat4::scale(Vec3::ONE * 3.0));
+
+        let additional = add_organ(0..1, true, 3.0);
+        let placed = place_organ(&additional, &mut state, &[8.0]);
+        assert_mat_close(&placed.organ_transform, &Mat4::scale(Vec3::ONE * 3.0));
+
+        // without a scale parameter the template is placed unscaled
+        let placed = place_organ(&organ, &mut state, &[]);
+        assert_mat_close(&placed.organ_transform, &Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn thin_stems_droop_more_than_thick_stems() {
+        let gravitropism = TurtleOperation::Gravitropism { gravity: -UP, default_elasticity: 0.5 };
+        let droop = |thickness: f32, params: &[f32]| {
+            let mut state = TurtleState {
+                thickness,
+                ..Default::default()
+            };
+            operate(&gravitropism, &mut state, params);
+            heading(&state)
+        };
+
+        let thin = droop(1.0, &[]);
+        let thick = droop(4.0, &[]);
+        assert_vec_close(thin, Vec3::new(0.5f32.sqrt(), -(0.5f32.sqrt()), 0.0));
+        assert!(thick.y < 0.0);
+        assert!(thin.y < thick.y);
+        // the elasticity parameter overrides the default
+        assert_vec_close(droop(1.0, &[0.0]), Vec3::RIGHT);
+    }
+
+    /// a 4 voxel cube of 1 unit voxels at the world origin, with one layer of light increasing towards -x
+    fn light_towards_negative_x() -> VoxelWorldVolumetricLayerDataOwned {
+        let layout = VolumetricWorldVoxelLayout {
+            volume: VoxelVolume {
+                voxel_origin: Vec3::ZERO,
+                world_size: Vec3::ONE * 4.0,
+                world_resolution: Vec3Int::new(4, 4, 4),
+            },
+            data_layer_count: 1,
+        };
+        let mut light = VoxelWorldVolumetricLayerDataOwned::new(layout);
+        let mut light_data = light.borrow_mut();
+        for voxel_index in 0..layout.volume.total_voxels() {
+            let coordinates = layout.volume.coordinates_from_voxel_index(voxel_index);
+            light_data.set(voxel_index, 0, 4.0 - coordinates.x as f32);
+        }
+        light
+    }
+
+    #[test]
+    fn phototropism_bends_towards_light_in_world_space() {
+        let mut light = light_towards_negative_x();
+        let light_data = light.borrow_mut();
+        let phototropism = TurtleOperation::Phototropism { light_layer: 0, default_factor: 0.5 };
+        let phototropism_in = |local_to_world: Mat4| {
+            let environment = TurtleEnvironment {
+                voxel_layers: Some(&light_data),
+                local_to_world,
+            };
+            let mut state = TurtleState::default();
+            phototropism.operate(&mut state, 0, &[], &[], &environment, &mut empty_output());
+            heading(&state)
+        };
+
+        // the plant is moved into the middle of the volume and turned so world -x is its local y
+        let placed = Mat4::translation(Vec3::new(2.5, 2.5, 2.5)) * Mat4::rotation(FORWARD, 90.0);
+        assert_vec_close(phototropism_in(placed), Vec3::new(0.5f32.sqrt(), 0.5f32.sqrt(), 0.0));
+
+        // without local_to_world the turtle is at the corner of the volume, where the light points straight back along its heading
+        assert_vec_close(phototropism_in(Mat4::translation(Vec3::new(0.5, 0.5, 0.5))), Vec3::RIGHT);
+        // and outside of the volume nothing happens
+        assert_vec_close(phototropism_in(Mat4::translation(Vec3::new(-2.0, 0.5, 0.5))), Vec3::RIGHT);
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use crate::diffusion::branch_tree::{BranchTree, BranchTreeError};
+use crate::diffusion::extract_graph::SymbolString;
+
+/// The state of a turtle confined to the plane, as in classic 2D L-systems
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlanarTurtleState {
+    pub position: [f32; 2],
+    /// degrees counterclockwise from the x axis
+    pub heading: f32,
+    pub thickness: f32,
+}
+
+impl Default for PlanarTurtleState {
+    /// at the origin, facing up
+    fn default() -> Self {
+        PlanarTurtleState {
+            position: [0.0, 0.0],
+            heading: 90.0,
+            thickness: 1.0,
+        }
+    }
+}
+
+/// The operations of a classic bracketed L-system turtle, such as F, f, + and -.
+///     symbols with an unexpected number of parameters leave the turtle unchanged
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlanarOperation {
+    /// params: (length), or none. move forward, drawing a line
+    Draw {
+        default_length: f32,
+    },
+    /// params: (length), or none. move forward without drawing
+    Move {
+        default_length: f32,
+    },
+    /// params: (degrees), or none. positive degrees turn counterclockwise
+    Turn {
+        default_degrees: f32,
+    },
+    /// params: (factor), or none
+    ScaleThickness {
+        default_factor: f32,
+    },
+}
+
+/// A line drawn by the planar turtle
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlanarLine {
+    pub start: [f32; 2],
+    pub end: [f32; 2],
+    pub thickness: f32,
+    /// index of the symbol which drew the line
+    pub symbol_index: i32,
+}
+
+/// Every planar operation, keyed by the symbol which applies it
+#[derive(Clone, Debug)]
+pub struct PlanarTurtleTable {
+    pub operations: HashMap<i32, PlanarOperation>,
+    pub branch_open_symbol: i32,
+    pub branch_close_symbol: i32,
+}
+
+impl PlanarTurtleTable {
+    pub fn new(branch_open_symbol: i32, branch_close_symbol: i32) -> Self {
+        PlanarTurtleTable {
+            operations: HashMap::new(),
+            branch_open_symbol,
+            branch_close_symbol,
+        }
+    }
+
+    /// replaces any operation already applied by the symbol
+    pub fn add(&mut self, symbol: i32, operation: PlanarOperation) -> &mut Self {
+        self.operations.insert(symbol, operation);
+        self
+    }
+}
+
+fn single_parameter(params: &[f32], default_value: f32) -> Option<f32> {
+    match *params {
+        [] => Some(default_value),
+        [value] => Some(value),
+        _ => None,
+    }
+}
+
+/// walk the string applying every operation in the table, saving the turtle state when entering each branch.
+///     returns an error without interpreting anything if the branch symbols do not match
+pub fn interpret_planar_turtle(
+    symbol_string: &SymbolString,
+    table: &PlanarTurtleTable,
+    default_state: &PlanarTurtleState) -> Result<Vec<PlanarLine>, BranchTreeError> {
+
+    let mut lines = Vec::new();
+    let branch_tree = BranchTree::new(symbol_string, table.branch_open_symbol, table.branch_close_symbol, table.branch_open_symbol)?;
+    // indexed by branch, the state of the turtle as it entered the branch
+    let mut branch_states = vec![*default_state; branch_tree.branches().len()];
+    let mut current_state = *default_state;
+
+    for (symbol_index, symbol, params) in symbol_string.iter() {
+        if symbol == table.branch_open_symbol {
+            branch_states[branch_tree.branch_of(symbol_index)] = current_state;
+            continue;
+        }
+        if symbol == table.branch_close_symbol {
+            current_state = branch_states[branch_tree.branch_of(symbol_index)];
+            continue;
+        }
+        let Some(operation) = table.operations.get(&symbol) else {
+            continue;
+        };
+        match *operation {
+            PlanarOperation::Draw { default_length } | PlanarOperation::Move { default_length } => {
+                let Some(length) = single_parameter(params, default_length) else {
+                    continue;
+                };
+                let start = current_state.position;
+                let (sin, cos) = current_state.heading.to_radians().sin_cos();
+                current_state.position = [start[0] + cos * length, start[1] + sin * length];
+                if let PlanarOperation::Draw { .. } = operation {
+                    lines.push(PlanarLine {
+                        start,
+                        end: current_state.position,
+                        thickness: current_state.thickness,
+                        symbol_index: symbol_index as i32,
+                    });
+                }
+            }
+            PlanarOperation::Turn { default_degrees } => {
+                if let Some(degrees) = single_parameter(params, default_degrees) {
+                    current_state.heading += degrees;
+                }
+            }
+            PlanarOperation::ScaleThickness { default_factor } => {
+                if let Some(factor) = single_parameter(params, default_factor) {
+                    current_state.thickness *= factor;
+                }
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// round away floating point noise, so the same drawing always writes the same text
+fn svg_number(value: f32) -> f32 {
+    let rounded = (value * 10000.0).round() / 10000.0;
+    // avoid writing -0
+    rounded + 0.0
+}
+
+/// Write the lines as an SVG document, with y pointing up and the view fitted around every line.
+///     each line's stroke width is its thickness multiplied by the stroke width scale.
+///     lines are written in the order they were drawn, with rounded caps so joined lines look continuous
+pub fn planar_lines_to_svg(lines: &[PlanarLine], stroke_width_scale: f32) -> String {
+    let mut min = [f32::INFINITY; 2];
+    let mut max = [f32::NEG_INFINITY; 2];
+    let mut max_stroke_width: f32 = 0.0;
+    for line in lines.iter() {
+        for point in [line.start, line.end] {
+            min = [min[0].min(point[0]), min[1].min(point[1])];
+            max = [max[0].max(point[0]), max[1].max(point[1])];
+        }
+        max_stroke_width = max_stroke_width.max(line.thickness * stroke_width_scale);
+    }
+    if lines.is_empty() {
+        min = [0.0, 0.0];
+        max = [0.0, 0.0];
+    }
+    // leave room for the stroke around the outermost lines, and never write an empty view
+    let margin = if lines.is_empty() { 0.5 } else { (max_stroke_width * 0.5).max(f32::EPSILON) };
+    let width = max[0] - min[0] + margin * 2.0;
+    let height = max[1] - min[1] + margin * 2.0;
+
+    let mut svg = String::new();
+    // svg y points down, so every y is negated
+    writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
+        svg_number(min[0] - margin), svg_number(-max[1] - margin), svg_number(width), svg_number(height)).unwrap();
+    svg.push_str("  <g stroke=\"black\" stroke-linecap=\"round\" fill=\"none\">\n");
+    for line in lines.iter() {
+        writeln!(svg, "    <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke-width=\"{}\"/>",
+            svg_number(line.start[0]), svg_number(-line.start[1]), svg_number(line.end[0]), svg_number(-line.end[1]),
+            svg_number(line.thickness * stroke_width_scale)).unwrap();
+    }
+    svg.push_str("  </g>\n");
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::symbol_string_text::parse_symbol_string;
+
+    const OPEN: i32 = -10;
+    const CLOSE: i32 = -11;
+    const DRAW: i32 = 1;
+    const LEFT: i32 = 2;
+    const RIGHT: i32 = 3;
+    const THIN: i32 = 4;
+    const SKIP: i32 = 5;
+
+    fn interpret(text: &str) -> Vec<PlanarLine> {
+        let mut table = PlanarTurtleTable::new(OPEN, CLOSE);
+        table
+            .add(DRAW, PlanarOperation::Draw { default_length: 1.0 })
+            .add(SKIP, PlanarOperation::Move { default_length: 1.0 })
+            .add(LEFT, PlanarOperation::Turn { default_degrees: 90.0 })
+            .add(RIGHT, PlanarOperation::Turn { default_degrees: -90.0 })
+            .add(THIN, PlanarOperation::ScaleThickness { default_factor: 0.5 });
+        let symbol_string = parse_symbol_string(text, None).unwrap();
+        interpret_planar_turtle(&symbol_string.borrow(), &table, &PlanarTurtleState::default()).unwrap()
+    }
+
+    fn assert_line(line: &PlanarLine, start: [f32; 2], end: [f32; 2], thickness: f32, symbol_index: i32) {
+        let close = |actual: [f32; 2], expected: [f32; 2]| (actual[0] - expected[0]).abs() < 1e-5 && (actual[1] - expected[1]).abs() < 1e-5;
+        assert!(close(line.start, start) && close(line.end, end), "{:?} does not run from {:?} to {:?}", line, start, end);
+        assert_eq!(line.thickness, thickness);
+        assert_eq!(line.symbol_index, symbol_index);
+    }
+
+    #[test]
+    fn branches_draw_from_where_they_start() {
+        // F[+!F][-F]F
+        let lines = interpret("1 -10 2 4 1 -11 -10 3 1 -11 1");
+
+        assert_eq!(lines.len(), 4);
+        assert_line(&lines[0], [0.0, 0.0], [0.0, 1.0], 1.0, 0);
+        assert_line(&lines[1], [0.0, 1.0], [-1.0, 1.0], 0.5, 4);
+        // heading and thickness are restored after each branch
+        assert_line(&lines[2], [0.0, 1.0], [1.0, 1.0], 1.0, 8);
+        assert_line(&lines[3], [0.0, 1.0], [0.0, 2.0], 1.0, 10);
+    }
+
+    #[test]
+    fn parameters_override_defaults() {
+        // F(2) +(-90) f(2) !(0.25) F(1,2) F
+        let lines = interpret("1(2) 2(-90) 5(2) 4(0.25) 1(1,2) 1");
+
+        assert_eq!(lines.len(), 2);
+        assert_line(&lines[0], [0.0, 0.0], [0.0, 2.0], 1.0, 0);
+        // a draw with too many parameters leaves the turtle where it is
+        assert_line(&lines[1], [2.0, 2.0], [3.0, 2.0], 0.25, 5);
+    }
+
+    #[test]
+    fn unmatched_branches_are_rejected() {
+        let symbol_string = parse_symbol_string("1 -11", None).unwrap();
+        let table = PlanarTurtleTable::new(OPEN, CLOSE);
+        assert!(interpret_planar_turtle(&symbol_string.borrow(), &table, &PlanarTurtleState::default()).is_err());
+    }
+
+    #[test]
+    fn svg_flips_y_and_fits_the_view_around_the_strokes() {
+        let lines = [
+            PlanarLine { start: [0.0, 0.0], end: [0.0, 2.0], thickness: 1.0, symbol_index: 0 },
+            PlanarLine { start: [0.0, 2.0], end: [-1.0, 3.0], thickness: 2.0, symbol_index: 1 },
+        ];
+        let svg = planar_lines_to_svg(&lines, 0.25);
+
+        assert_eq!(svg, concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-1.25 -3.25 1.5 3.5\">\n",
+            "  <g stroke=\"black\" stroke-linecap=\"round\" fill=\"none\">\n",
+            "    <line x1=\"0\" y1=\"0\" x2=\"0\" y2=\"-2\" stroke-width=\"0.25\"/>\n",
+            "    <line x1=\"0\" y1=\"-2\" x2=\"-1\" y2=\"-3\" stroke-width=\"0.5\"/>\n",
+            "  </g>\n",
+            "</svg>\n",
+        ));
+    }
+
+    #[test]
+    fn empty_svg_has_a_view() {
+        let svg = planar_lines_to_svg(&[], 1.0);
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-0.5 -0.5 1 1\">\n"));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::math::Mat4;
+
+/// Matches the memory layout of the unity TurtleState
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TurtleState {
+    pub transformation: Mat4,
+    pub thickness: f32,
+    /// the bits of the identity parameter. 0 means the organ has no identity
+    pub organ_identity: u32,
+    pub custom_data: [u8; 4],
+    /// the stem most recently added along the current branch, or -1
+    pub index_in_stem_tree: i32,
+}
+
+impl Default for TurtleState {
+    fn default() -> Self {
+        TurtleState {
+            transformation: Mat4::IDENTITY,
+            thickness: 1.0,
+            organ_identity: 0,
+            custom_data: [0; 4],
+            index_in_stem_tree: -1,
+        }
+    }
+}
+
+/// A single organ mesh placed by the turtle
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TurtleOrganInstance {
+    /// index of the organ template in the template list
+    pub organ_template_index: i32,
+    pub organ_transform: Mat4,
+    pub thickness: f32,
+    pub organ_identity: u32,
+    pub custom_data: [u8; 4],
+    /// the stem this organ grows from, or -1
+    pub index_in_stem_tree: i32,
+    /// index of the symbol which placed the organ
+    pub symbol_index: i32,
+}
+
+/// A single stem segment placed by the turtle. stems form a tree through their parent index
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TurtleStemInstance {
+    pub stem_class_index: i32,
+    /// the turtle transform, scaled by the length along x and the radius along y and z
+    pub orientation: Mat4,
+    /// index of the parent stem, or -1
+    pub parent_index: i32,
+    pub organ_identity: u32,
+    pub custom_data: [u8; 4],
+}
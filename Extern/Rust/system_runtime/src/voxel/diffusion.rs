@@ -0,0 +1,336 @@
+use crate::math::Vec3Int;
+use crate::voxel::layout::{VoxelVolume, VoxelWorldVolumetricLayerData};
+
+const ADJACENCY_VECTORS: [Vec3Int; 6] = [
+    Vec3Int::new(1, 0, 0),
+    Vec3Int::new(-1, 0, 0),
+    Vec3Int::new(0, 1, 0),
+    Vec3Int::new(0, -1, 0),
+    Vec3Int::new(0, 0, 1),
+    Vec3Int::new(0, 0, -1),
+];
+
+const AXIS_VECTORS: [Vec3Int; 3] = [
+    Vec3Int::new(1, 0, 0),
+    Vec3Int::new(0, 1, 0),
+    Vec3Int::new(0, 0, 1),
+];
+
+/// the largest diffusion factor allowed between two voxels when diffusing by voxel constants
+const MAXIMUM_BY_VOXEL_DIFFUSION_FACTOR: f32 = 1.0 / 7.0;
+
+/// How voxels at the edge of the volume interact with the space outside of it
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum VoxelBoundaryMode {
+    /// nothing crosses the edge of the volume, the total amount in the layer is conserved
+    #[default]
+    Conserve = 0,
+    /// the space outside the volume holds a fixed value. resources flow in or out across the edge towards that value.
+    ///     with a boundary value of 0 this matches the unity kernel diffuser
+    FixedValue = 1,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct VoxelBoundary {
+    pub mode: VoxelBoundaryMode,
+    /// used by FixedValue, the value held by every voxel outside of the volume
+    pub boundary_value: f32,
+}
+
+/// Build a one dimensional diffusion kernel, by diffusing a single unit in the center of the kernel
+///     for as many passes as the kernel is wide. the result is symmetric and sums to 1.
+/// returns None if the kernel size is not odd
+pub fn compute_kernel(kernel_size: usize, delta_time: f32, diffusion_constant: f32) -> Option<Vec<f32>> {
+    if kernel_size % 2 != 1 {
+        return None;
+    }
+    let combined_diffusion_factor = delta_time * diffusion_constant;
+    let kernel_origin = kernel_size / 2;
+    let mut kernel = vec![0.0; kernel_size];
+    kernel[kernel_origin] = 1.0;
+
+    for _ in 0..(kernel_origin * 2) {
+        for i in 0..(kernel_size - 1) {
+            let movement = (kernel[i] - kernel[i + 1]) * combined_diffusion_factor;
+            kernel[i] -= movement;
+            kernel[i + 1] += movement;
+        }
+    }
+
+    // ensure symmetry
+    for i in 0..kernel_origin {
+        let inverse_index = kernel_size - i - 1;
+        let average = (kernel[i] + kernel[inverse_index]) / 2.0;
+        kernel[i] = average;
+        kernel[inverse_index] = average;
+    }
+
+    Some(kernel)
+}
+
+/// mirror a coordinate which lies outside of 0..resolution back inside of it
+fn mirror_coordinate(coordinate: i32, resolution: i32) -> i32 {
+    let mirrored = if coordinate < 0 {
+        -coordinate - 1
+    } else if coordinate >= resolution {
+        2 * resolution - coordinate - 1
+    } else {
+        coordinate
+    };
+    mirrored.clamp(0, resolution - 1)
+}
+
+fn mirror_coordinates(volume: &VoxelVolume, coordinates: Vec3Int) -> Vec3Int {
+    let resolution = volume.world_resolution;
+    Vec3Int::new(
+        mirror_coordinate(coordinates.x, resolution.x),
+        mirror_coordinate(coordinates.y, resolution.y),
+        mirror_coordinate(coordinates.z, resolution.z),
+    )
+}
+
+fn kernel_pass(
+    volume: &VoxelVolume,
+    source: &[f32],
+    target: &mut [f32],
+    kernel: &[f32],
+    axis: Vec3Int,
+    boundary: &VoxelBoundary) {
+    let kernel_origin = (kernel.len() / 2) as i32;
+    for (voxel_index, target_value) in target.iter_mut().enumerate() {
+        let root_coordinates = volume.coordinates_from_voxel_index(voxel_index);
+        let mut new_value = 0.0;
+        for (kernel_index, kernel_weight) in kernel.iter().enumerate() {
+            let distance = kernel_index as i32 - kernel_origin;
+            let offset = Vec3Int::new(axis.x * distance, axis.y * distance, axis.z * distance);
+            let sample_coordinates = root_coordinates + offset;
+            let sample_value = match volume.voxel_index_from_coordinates(sample_coordinates) {
+                Some(sample_index) => source[sample_index],
+                None => match boundary.mode {
+                    VoxelBoundaryMode::FixedValue => boundary.boundary_value,
+                    VoxelBoundaryMode::Conserve => {
+                        // mirroring the edge of the volume with a symmetric kernel conserves the total
+                        let mirrored = mirror_coordinates(volume, sample_coordinates);
+                        source[volume.voxel_index_from_coordinates(mirrored).unwrap()]
+                    }
+                },
+            };
+            new_value += sample_value * kernel_weight;
+        }
+        *target_value = new_value;
+    }
+}
+
+/// Diffuse one layer with a separable kernel, in one pass along each axis.
+///     may perform better for higher fidelity diffusion, where a wide gradient must be generated in a single step.
+/// returns false if the layer does not exist or the kernel size is not odd
+pub fn diffuse_layer_by_kernel(
+    layer_data: &mut VoxelWorldVolumetricLayerData,
+    layer: i32,
+    kernel_size: usize,
+    delta_time: f32,
+    diffusion_constant: f32,
+    boundary: &VoxelBoundary) -> bool {
+    if !layer_data.is_valid_layer(layer) {
+        return false;
+    }
+    let kernel = match compute_kernel(kernel_size, delta_time, diffusion_constant) {
+        Some(kernel) => kernel,
+        None => return false,
+    };
+    let volume = layer_data.layout.volume;
+    let layer = layer as usize;
+
+    let mut current = layer_data.copy_layer(layer);
+    let mut next = vec![0.0; current.len()];
+    for axis in AXIS_VECTORS {
+        kernel_pass(&volume, &current, &mut next, &kernel, axis, boundary);
+        std::mem::swap(&mut current, &mut next);
+    }
+
+    for (voxel_index, value) in current.into_iter().enumerate() {
+        layer_data.set(voxel_index, layer, value);
+    }
+    true
+}
+
+/// Diffuse one layer only between directly adjacent voxels. lower quality than the kernel,
+///     but handles the boundary without needing a symmetric kernel.
+/// returns false if the layer does not exist, or if delta_time * diffusion_constant is not below 1/6
+pub fn diffuse_layer_by_adjacency(
+    layer_data: &mut VoxelWorldVolumetricLayerData,
+    layer: i32,
+    delta_time: f32,
+    diffusion_constant: f32,
+    boundary: &VoxelBoundary) -> bool {
+    let combined_diffusion_factor = delta_time * diffusion_constant;
+    if combined_diffusion_factor >= 1.0 / 6.0 {
+        return false;
+    }
+    diffuse_by_adjacency_internal(layer_data, layer, boundary, |_, _| combined_diffusion_factor)
+}
+
+/// Diffuse one layer between adjacent voxels, scaling the diffusion between each pair of voxels by the average
+///     of their multipliers. allows diffusion to be limited to certain volumes, such as soil.
+///     multipliers are indexed by voxel, and raised to at least minimum_multiplier.
+/// returns false if the layer does not exist, if there is not one multiplier per voxel,
+///     or if delta_time * diffusion_constant is not below 1/6
+pub fn diffuse_layer_by_voxel_constant(
+    layer_data: &mut VoxelWorldVolumetricLayerData,
+    layer: i32,
+    multipliers: &[f32],
+    minimum_multiplier: f32,
+    delta_time: f32,
+    diffusion_constant: f32,
+    boundary: &VoxelBoundary) -> bool {
+    let combined_diffusion_factor = delta_time * diffusion_constant;
+    if combined_diffusion_factor >= 1.0 / 6.0 || multipliers.len() != layer_data.layout.volume.total_voxels() {
+        return false;
+    }
+    diffuse_by_adjacency_internal(layer_data, layer, boundary, |self_index, other_index| {
+        let self_multiplier = multipliers[self_index].max(minimum_multiplier);
+        let other_multiplier = match other_index {
+            Some(other_index) => multipliers[other_index].max(minimum_multiplier),
+            None => self_multiplier,
+        };
+        let average_multiplier = (self_multiplier + other_multiplier) / 2.0;
+        (combined_diffusion_factor * average_multiplier).min(MAXIMUM_BY_VOXEL_DIFFUSION_FACTOR)
+    })
+}
+
+/// diffusion_factor_between is given the voxel being updated and its neighbor, or None when the neighbor is outside the volume.
+///     it must return the same factor regardless of which voxel of a pair is being updated, to conserve resources
+fn diffuse_by_adjacency_internal(
+    layer_data: &mut VoxelWorldVolumetricLayerData,
+    layer: i32,
+    boundary: &VoxelBoundary,
+    diffusion_factor_between: impl Fn(usize, Option<usize>) -> f32) -> bool {
+    if !layer_data.is_valid_layer(layer) {
+        return false;
+    }
+    let volume = layer_data.layout.volume;
+    let layer = layer as usize;
+    let source = layer_data.copy_layer(layer);
+
+    for (voxel_index, original_value) in source.iter().enumerate() {
+        let root_coordinates = volume.coordinates_from_voxel_index(voxel_index);
+        let mut new_value = *original_value;
+        for offset in ADJACENCY_VECTORS {
+            let sample_index = volume.voxel_index_from_coordinates(root_coordinates + offset);
+            let sample_value = match (sample_index, boundary.mode) {
+                (Some(sample_index), _) => source[sample_index],
+                (None, VoxelBoundaryMode::FixedValue) => boundary.boundary_value,
+                // nothing diffuses across the edge, so no resources are lost or gained from the boundary
+                (None, VoxelBoundaryMode::Conserve) => continue,
+            };
+            new_value += (sample_value - original_value) * diffusion_factor_between(voxel_index, sample_index);
+        }
+        layer_data.set(voxel_index, layer, new_value);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec3;
+    use crate::voxel::layout::{VolumetricWorldVoxelLayout, VoxelWorldVolumetricLayerDataOwned};
+
+    const CONSERVE: VoxelBoundary = VoxelBoundary { mode: VoxelBoundaryMode::Conserve, boundary_value: 0.0 };
+
+    fn layers(resolution: Vec3Int) -> VoxelWorldVolumetricLayerDataOwned {
+        VoxelWorldVolumetricLayerDataOwned::new(VolumetricWorldVoxelLayout {
+            volume: VoxelVolume {
+                voxel_origin: Vec3::ZERO,
+                world_size: Vec3::ONE,
+                world_resolution: resolution,
+            },
+            data_layer_count: 2,
+        })
+    }
+
+    /// an uneven spread in layer 1, with the most at the edges of the volume. layer 0 is left empty
+    fn uneven_layers() -> VoxelWorldVolumetricLayerDataOwned {
+        let mut layers = layers(Vec3Int::new(4, 3, 5));
+        let mut data = layers.borrow_mut();
+        for voxel_index in 0..data.layout.volume.total_voxels() {
+            data.set(voxel_index, 1, ((voxel_index * 7) % 11) as f32);
+        }
+        data.set(0, 1, 40.0);
+        layers
+    }
+
+    fn layer_total(layers: &mut VoxelWorldVolumetricLayerDataOwned, layer: usize) -> f32 {
+        layers.borrow_mut().copy_layer(layer).iter().sum()
+    }
+
+    fn assert_layer_close(layer: &[f32], expected: &[f32]) {
+        assert_eq!(layer.len(), expected.len());
+        for (actual, expected) in layer.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-4, "{:?} != {:?}", layer, expected);
+        }
+    }
+
+    /// runs a few steps of each diffusion method over layer 1
+    fn diffuse_each_way(boundary: &VoxelBoundary, mut make_layers: impl FnMut() -> VoxelWorldVolumetricLayerDataOwned) -> Vec<VoxelWorldVolumetricLayerDataOwned> {
+        let mut by_kernel = make_layers();
+        let mut by_adjacency = make_layers();
+        let mut by_voxel_constant = make_layers();
+        let multipliers: Vec<f32> = (0..by_voxel_constant.layout.volume.total_voxels()).map(|voxel_index| (voxel_index % 3) as f32).collect();
+        for _ in 0..4 {
+            assert!(diffuse_layer_by_kernel(&mut by_kernel.borrow_mut(), 1, 5, 1.0, 0.2, boundary));
+            assert!(diffuse_layer_by_adjacency(&mut by_adjacency.borrow_mut(), 1, 1.0, 0.1, boundary));
+            assert!(diffuse_layer_by_voxel_constant(&mut by_voxel_constant.borrow_mut(), 1, &multipliers, 0.5, 1.0, 0.1, boundary));
+        }
+        vec![by_kernel, by_adjacency, by_voxel_constant]
+    }
+
+    #[test]
+    fn conserve_keeps_the_total_in_the_layer() {
+        let total_before = layer_total(&mut uneven_layers(), 1);
+
+        for mut diffused in diffuse_each_way(&CONSERVE, uneven_layers) {
+            let total_after = layer_total(&mut diffused, 1);
+            assert!((total_after - total_before).abs() < 1e-3, "{} != {}", total_after, total_before);
+            assert_eq!(layer_total(&mut diffused, 0), 0.0);
+            // the peak in the corner has spread out
+            assert!(diffused.borrow_mut().get(0, 1) < 40.0);
+        }
+    }
+
+    #[test]
+    fn fixed_value_holds_a_volume_already_at_the_boundary_value() {
+        let boundary = VoxelBoundary { mode: VoxelBoundaryMode::FixedValue, boundary_value: 3.0 };
+        let at_boundary_value = || {
+            let mut layers = layers(Vec3Int::new(4, 3, 5));
+            let mut data = layers.borrow_mut();
+            for voxel_index in 0..data.layout.volume.total_voxels() {
+                data.set(voxel_index, 1, 3.0);
+            }
+            layers
+        };
+
+        for mut diffused in diffuse_each_way(&boundary, at_boundary_value) {
+            assert_layer_close(&diffused.borrow_mut().copy_layer(1), &[3.0; 60]);
+        }
+    }
+
+    #[test]
+    fn fixed_value_flows_in_across_each_open_face() {
+        let boundary = VoxelBoundary { mode: VoxelBoundaryMode::FixedValue, boundary_value: 1.0 };
+        let mut layers = layers(Vec3Int::new(3, 3, 3));
+        assert!(diffuse_layer_by_adjacency(&mut layers.borrow_mut(), 1, 1.0, 0.1, &boundary));
+
+        let data = layers.borrow_mut();
+        let volume = data.layout.volume;
+        let at = |x, y, z| data.get(volume.voxel_index_from_coordinates(Vec3Int::new(x, y, z)).unwrap(), 1);
+        // corners touch three faces, edges two, face centers one, and the center none
+        assert!((at(0, 0, 0) - 0.3).abs() < 1e-6);
+        assert!((at(1, 0, 0) - 0.2).abs() < 1e-6);
+        assert!((at(1, 1, 0) - 0.1).abs() < 1e-6);
+        assert_eq!(at(1, 1, 1), 0.0);
+        assert_eq!(data.copy_layer(0), vec![0.0; 27]);
+    }
+}
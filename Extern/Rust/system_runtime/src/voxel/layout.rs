@@ -0,0 +1,164 @@
+use crate::math::{Vec3, Vec3Int};
+
+/// A box of world space divided into a grid of voxels. matches the memory layout of the unity VoxelVolume
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct VoxelVolume {
+    pub voxel_origin: Vec3,
+    pub world_size: Vec3,
+    pub world_resolution: Vec3Int,
+}
+
+impl VoxelVolume {
+    pub fn total_voxels(&self) -> usize {
+        (self.world_resolution.x * self.world_resolution.y * self.world_resolution.z).max(0) as usize
+    }
+
+    pub fn voxel_size(&self) -> Vec3 {
+        Vec3::new(
+            self.world_size.x / self.world_resolution.x as f32,
+            self.world_size.y / self.world_resolution.y as f32,
+            self.world_size.z / self.world_resolution.z as f32,
+        )
+    }
+
+    /// returns None when the coordinates are outside of the volume
+    pub fn voxel_index_from_coordinates(&self, coordinates: Vec3Int) -> Option<usize> {
+        let resolution = self.world_resolution;
+        if coordinates.x < 0 || coordinates.x >= resolution.x ||
+            coordinates.y < 0 || coordinates.y >= resolution.y ||
+            coordinates.z < 0 || coordinates.z >= resolution.z {
+            return None;
+        }
+        Some(((coordinates.x * resolution.y + coordinates.y) * resolution.z + coordinates.z) as usize)
+    }
+
+    pub fn coordinates_from_voxel_index(&self, voxel_index: usize) -> Vec3Int {
+        let resolution = self.world_resolution;
+        let index = voxel_index as i32;
+        Vec3Int::new(
+            index / (resolution.y * resolution.z),
+            (index / resolution.z) % resolution.y,
+            index % resolution.z,
+        )
+    }
+
+    /// the coordinates may be outside of the volume
+    pub fn coordinates_from_world_position(&self, world_position: Vec3) -> Vec3Int {
+        let relative_position = world_position - self.voxel_origin;
+        let resolution = self.world_resolution;
+        Vec3Int::new(
+            (relative_position.x * resolution.x as f32 / self.world_size.x).floor() as i32,
+            (relative_position.y * resolution.y as f32 / self.world_size.y).floor() as i32,
+            (relative_position.z * resolution.z as f32 / self.world_size.z).floor() as i32,
+        )
+    }
+
+    pub fn voxel_index_from_world_position(&self, world_position: Vec3) -> Option<usize> {
+        self.voxel_index_from_coordinates(self.coordinates_from_world_position(world_position))
+    }
+
+    /// the world position of the center of the voxel
+    pub fn world_position_from_coordinates(&self, coordinates: Vec3Int) -> Vec3 {
+        let voxel_size = self.voxel_size();
+        let coordinates = Vec3::new(coordinates.x as f32, coordinates.y as f32, coordinates.z as f32);
+        voxel_size.scale(coordinates) + self.voxel_origin + voxel_size * 0.5
+    }
+}
+
+/// A voxel volume holding several layers of data per voxel. matches the memory layout of the unity VolumetricWorldVoxelLayout
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct VolumetricWorldVoxelLayout {
+    pub volume: VoxelVolume,
+    pub data_layer_count: i32,
+}
+
+impl VolumetricWorldVoxelLayout {
+    pub fn total_data_size(&self) -> usize {
+        self.volume.total_voxels() * self.data_layer_count.max(0) as usize
+    }
+
+    pub fn data_index(&self, voxel_index: usize, layer: usize) -> usize {
+        voxel_index * self.data_layer_count as usize + layer
+    }
+}
+
+/// Every layer of every voxel, stored voxel by voxel with all layers of one voxel next to each other.
+/// the same layout as the unity VoxelWorldVolumetricLayerData
+pub struct VoxelWorldVolumetricLayerData<'a> {
+    pub layout: VolumetricWorldVoxelLayout,
+    pub data: &'a mut [f32],
+}
+
+impl VoxelWorldVolumetricLayerData<'_> {
+    pub fn get(&self, voxel_index: usize, layer: usize) -> f32 {
+        self.data[self.layout.data_index(voxel_index, layer)]
+    }
+
+    pub fn set(&mut self, voxel_index: usize, layer: usize, value: f32) {
+        let data_index = self.layout.data_index(voxel_index, layer);
+        self.data[data_index] = value;
+    }
+
+    /// copy one layer out into a contiguous list, indexed by voxel
+    pub fn copy_layer(&self, layer: usize) -> Vec<f32> {
+        (0..self.layout.volume.total_voxels())
+            .map(|voxel_index| self.get(voxel_index, layer))
+            .collect()
+    }
+
+    pub fn is_valid_layer(&self, layer: i32) -> bool {
+        layer >= 0 && layer < self.layout.data_layer_count && self.data.len() >= self.layout.total_data_size()
+    }
+
+    /// the gradient of the layer at the world position, from the difference between the neighboring voxels along each axis.
+    ///     one sided at the edges of the volume. None when the position is outside of the volume
+    pub fn gradient(&self, layer: usize, world_position: Vec3) -> Option<Vec3> {
+        let volume = &self.layout.volume;
+        let coordinates = volume.coordinates_from_world_position(world_position);
+        let center_value = self.get(volume.voxel_index_from_coordinates(coordinates)?, layer);
+        let voxel_size = volume.voxel_size();
+
+        // the value and distance in voxels of the neighbor, or the center when the neighbor is outside of the volume
+        let neighbor = |offset: Vec3Int| match volume.voxel_index_from_coordinates(coordinates + offset) {
+            Some(voxel_index) => (self.get(voxel_index, layer), 1.0),
+            None => (center_value, 0.0),
+        };
+        let axis_gradient = |forward_offset: Vec3Int, backward_offset: Vec3Int, voxel_size: f32| {
+            let (forward_value, forward_distance) = neighbor(forward_offset);
+            let (backward_value, backward_distance) = neighbor(backward_offset);
+            let distance = forward_distance + backward_distance;
+            if distance == 0.0 {
+                return 0.0;
+            }
+            (forward_value - backward_value) / (distance * voxel_size)
+        };
+        Some(Vec3::new(
+            axis_gradient(Vec3Int::new(1, 0, 0), Vec3Int::new(-1, 0, 0), voxel_size.x),
+            axis_gradient(Vec3Int::new(0, 1, 0), Vec3Int::new(0, -1, 0), voxel_size.y),
+            axis_gradient(Vec3Int::new(0, 0, 1), Vec3Int::new(0, 0, -1), voxel_size.z),
+        ))
+    }
+}
+
+pub struct VoxelWorldVolumetricLayerDataOwned {
+    pub layout: VolumetricWorldVoxelLayout,
+    pub data: Vec<f32>,
+}
+
+impl VoxelWorldVolumetricLayerDataOwned {
+    pub fn new(layout: VolumetricWorldVoxelLayout) -> Self {
+        VoxelWorldVolumetricLayerDataOwned {
+            layout,
+            data: vec![0.0; layout.total_data_size()],
+        }
+    }
+
+    pub fn borrow_mut(&mut self) -> VoxelWorldVolumetricLayerData<'_> {
+        VoxelWorldVolumetricLayerData {
+            layout: self.layout,
+            data: &mut self.data,
+        }
+    }
+}
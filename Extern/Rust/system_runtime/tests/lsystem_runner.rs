@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn fixture(name: &str) -> String {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name).to_string_lossy().into_owned()
+}
+
+fn run_lsystem(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lsystem_runner"))
+        .args(args)
+        .output()
+        .expect("the runner should start")
+}
+
+#[test]
+fn steps_an_included_lsystem_and_runs_its_diffusion() {
+    let output = run_lsystem(&[&fixture("plant.lsystem"), "--stats"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // each amount is diffused into the node, then the builtin diffusion rule removes the empty amount symbol
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "r(0.5,2,10)F[]F[a]S");
+    let stats = String::from_utf8_lossy(&output.stderr);
+    assert!(stats.lines().last().unwrap().ends_with("resource 0 total 2"), "{}", stats);
+}
+
+#[test]
+fn saved_strings_continue_where_they_stopped() {
+    let saved = std::env::temp_dir().join(format!("lsystem_runner_test_{}.lsys", std::process::id()));
+    let saved = saved.to_string_lossy().into_owned();
+    let first = run_lsystem(&[&fixture("plant.lsystem"), "--steps", "1", "--seed", "7", "--quiet", "--save", &saved]);
+    assert!(first.status.success(), "{}", String::from_utf8_lossy(&first.stderr));
+    let resumed = run_lsystem(&[&saved, "--rules", &fixture("plant.lsystem"), "--steps", "1"]);
+    std::fs::remove_file(&saved).unwrap();
+    let direct = run_lsystem(&[&fixture("plant.lsystem"), "--steps", "2", "--seed", "7"]);
+    assert!(resumed.status.success(), "{}", String::from_utf8_lossy(&resumed.stderr));
+    assert_eq!(resumed.stdout, direct.stdout);
+}
+
+#[test]
+fn diffusion_on_an_unbalanced_string_is_an_error() {
+    let output = run_lsystem(&[&fixture("unbalanced.txt"), "--branch-symbols", "1", "2", "--diffusion", "3", "4", "--steps", "1"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("branch symbols of the string do not match"));
+}
+
+#[test]
+fn libraries_cannot_be_loaded_on_their_own() {
+    let output = run_lsystem(&[&fixture("unbalanced.txt"), "--rules", &fixture("lib/growth.lsyslib")]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("A library cannot be loaded on its own"));
+}